@@ -0,0 +1,713 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use crate::{Dimensions, ImageError, ImageFormatHint, ImageResult, PixelBuffer};
+use crate::{ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind};
+
+/// The time a frame is displayed, as a ratio of milliseconds.
+///
+/// Formats express frame times in different units, such as hundredths of a second for GIF or an
+/// arbitrary fraction of a second for APNG. A ratio represents all of them exactly.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Delay {
+    numer: u32,
+    denom: u32,
+}
+
+impl Delay {
+    /// Create a delay of `numerator / denominator` milliseconds.
+    ///
+    /// The ratio is reduced. A denominator of zero is treated as one.
+    pub fn from_numer_denom_ms(numerator: u32, denominator: u32) -> Self {
+        let denominator = denominator.max(1);
+        let divisor = gcd(numerator, denominator);
+        Delay {
+            numer: numerator / divisor,
+            denom: denominator / divisor,
+        }
+    }
+
+    /// Create a delay of whole milliseconds.
+    pub fn from_millis(millis: u32) -> Self {
+        Delay {
+            numer: millis,
+            denom: 1,
+        }
+    }
+
+    /// Create a delay from a duration, saturating at the largest representable delay.
+    ///
+    /// Durations are represented with a precision of microseconds.
+    pub fn from_saturating_duration(duration: Duration) -> Self {
+        let micros = duration.as_micros();
+        match u32::try_from(micros) {
+            Ok(micros) => Delay::from_numer_denom_ms(micros, 1000),
+            Err(_) => {
+                let millis = u32::try_from(duration.as_millis()).unwrap_or(u32::MAX);
+                Delay::from_millis(millis)
+            }
+        }
+    }
+
+    /// Returns the reduced ratio of milliseconds as numerator and denominator.
+    pub fn numer_denom_ms(self) -> (u32, u32) {
+        (self.numer, self.denom)
+    }
+
+    /// Returns the delay as a duration, rounded down to whole nanoseconds.
+    pub fn to_duration(self) -> Duration {
+        let nanos = u64::from(self.numer) * 1_000_000 / u64::from(self.denom);
+        Duration::from_nanos(nanos)
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Delay::from_millis(0)
+    }
+}
+
+impl Ord for Delay {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = u64::from(self.numer) * u64::from(other.denom);
+        let rhs = u64::from(other.numer) * u64::from(self.denom);
+        lhs.cmp(&rhs)
+    }
+}
+
+impl PartialOrd for Delay {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A value which is either known exactly or estimated.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Estimate<T> {
+    /// The value is exact.
+    Exact(T),
+    /// The value is approximate, for example extrapolated from the part of the file that was read.
+    Approximate(T),
+}
+
+impl<T> Estimate<T> {
+    /// Returns the value, whether it is exact or not.
+    pub fn value(self) -> T {
+        match self {
+            Estimate::Exact(value) | Estimate::Approximate(value) => value,
+        }
+    }
+
+    /// Returns if the value is exact.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Estimate::Exact(_))
+    }
+}
+
+/// How often an animation is played.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoopCount {
+    /// The animation repeats forever.
+    #[default]
+    Infinite,
+    /// The animation is played the given number of times in total, and then stops at its last
+    /// frame.
+    ///
+    /// Formats count differently, GIF for example stores the number of repetitions after the
+    /// first play. Codecs convert to and from this count.
+    Finite(NonZeroU32),
+}
+
+/// What happens to the area of a frame on the canvas before the next frame is drawn.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DisposalMethod {
+    /// The frame is left on the canvas.
+    #[default]
+    Keep,
+    /// The area of the frame is cleared to transparent black.
+    Background,
+    /// The area of the frame is restored to what it was before the frame was drawn.
+    Previous,
+}
+
+/// How the pixels of a frame are combined with the canvas.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BlendMode {
+    /// The pixels of the frame replace those of the canvas, including their alpha.
+    #[default]
+    Source,
+    /// The pixels of the frame are composited over those of the canvas by their alpha.
+    Over,
+}
+
+/// A single frame of an animation.
+///
+/// The pixels of a frame cover a rectangle of the canvas starting at its offset. Frames may carry
+/// their own color profile and metadata, which take precedence over those of the animation.
+///
+/// Decoders which produce partial frames describe how to combine them with the disposal method
+/// and blend mode, see `FrameCompositor`. The defaults replace the canvas and keep the frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    buffer: PixelBuffer,
+    left: u32,
+    top: u32,
+    delay: Delay,
+    disposal: DisposalMethod,
+    blend: BlendMode,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+}
+
+impl Frame {
+    /// Create a frame at the top left of the canvas, without a delay or metadata.
+    pub fn new(buffer: PixelBuffer) -> Self {
+        Frame {
+            buffer,
+            left: 0,
+            top: 0,
+            delay: Delay::default(),
+            disposal: DisposalMethod::default(),
+            blend: BlendMode::default(),
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+        }
+    }
+
+    /// Set the offset of the frame on the canvas.
+    pub fn with_offset(mut self, left: u32, top: u32) -> Self {
+        self.left = left;
+        self.top = top;
+        self
+    }
+
+    /// Set the time the frame is displayed.
+    pub fn with_delay(mut self, delay: Delay) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set what happens to the area of the frame before the next frame is drawn.
+    pub fn with_disposal(mut self, disposal: DisposalMethod) -> Self {
+        self.disposal = disposal;
+        self
+    }
+
+    /// Set how the frame is combined with the canvas.
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Attach an ICC profile describing the colors of this frame.
+    pub fn with_icc_profile(mut self, profile: Vec<u8>) -> Self {
+        self.icc_profile = Some(profile);
+        self
+    }
+
+    /// Attach raw EXIF metadata to this frame.
+    pub fn with_exif(mut self, exif: Vec<u8>) -> Self {
+        self.exif = Some(exif);
+        self
+    }
+
+    /// Attach a raw XMP packet to this frame.
+    pub fn with_xmp(mut self, xmp: Vec<u8>) -> Self {
+        self.xmp = Some(xmp);
+        self
+    }
+
+    /// The pixels of the frame.
+    pub fn buffer(&self) -> &PixelBuffer {
+        &self.buffer
+    }
+
+    /// Returns the pixels of the frame, discarding everything else.
+    pub fn into_buffer(self) -> PixelBuffer {
+        self.buffer
+    }
+
+    /// The distance of the frame from the left edge of the canvas.
+    pub fn left(&self) -> u32 {
+        self.left
+    }
+
+    /// The distance of the frame from the top edge of the canvas.
+    pub fn top(&self) -> u32 {
+        self.top
+    }
+
+    /// The time the frame is displayed.
+    pub fn delay(&self) -> Delay {
+        self.delay
+    }
+
+    /// What happens to the area of the frame before the next frame is drawn.
+    pub fn disposal(&self) -> DisposalMethod {
+        self.disposal
+    }
+
+    /// How the frame is combined with the canvas.
+    pub fn blend(&self) -> BlendMode {
+        self.blend
+    }
+
+    /// The ICC profile of this frame, if it has its own.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// The raw EXIF metadata of this frame, if it has its own.
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.exif.as_deref()
+    }
+
+    /// The raw XMP packet of this frame, if it has its own.
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.xmp.as_deref()
+    }
+}
+
+/// The trait implemented by decoders of animated images.
+pub trait AnimationDecoder {
+    /// Decode the next frame, or return `None` after the last one.
+    ///
+    /// After an error was returned, the decoder may not be able to produce further frames.
+    fn next_frame(&mut self) -> Option<ImageResult<Frame>>;
+
+    /// Skip the next frame, returning its delay, or return `None` after the last one.
+    ///
+    /// Formats which can skip over the data of a frame should override this. The provided
+    /// implementation decodes the frame and discards its pixels.
+    fn skip_frame(&mut self) -> Option<ImageResult<Delay>> {
+        Some(self.next_frame()?.map(|frame| frame.delay()))
+    }
+
+    /// Position the decoder so that `next_frame` returns the frame with the given index.
+    ///
+    /// The index is counted from the start of the animation. Formats with a table of frames
+    /// should implement this efficiently, the provided implementation returns an unsupported
+    /// error. `Frames::seek_to_frame` falls back to skipping frames sequentially.
+    fn seek_to_frame(&mut self, index: u32) -> ImageResult<()> {
+        let _ = index;
+        Err(unsupported_seek())
+    }
+
+    /// Position the decoder at the frame which is displayed at the given time.
+    ///
+    /// The time is counted from the start of the animation. Returns the index of the frame that
+    /// `next_frame` returns next. The provided implementation returns an unsupported error,
+    /// `Frames::seek_to_time` falls back to decoding frames sequentially.
+    fn seek_to_time(&mut self, time: Delay) -> ImageResult<u32> {
+        let _ = time;
+        Err(unsupported_seek())
+    }
+
+    /// The number of frames of the whole animation, if it can be determined without decoding.
+    ///
+    /// Formats with an index of frames should answer exactly. Others may return an estimate, for
+    /// example from the file size, or `None`. The provided implementation returns `None`.
+    fn frame_count_hint(&self) -> Option<Estimate<u32>> {
+        None
+    }
+
+    /// The time to display every frame of the animation once, if it can be determined without
+    /// decoding.
+    ///
+    /// As with `frame_count_hint`, the provided implementation returns `None`.
+    fn total_duration_hint(&self) -> Option<Estimate<Delay>> {
+        None
+    }
+
+    /// How often the animation is played, if the file specifies it.
+    ///
+    /// The provided implementation returns `None`.
+    fn loop_count(&self) -> Option<LoopCount> {
+        None
+    }
+
+    /// The size of the canvas the frames are drawn on, if the file specifies it.
+    ///
+    /// Frames may cover only a part of the canvas. The provided implementation returns `None`.
+    fn canvas_size(&self) -> Option<Dimensions> {
+        None
+    }
+
+    /// Returns an iterator over the remaining frames.
+    fn into_frames(self) -> Frames<Self>
+    where
+        Self: Sized,
+    {
+        Frames {
+            decoder: self,
+            index: 0,
+            elapsed: Some(Duration::ZERO),
+            pending: None,
+        }
+    }
+}
+
+impl<D: AnimationDecoder + ?Sized> AnimationDecoder for &mut D {
+    fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+        (**self).next_frame()
+    }
+
+    fn skip_frame(&mut self) -> Option<ImageResult<Delay>> {
+        (**self).skip_frame()
+    }
+
+    fn seek_to_frame(&mut self, index: u32) -> ImageResult<()> {
+        (**self).seek_to_frame(index)
+    }
+
+    fn seek_to_time(&mut self, time: Delay) -> ImageResult<u32> {
+        (**self).seek_to_time(time)
+    }
+
+    fn frame_count_hint(&self) -> Option<Estimate<u32>> {
+        (**self).frame_count_hint()
+    }
+
+    fn total_duration_hint(&self) -> Option<Estimate<Delay>> {
+        (**self).total_duration_hint()
+    }
+
+    fn loop_count(&self) -> Option<LoopCount> {
+        (**self).loop_count()
+    }
+
+    fn canvas_size(&self) -> Option<Dimensions> {
+        (**self).canvas_size()
+    }
+}
+
+impl<D: AnimationDecoder + ?Sized> AnimationDecoder for Box<D> {
+    fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+        (**self).next_frame()
+    }
+
+    fn skip_frame(&mut self) -> Option<ImageResult<Delay>> {
+        (**self).skip_frame()
+    }
+
+    fn seek_to_frame(&mut self, index: u32) -> ImageResult<()> {
+        (**self).seek_to_frame(index)
+    }
+
+    fn seek_to_time(&mut self, time: Delay) -> ImageResult<u32> {
+        (**self).seek_to_time(time)
+    }
+
+    fn frame_count_hint(&self) -> Option<Estimate<u32>> {
+        (**self).frame_count_hint()
+    }
+
+    fn total_duration_hint(&self) -> Option<Estimate<Delay>> {
+        (**self).total_duration_hint()
+    }
+
+    fn loop_count(&self) -> Option<LoopCount> {
+        (**self).loop_count()
+    }
+
+    fn canvas_size(&self) -> Option<Dimensions> {
+        (**self).canvas_size()
+    }
+}
+
+/// An iterator over the frames of an `AnimationDecoder`.
+///
+/// The iterator counts the frames and their display time, assuming that the decoder was at the
+/// start of the animation when it was created. This allows seeking forward in decoders which do
+/// not implement seeking themselves.
+pub struct Frames<D> {
+    decoder: D,
+    /// The index of the next frame.
+    index: u32,
+    /// The time at which the next frame is displayed, if known.
+    elapsed: Option<Duration>,
+    /// A frame which was decoded while seeking but not yet returned.
+    pending: Option<Frame>,
+}
+
+impl<D> Frames<D> {
+    /// Returns the decoder.
+    pub fn decoder(&self) -> &D {
+        &self.decoder
+    }
+
+    /// Returns the underlying decoder.
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+}
+
+impl<D: AnimationDecoder> Frames<D> {
+    /// Position the iterator so that it returns the frame with the given index next.
+    ///
+    /// Uses `AnimationDecoder::seek_to_frame` if the decoder supports it. Otherwise frames are
+    /// skipped up to the index, which is only possible forward. Seeking past the last frame
+    /// results in a `ParameterErrorKind::NoMoreData` error.
+    pub fn seek_to_frame(&mut self, index: u32) -> ImageResult<()> {
+        match self.decoder.seek_to_frame(index) {
+            Err(ImageError::Unsupported(_)) => {}
+            result => {
+                self.pending = None;
+                self.elapsed = None;
+                if result.is_ok() {
+                    self.index = index;
+                }
+                return result;
+            }
+        }
+
+        if index < self.index {
+            return Err(unsupported_seek());
+        }
+        if index > self.index {
+            if let Some(frame) = self.pending.take() {
+                self.advance(frame.delay());
+            }
+        }
+        while self.index < index {
+            let delay = self.decoder.skip_frame().ok_or_else(no_more_frames)??;
+            self.advance(delay);
+        }
+        Ok(())
+    }
+
+    /// Position the iterator at the frame which is displayed at the given time, returning its
+    /// index.
+    ///
+    /// Uses `AnimationDecoder::seek_to_time` if the decoder supports it. Otherwise frames are
+    /// decoded until the one displayed at that time, which is only possible forward. Seeking past
+    /// the end of the animation results in a `ParameterErrorKind::NoMoreData` error.
+    pub fn seek_to_time(&mut self, time: Delay) -> ImageResult<u32> {
+        match self.decoder.seek_to_time(time) {
+            Err(ImageError::Unsupported(_)) => {}
+            result => {
+                self.pending = None;
+                self.elapsed = None;
+                if let Ok(index) = result {
+                    self.index = index;
+                }
+                return result;
+            }
+        }
+
+        let target = time.to_duration();
+        match self.elapsed {
+            Some(elapsed) if elapsed <= target => {}
+            _ => return Err(unsupported_seek()),
+        }
+
+        loop {
+            let frame = match self.pending.take() {
+                Some(frame) => frame,
+                None => self.decoder.next_frame().ok_or_else(no_more_frames)??,
+            };
+            let end = self
+                .elapsed
+                .map(|elapsed| elapsed + frame.delay().to_duration());
+            if end.is_some_and(|end| end > target) {
+                self.pending = Some(frame);
+                return Ok(self.index);
+            }
+            self.advance(frame.delay());
+        }
+    }
+
+    /// Account for a frame that was consumed.
+    fn advance(&mut self, delay: Delay) {
+        self.index += 1;
+        self.elapsed = self.elapsed.map(|elapsed| elapsed + delay.to_duration());
+    }
+}
+
+impl<D: AnimationDecoder> Iterator for Frames<D> {
+    type Item = ImageResult<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = match self.pending.take() {
+            Some(frame) => Ok(frame),
+            None => self.decoder.next_frame()?,
+        };
+        if let Ok(frame) = &frame {
+            self.advance(frame.delay());
+        }
+        Some(frame)
+    }
+}
+
+fn unsupported_seek() -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Unknown,
+        UnsupportedErrorKind::GenericFeature("seeking frames".into()),
+    ))
+}
+
+fn no_more_frames() -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::NoMoreData))
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let rem = a % b;
+        a = b;
+        b = rem;
+    }
+    a.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorType;
+
+    #[test]
+    fn delays_are_reduced_ratios() {
+        let delay = Delay::from_numer_denom_ms(100, 30);
+        assert_eq!(delay.numer_denom_ms(), (10, 3));
+        assert_eq!(delay, Delay::from_numer_denom_ms(10, 3));
+        assert_eq!(Delay::from_numer_denom_ms(0, 0).numer_denom_ms(), (0, 1));
+        assert!(Delay::from_millis(3) < delay);
+        assert!(Delay::from_millis(4) > delay);
+
+        let duration = Duration::from_micros(2500);
+        assert_eq!(
+            Delay::from_saturating_duration(duration).numer_denom_ms(),
+            (5, 2)
+        );
+        assert_eq!(Delay::from_numer_denom_ms(5, 2).to_duration(), duration);
+    }
+
+    #[test]
+    fn frames_carry_metadata() {
+        let buffer = PixelBuffer::new(2, 1, ColorType::L8).unwrap();
+        let frame = Frame::new(buffer.clone())
+            .with_offset(3, 4)
+            .with_icc_profile(vec![1, 2, 3]);
+
+        assert_eq!((frame.left(), frame.top()), (3, 4));
+        assert_eq!(frame.icc_profile(), Some(&[1, 2, 3][..]));
+        assert_eq!(frame.exif(), None);
+        assert_eq!(frame.into_buffer(), buffer);
+    }
+
+    /// Produces frames with delays of a countdown of milliseconds.
+    struct Countdown(u32);
+
+    impl AnimationDecoder for Countdown {
+        fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+            self.0 = self.0.checked_sub(1)?;
+            let buffer = PixelBuffer::new(1, 1, ColorType::L8).unwrap();
+            Some(Ok(Frame::new(buffer).with_delay(Delay::from_millis(self.0))))
+        }
+    }
+
+    #[test]
+    fn iterates_frames() {
+        let delays: Vec<_> = Countdown(3)
+            .into_frames()
+            .map(|frame| frame.unwrap().delay())
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                Delay::from_millis(2),
+                Delay::from_millis(1),
+                Delay::from_millis(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn forwards_hints() {
+        /// Knows its frames from an index but never decodes them.
+        struct Indexed;
+
+        impl AnimationDecoder for Indexed {
+            fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+                None
+            }
+
+            fn frame_count_hint(&self) -> Option<Estimate<u32>> {
+                Some(Estimate::Exact(3))
+            }
+
+            fn total_duration_hint(&self) -> Option<Estimate<Delay>> {
+                Some(Estimate::Approximate(Delay::from_millis(300)))
+            }
+        }
+
+        let decoder: Box<dyn AnimationDecoder> = Box::new(Indexed);
+        let count = decoder.frame_count_hint().unwrap();
+        assert!(count.is_exact());
+        assert_eq!(count.value(), 3);
+        let duration = decoder.total_duration_hint().unwrap();
+        assert!(!duration.is_exact());
+        assert_eq!(duration.value(), Delay::from_millis(300));
+
+        let frames = Countdown(3).into_frames();
+        assert_eq!(frames.decoder().frame_count_hint(), None);
+        assert_eq!(frames.decoder().total_duration_hint(), None);
+    }
+
+    #[test]
+    fn seeks_sequentially() {
+        let delay = |frame: Frame| frame.delay().numer_denom_ms().0;
+
+        let mut frames = Countdown(10).into_frames();
+        frames.seek_to_frame(2).unwrap();
+        assert_eq!(delay(frames.next().unwrap().unwrap()), 7);
+        assert!(frames.seek_to_frame(1).is_err());
+
+        // Frames 3 to 5 are displayed from 0 to 6, 6 to 11 and 11 to 15 ms after frame 3.
+        assert_eq!(frames.seek_to_time(Delay::from_millis(24 + 12)).unwrap(), 5);
+        assert_eq!(frames.seek_to_time(Delay::from_millis(24 + 14)).unwrap(), 5);
+        frames.seek_to_frame(5).unwrap();
+        assert_eq!(delay(frames.next().unwrap().unwrap()), 4);
+
+        match frames.seek_to_frame(20) {
+            Err(ImageError::Parameter(err)) => {
+                assert_eq!(err.kind(), ParameterErrorKind::NoMoreData)
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn keeps_index_after_failed_seeks() {
+        /// Rejects seeking to frame 8 and seeks no other frame itself.
+        struct Rejecting(Countdown);
+
+        impl AnimationDecoder for Rejecting {
+            fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+                self.0.next_frame()
+            }
+
+            fn seek_to_frame(&mut self, index: u32) -> ImageResult<()> {
+                match index {
+                    8 => Err(ImageError::Parameter(ParameterError::from_kind(
+                        ParameterErrorKind::Generic("corrupt index".into()),
+                    ))),
+                    _ => Err(unsupported_seek()),
+                }
+            }
+        }
+
+        let mut frames = Rejecting(Countdown(10)).into_frames();
+        assert!(frames.seek_to_frame(8).is_err());
+        frames.seek_to_frame(4).unwrap();
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.delay().numer_denom_ms().0, 5);
+    }
+}
@@ -0,0 +1,343 @@
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use crate::error::{ImageError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::{AnimationDecoder, BlendMode, Delay, Dimensions, DisposalMethod, Frame};
+use crate::{FrameCompositor, ImageResult, LoopCount};
+
+/// The trait implemented by encoders of animated images.
+///
+/// The encoder owns its output and writes frames as they are passed in. The capabilities of the
+/// format are reported so that `transcode_animation` can adapt frames of other formats. The
+/// provided implementations describe the simplest format: full frames which replace the canvas.
+pub trait AnimationEncoder {
+    /// Encode the next frame.
+    ///
+    /// Frames with a disposal method, blend mode or offset the encoder does not support result
+    /// in an unsupported error.
+    fn write_frame(&mut self, frame: &Frame) -> ImageResult<()>;
+
+    /// Complete the animation after its last frame.
+    fn finish(self) -> ImageResult<()>
+    where
+        Self: Sized;
+
+    /// Set the size of the canvas, before the first frame is written.
+    ///
+    /// The provided implementation accepts every size, for formats which take it from the first
+    /// frame.
+    fn set_canvas_size(&mut self, size: Dimensions) -> ImageResult<()> {
+        let _ = size;
+        Ok(())
+    }
+
+    /// Set how often the animation is played, before the first frame is written.
+    ///
+    /// The provided implementation returns an unsupported error.
+    fn set_loop_count(&mut self, count: LoopCount) -> ImageResult<()> {
+        let _ = count;
+        Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature("loop counts".into()),
+            ),
+        ))
+    }
+
+    /// Returns whether frames may use the disposal method.
+    ///
+    /// The provided implementation only supports `DisposalMethod::Keep`.
+    fn supports_disposal(&self, method: DisposalMethod) -> bool {
+        method == DisposalMethod::Keep
+    }
+
+    /// Returns whether frames may use the blend mode.
+    ///
+    /// The provided implementation only supports `BlendMode::Source`.
+    fn supports_blend(&self, mode: BlendMode) -> bool {
+        mode == BlendMode::Source
+    }
+
+    /// Returns whether frames may cover only a part of the canvas.
+    ///
+    /// The provided implementation returns `false`.
+    fn supports_partial_frames(&self) -> bool {
+        false
+    }
+
+    /// The unit in which the format stores delays, if it can not store every `Delay` exactly.
+    ///
+    /// GIF for example stores hundredths of a second. The provided implementation returns
+    /// `None`.
+    fn delay_granularity(&self) -> Option<Delay> {
+        None
+    }
+}
+
+/// Options of `transcode_animation`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TranscodeOptions {
+    loop_count: Option<LoopCount>,
+    strip_frame_metadata: bool,
+}
+
+impl TranscodeOptions {
+    /// Create options which keep everything of the animation.
+    pub fn new() -> Self {
+        TranscodeOptions::default()
+    }
+
+    /// Play the animation a different number of times than the decoder reports.
+    pub fn with_loop_count(mut self, count: LoopCount) -> Self {
+        self.loop_count = Some(count);
+        self
+    }
+
+    /// Drop the ICC profiles, EXIF and XMP attached to individual frames.
+    pub fn with_strip_frame_metadata(mut self, strip: bool) -> Self {
+        self.strip_frame_metadata = strip;
+        self
+    }
+}
+
+/// Re-encode an animation in another format, preserving its timing and appearance.
+///
+/// The loop count and canvas size of the decoder are passed to the encoder, if it reports them.
+/// Frames are passed on as they are while the encoder supports their disposal method, blend mode
+/// and placement. From the first frame it does not support onwards, the frames are composited
+/// with a `FrameCompositor` and written as full 8-bit RGBA canvases instead. Without a canvas size
+/// from the decoder, the canvas is the extent of the first frame.
+///
+/// Delays are rounded to the `AnimationEncoder::delay_granularity` of the encoder. The rounding
+/// is applied to the time at which each frame ends, so that errors do not accumulate over the
+/// animation.
+pub fn transcode_animation<D, E>(
+    mut decoder: D,
+    mut encoder: E,
+    options: &TranscodeOptions,
+) -> ImageResult<()>
+where
+    D: AnimationDecoder,
+    E: AnimationEncoder,
+{
+    if let Some(count) = options.loop_count.or_else(|| decoder.loop_count()) {
+        encoder.set_loop_count(count)?;
+    }
+
+    let mut retimer = Retimer::new(encoder.delay_granularity());
+    let mut compositor: Option<FrameCompositor> = None;
+    let mut compositing = false;
+    while let Some(frame) = decoder.next_frame() {
+        let frame = frame?;
+        let compositor = match &mut compositor {
+            Some(compositor) => compositor,
+            None => {
+                let size = decoder.canvas_size().unwrap_or_else(|| extent(&frame));
+                encoder.set_canvas_size(size)?;
+                compositor.insert(FrameCompositor::new(size.width, size.height)?)
+            }
+        };
+
+        let canvas = compositor.composite(&frame);
+        let delay = retimer.retime(frame.delay());
+        compositing = compositing || !is_supported(&encoder, &frame, canvas.size());
+        let output = if compositing {
+            Frame::new(canvas.clone())
+        } else {
+            Frame::new(frame.buffer().clone())
+                .with_offset(frame.left(), frame.top())
+                .with_disposal(frame.disposal())
+                .with_blend(frame.blend())
+        };
+
+        let mut output = output.with_delay(delay);
+        if !options.strip_frame_metadata {
+            output = with_metadata_of(output, &frame);
+        }
+        encoder.write_frame(&output)?;
+    }
+
+    encoder.finish()
+}
+
+/// Returns whether the encoder can write the frame as it is.
+fn is_supported<E: AnimationEncoder>(encoder: &E, frame: &Frame, canvas: Dimensions) -> bool {
+    let full = frame.left() == 0 && frame.top() == 0 && frame.buffer().size() == canvas;
+    encoder.supports_disposal(frame.disposal())
+        && encoder.supports_blend(frame.blend())
+        && (full || encoder.supports_partial_frames())
+}
+
+/// The size of a canvas which just fits the frame at its offset.
+fn extent(frame: &Frame) -> Dimensions {
+    let size = frame.buffer().size();
+    Dimensions::new(
+        frame.left().saturating_add(size.width),
+        frame.top().saturating_add(size.height),
+    )
+}
+
+fn with_metadata_of(mut frame: Frame, source: &Frame) -> Frame {
+    if let Some(profile) = source.icc_profile() {
+        frame = frame.with_icc_profile(profile.to_vec());
+    }
+    if let Some(exif) = source.exif() {
+        frame = frame.with_exif(exif.to_vec());
+    }
+    if let Some(xmp) = source.xmp() {
+        frame = frame.with_xmp(xmp.to_vec());
+    }
+    frame
+}
+
+/// Rounds the end times of frames to a granularity.
+struct Retimer {
+    /// The granularity in nanoseconds, if delays are rounded.
+    granularity: Option<u128>,
+    /// The exact time at which the last frame ends.
+    end: u128,
+    /// The rounded time at which the last frame ends.
+    rounded_end: u128,
+}
+
+impl Retimer {
+    fn new(granularity: Option<Delay>) -> Self {
+        Retimer {
+            granularity: granularity
+                .map(|delay| delay.to_duration().as_nanos())
+                .filter(|&nanos| nanos > 0),
+            end: 0,
+            rounded_end: 0,
+        }
+    }
+
+    fn retime(&mut self, delay: Delay) -> Delay {
+        let granularity = match self.granularity {
+            Some(granularity) => granularity,
+            None => return delay,
+        };
+
+        self.end += delay.to_duration().as_nanos();
+        let rounded_end = (self.end + granularity / 2) / granularity * granularity;
+        let rounded = rounded_end - self.rounded_end;
+        self.rounded_end = rounded_end;
+        let nanos = u64::try_from(rounded).unwrap_or(u64::MAX);
+        Delay::from_saturating_duration(Duration::from_nanos(nanos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColorType, PixelBuffer};
+    use std::num::NonZeroU32;
+
+    struct VecDecoder(std::vec::IntoIter<Frame>);
+
+    impl AnimationDecoder for VecDecoder {
+        fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+            self.0.next().map(Ok)
+        }
+
+        fn loop_count(&self) -> Option<LoopCount> {
+            NonZeroU32::new(2).map(LoopCount::Finite)
+        }
+    }
+
+    /// Records frames, supporting partial frames in centiseconds but no disposal.
+    #[derive(Default)]
+    struct Recorder {
+        frames: Vec<Frame>,
+        canvas: Option<Dimensions>,
+        loop_count: Option<LoopCount>,
+    }
+
+    impl AnimationEncoder for &mut Recorder {
+        fn write_frame(&mut self, frame: &Frame) -> ImageResult<()> {
+            self.frames.push(frame.clone());
+            Ok(())
+        }
+
+        fn finish(self) -> ImageResult<()> {
+            Ok(())
+        }
+
+        fn set_canvas_size(&mut self, size: Dimensions) -> ImageResult<()> {
+            self.canvas = Some(size);
+            Ok(())
+        }
+
+        fn set_loop_count(&mut self, count: LoopCount) -> ImageResult<()> {
+            self.loop_count = Some(count);
+            Ok(())
+        }
+
+        fn supports_partial_frames(&self) -> bool {
+            true
+        }
+
+        fn delay_granularity(&self) -> Option<Delay> {
+            Some(Delay::from_millis(10))
+        }
+    }
+
+    fn gray(width: u32, value: u8) -> PixelBuffer {
+        PixelBuffer::from_vec(width, 1, ColorType::L8, vec![value; width as usize]).unwrap()
+    }
+
+    #[test]
+    fn passes_supported_frames() {
+        let frames = vec![
+            Frame::new(gray(2, 10)).with_delay(Delay::from_millis(15)),
+            Frame::new(gray(1, 20))
+                .with_offset(1, 0)
+                .with_delay(Delay::from_millis(15))
+                .with_exif(b"exif".to_vec()),
+            Frame::new(gray(2, 30)).with_delay(Delay::from_millis(15)),
+        ];
+        let mut recorder = Recorder::default();
+        let decoder = VecDecoder(frames.clone().into_iter());
+        transcode_animation(decoder, &mut recorder, &TranscodeOptions::new()).unwrap();
+
+        assert_eq!(recorder.canvas, Some(Dimensions::new(2, 1)));
+        assert_eq!(
+            recorder.loop_count,
+            NonZeroU32::new(2).map(LoopCount::Finite)
+        );
+        let delays: Vec<_> = recorder.frames.iter().map(Frame::delay).collect();
+        let millis = [20, 10, 20].map(Delay::from_millis);
+        assert_eq!(delays, millis);
+        for (written, frame) in recorder.frames.iter().zip(&frames) {
+            assert_eq!(written.clone().with_delay(frame.delay()), *frame);
+        }
+    }
+
+    #[test]
+    fn composites_unsupported_frames() {
+        let frames = vec![
+            Frame::new(gray(2, 10)),
+            Frame::new(gray(1, 20)).with_disposal(DisposalMethod::Background),
+            Frame::new(gray(1, 30)).with_offset(1, 0),
+        ];
+        let mut recorder = Recorder::default();
+        let options = TranscodeOptions::new().with_loop_count(LoopCount::Infinite);
+        transcode_animation(VecDecoder(frames.into_iter()), &mut recorder, &options).unwrap();
+
+        assert_eq!(recorder.loop_count, Some(LoopCount::Infinite));
+        assert_eq!(recorder.frames[0].buffer().color_type(), ColorType::L8);
+        let canvases: Vec<_> = recorder.frames[1..]
+            .iter()
+            .map(|frame| frame.buffer().as_bytes().to_vec())
+            .collect();
+        assert_eq!(
+            canvases,
+            [
+                [20, 20, 20, 255, 10, 10, 10, 255],
+                [0, 0, 0, 0, 30, 30, 30, 255]
+            ]
+        );
+        assert!(recorder.frames[1..]
+            .iter()
+            .all(|frame| frame.disposal() == DisposalMethod::Keep));
+    }
+}
@@ -0,0 +1,288 @@
+//! Utilities for benchmarking codec implementations.
+//!
+//! The functions in this module drive a decoder or encoder over an image several times and
+//! report the throughput of the fastest run. Using them from the benchmarks of every format crate
+//! keeps the methodology, and with it the numbers, comparable. They work with any benchmark
+//! harness, or none at all.
+//!
+//! This module is only available with the `bench-util` feature.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::convert::{f32_to_u16, f32_to_u8, SampleFormat};
+use crate::{ColorType, Dimensions, ImageDecoder, ImageEncoder, ImageResult};
+
+/// Image sizes covering images which fit into the cache up to large photos.
+pub const STANDARD_SIZES: [Dimensions; 3] = [
+    Dimensions::new(64, 64),
+    Dimensions::new(512, 512),
+    Dimensions::new(2048, 2048),
+];
+
+/// The timing of repeated runs over the same image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Measurement {
+    color_type: ColorType,
+    width: u32,
+    height: u32,
+    iterations: u32,
+    fastest: Duration,
+    total: Duration,
+}
+
+impl Measurement {
+    /// The color type of the decoded pixels.
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// Returns a tuple containing the width and height of the image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the width and height of the image.
+    pub fn size(&self) -> Dimensions {
+        Dimensions::new(self.width, self.height)
+    }
+
+    /// The number of bytes of the decoded pixels.
+    pub fn bytes(&self) -> u64 {
+        self.size().pixel_count() * u64::from(self.color_type.bytes_per_pixel())
+    }
+
+    /// The number of runs.
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// The duration of the fastest run.
+    pub fn fastest(&self) -> Duration {
+        self.fastest
+    }
+
+    /// The average duration of a run.
+    pub fn mean(&self) -> Duration {
+        self.total / self.iterations
+    }
+
+    /// The throughput of the fastest run in millions of decoded bytes per second.
+    pub fn megabytes_per_second(&self) -> f64 {
+        let seconds = self.fastest.as_secs_f64().max(f64::MIN_POSITIVE);
+        self.bytes() as f64 / seconds / 1e6
+    }
+
+    fn record(&mut self, run: Duration) {
+        self.fastest = self.fastest.min(run);
+        self.total += run;
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {}x{}: {:.1} MB/s",
+            self.color_type,
+            self.width,
+            self.height,
+            self.megabytes_per_second(),
+        )
+    }
+}
+
+/// Generate a deterministic image of the given size and color type.
+///
+/// The image is a gradient overlaid with noise, so that it neither compresses trivially nor is
+/// incompressible. Samples are in native endian.
+pub fn synthetic_image(width: u32, height: u32, color: ColorType) -> Vec<u8> {
+    let layout = color.layout();
+    let channels = layout.channels().len();
+    let span = (u64::from(width) + u64::from(height)).max(1) as f32;
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut buf =
+        Vec::with_capacity(width as usize * height as usize * usize::from(color.bytes_per_pixel()));
+
+    for y in 0..height {
+        for x in 0..width {
+            for channel in 0..channels {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let noise = (state >> 40) as f32 / (1u64 << 24) as f32;
+                let offset = (channel as u32 * width / 4) as f32;
+                let gradient = ((x + y) as f32 + offset) % span / span;
+                let value = 0.75 * gradient + 0.25 * noise;
+
+                match layout.sample_format() {
+                    SampleFormat::U8 => buf.push(f32_to_u8(value)),
+                    SampleFormat::U16 => buf.extend_from_slice(&f32_to_u16(value).to_ne_bytes()),
+                    _ => buf.extend_from_slice(&value.to_ne_bytes()),
+                }
+            }
+        }
+    }
+    buf
+}
+
+/// Measure decoding the image produced by a factory.
+///
+/// The factory is called once per run and must produce a fresh decoder for the same image. Each
+/// run is timed from the call of the factory until `read_image` returns, so that parsing the
+/// header is included. The buffer is allocated once, outside of the timing. Zero iterations are
+/// treated as one.
+pub fn bench_decoder<'a, D, F>(mut decoder_factory: F, iterations: u32) -> ImageResult<Measurement>
+where
+    D: ImageDecoder<'a>,
+    F: FnMut() -> ImageResult<D>,
+{
+    let decoder = decoder_factory()?;
+    let (width, height) = decoder.dimensions();
+    let mut measurement = Measurement {
+        color_type: decoder.color_type(),
+        width,
+        height,
+        iterations: iterations.max(1),
+        fastest: Duration::MAX,
+        total: Duration::ZERO,
+    };
+    let mut buf = decoder.read_image_to_vec()?;
+
+    for _ in 0..measurement.iterations {
+        let start = Instant::now();
+        decoder_factory()?.read_image(&mut buf)?;
+        measurement.record(start.elapsed());
+    }
+    Ok(measurement)
+}
+
+/// Measure encoding an image with encoders produced by a factory.
+///
+/// The factory is called once per run, outside of the timing. The output is written into a
+/// buffer which is reused between runs. Zero iterations are treated as one.
+///
+/// # Panics
+///
+/// Panics if the length of `buf` does not match the dimensions and color type.
+pub fn bench_encoder<E, F>(
+    mut encoder_factory: F,
+    buf: &[u8],
+    dimensions: Dimensions,
+    color: ColorType,
+    iterations: u32,
+) -> ImageResult<Measurement>
+where
+    E: ImageEncoder,
+    F: FnMut() -> E,
+{
+    let Dimensions { width, height } = dimensions;
+    let mut measurement = Measurement {
+        color_type: color,
+        width,
+        height,
+        iterations: iterations.max(1),
+        fastest: Duration::MAX,
+        total: Duration::ZERO,
+    };
+    assert_eq!(
+        buf.len() as u64,
+        measurement.bytes(),
+        "buffer does not match the image"
+    );
+
+    let mut output = Vec::new();
+    for _ in 0..measurement.iterations {
+        output.clear();
+        let encoder = encoder_factory();
+        let start = Instant::now();
+        encoder.write_image(&mut output, buf, width, height, color.into())?;
+        measurement.record(start.elapsed());
+    }
+    Ok(measurement)
+}
+
+/// Measure encoding synthetic images of each color type and size.
+///
+/// Color types the encoder does not support, see `ImageEncoder::supports_color`, are skipped.
+/// The measurements are returned in the order of the sizes, then of the color types.
+pub fn bench_encoder_matrix<E, F>(
+    mut encoder_factory: F,
+    colors: &[ColorType],
+    sizes: &[Dimensions],
+    iterations: u32,
+) -> ImageResult<Vec<Measurement>>
+where
+    E: ImageEncoder,
+    F: FnMut() -> E,
+{
+    let mut measurements = Vec::new();
+    for &size in sizes {
+        for &color in colors {
+            if !encoder_factory().supports_color(color.into()) {
+                continue;
+            }
+            let image = synthetic_image(size.width, size.height, color);
+            let measurement = bench_encoder(&mut encoder_factory, &image, size, color, iterations)?;
+            measurements.push(measurement);
+        }
+    }
+    Ok(measurements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryDecoder;
+    use crate::ExtendedColorType;
+    use std::io::Write;
+
+    /// Copies 8-bit images unchanged.
+    struct CopyEncoder;
+
+    impl ImageEncoder for CopyEncoder {
+        fn write_image<W: Write>(
+            self,
+            mut writer: W,
+            buf: &[u8],
+            _: u32,
+            _: u32,
+            _: ExtendedColorType,
+        ) -> ImageResult<()> {
+            Ok(writer.write_all(buf)?)
+        }
+
+        fn supports_color(&self, color_type: ExtendedColorType) -> bool {
+            color_type == ExtendedColorType::Rgb8
+        }
+    }
+
+    #[test]
+    fn synthesizes_images() {
+        let image = synthetic_image(16, 8, ColorType::La16);
+        assert_eq!(image.len(), 16 * 8 * 4);
+        assert_eq!(image, synthetic_image(16, 8, ColorType::La16));
+        assert!(synthetic_image(0, 8, ColorType::Rgb8).is_empty());
+    }
+
+    #[test]
+    fn measures_runs() {
+        let image = synthetic_image(4, 3, ColorType::Rgb8);
+        let measurement = bench_decoder(
+            || Ok(MemoryDecoder::new(4, 3, ColorType::Rgb8, image.clone())),
+            3,
+        )
+        .unwrap();
+        assert_eq!(measurement.iterations(), 3);
+        assert_eq!(measurement.bytes(), 36);
+        assert!(measurement.fastest() <= measurement.mean());
+        assert!(measurement.to_string().starts_with("Rgb8 4x3: "));
+
+        let colors = [ColorType::L8, ColorType::Rgb8];
+        let sizes = [Dimensions::new(2, 2), Dimensions::new(4, 4)];
+        let measurements = bench_encoder_matrix(|| CopyEncoder, &colors, &sizes, 0).unwrap();
+        assert_eq!(measurements.len(), 2);
+        assert_eq!(measurements[1].size(), Dimensions::new(4, 4));
+        assert_eq!(measurements[1].iterations(), 1);
+    }
+}
@@ -0,0 +1,384 @@
+use std::io::Read;
+use std::mem::MaybeUninit;
+
+use crate::{AlphaMode, Cicp, DecodeFidelity, DecodeStats, HdrMetadata, ImageResult, Limits};
+use crate::{ColorType, ExtendedColorType, ImageDecoder, Progress, ProgressOptions, RowOrder};
+
+/// A decoder of any type, behind a box.
+///
+/// `ImageDecoder` has an associated reader type and generic methods, so it can not be used as a
+/// trait object. This type erases the decoder instead, which lets the decoders of different
+/// formats be selected at runtime, as done by `CodecRegistry`. Every method is forwarded to the
+/// boxed decoder, including the read methods it overrides.
+pub struct BoxedDecoder<'a> {
+    inner: Box<dyn ErasedDecoder<'a> + 'a>,
+}
+
+impl<'a> BoxedDecoder<'a> {
+    /// Box a decoder.
+    pub fn new<D: ImageDecoder<'a> + 'a>(decoder: D) -> Self {
+        BoxedDecoder {
+            inner: Box::new(decoder),
+        }
+    }
+}
+
+/// The object safe part of `ImageDecoder`, implemented for every decoder.
+trait ErasedDecoder<'a> {
+    fn dimensions(&self) -> (u32, u32);
+    fn color_type(&self) -> ColorType;
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()>;
+    fn original_color_type(&self) -> ExtendedColorType;
+    fn decode_fidelity(&self) -> DecodeFidelity;
+    fn hdr_metadata(&self) -> Option<HdrMetadata>;
+    fn cicp(&self) -> Option<Cicp>;
+    fn icc_profile(&self) -> Option<Vec<u8>>;
+    fn alpha_mode(&self) -> AlphaMode;
+    fn row_order(&self) -> RowOrder;
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32);
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()>;
+    fn limits(&self) -> Limits;
+    fn total_bytes(&self) -> u64;
+    fn scanline_bytes(&self) -> u64;
+    fn into_reader(self: Box<Self>) -> ImageResult<Box<dyn Read + 'a>>;
+    fn read_image(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()>;
+    fn read_image_with_progress(
+        self: Box<Self>,
+        buf: &mut [u8],
+        progress_callback: &dyn Fn(Progress),
+    ) -> ImageResult<()>;
+    fn read_image_with_progress_options(
+        self: Box<Self>,
+        buf: &mut [u8],
+        options: ProgressOptions,
+        progress_callback: &dyn Fn(Progress),
+    ) -> ImageResult<()>;
+    fn read_image_with_row_hook(
+        self: Box<Self>,
+        buf: &mut [u8],
+        hook: &mut dyn FnMut(&[u8]),
+    ) -> ImageResult<()>;
+    fn read_image_with_stats(self: Box<Self>, buf: &mut [u8]) -> ImageResult<DecodeStats>;
+    fn read_image_uninit(self: Box<Self>, buf: &mut [MaybeUninit<u8>]) -> ImageResult<&mut [u8]>;
+    fn read_image_to_vec(self: Box<Self>) -> ImageResult<Vec<u8>>;
+    fn compressed_color_type(&self) -> Option<ExtendedColorType>;
+    fn read_compressed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()>;
+    fn read_compressed_to_vec(self: Box<Self>) -> ImageResult<(ExtendedColorType, Vec<u8>)>;
+}
+
+impl<'a, D: ImageDecoder<'a>> ErasedDecoder<'a> for D {
+    fn dimensions(&self) -> (u32, u32) {
+        ImageDecoder::dimensions(self)
+    }
+
+    fn color_type(&self) -> ColorType {
+        ImageDecoder::color_type(self)
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        ImageDecoder::set_output_color(self, color)
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        ImageDecoder::original_color_type(self)
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        ImageDecoder::decode_fidelity(self)
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        ImageDecoder::hdr_metadata(self)
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        ImageDecoder::cicp(self)
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        ImageDecoder::icc_profile(self)
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        ImageDecoder::alpha_mode(self)
+    }
+
+    fn row_order(&self) -> RowOrder {
+        ImageDecoder::row_order(self)
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        ImageDecoder::set_scale_hint(self, numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        ImageDecoder::set_limits(self, limits)
+    }
+
+    fn limits(&self) -> Limits {
+        ImageDecoder::limits(self)
+    }
+
+    fn total_bytes(&self) -> u64 {
+        ImageDecoder::total_bytes(self)
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        ImageDecoder::scanline_bytes(self)
+    }
+
+    fn into_reader(self: Box<Self>) -> ImageResult<Box<dyn Read + 'a>> {
+        Ok(Box::new(ImageDecoder::into_reader(*self)?))
+    }
+
+    fn read_image(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        ImageDecoder::read_image(*self, buf)
+    }
+
+    fn read_image_with_progress(
+        self: Box<Self>,
+        buf: &mut [u8],
+        progress_callback: &dyn Fn(Progress),
+    ) -> ImageResult<()> {
+        ImageDecoder::read_image_with_progress(*self, buf, progress_callback)
+    }
+
+    fn read_image_with_progress_options(
+        self: Box<Self>,
+        buf: &mut [u8],
+        options: ProgressOptions,
+        progress_callback: &dyn Fn(Progress),
+    ) -> ImageResult<()> {
+        ImageDecoder::read_image_with_progress_options(*self, buf, options, progress_callback)
+    }
+
+    fn read_image_with_row_hook(
+        self: Box<Self>,
+        buf: &mut [u8],
+        hook: &mut dyn FnMut(&[u8]),
+    ) -> ImageResult<()> {
+        ImageDecoder::read_image_with_row_hook(*self, buf, hook)
+    }
+
+    fn read_image_with_stats(self: Box<Self>, buf: &mut [u8]) -> ImageResult<DecodeStats> {
+        ImageDecoder::read_image_with_stats(*self, buf)
+    }
+
+    fn read_image_uninit(self: Box<Self>, buf: &mut [MaybeUninit<u8>]) -> ImageResult<&mut [u8]> {
+        ImageDecoder::read_image_uninit(*self, buf)
+    }
+
+    fn read_image_to_vec(self: Box<Self>) -> ImageResult<Vec<u8>> {
+        ImageDecoder::read_image_to_vec(*self)
+    }
+
+    fn compressed_color_type(&self) -> Option<ExtendedColorType> {
+        ImageDecoder::compressed_color_type(self)
+    }
+
+    fn read_compressed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        ImageDecoder::read_compressed(*self, buf)
+    }
+
+    fn read_compressed_to_vec(self: Box<Self>) -> ImageResult<(ExtendedColorType, Vec<u8>)> {
+        ImageDecoder::read_compressed_to_vec(*self)
+    }
+}
+
+impl<'a> ImageDecoder<'a> for BoxedDecoder<'a> {
+    type Reader = Box<dyn Read + 'a>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        self.inner.set_output_color(color)
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        self.inner.decode_fidelity()
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        self.inner.into_reader()
+    }
+
+    fn total_bytes(&self) -> u64 {
+        self.inner.total_bytes()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        self.inner.scanline_bytes()
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        self.inner.read_image(buf)
+    }
+
+    fn read_image_with_progress<F: Fn(Progress)>(
+        self,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        self.inner.read_image_with_progress(buf, &progress_callback)
+    }
+
+    fn read_image_with_progress_options<F: Fn(Progress)>(
+        self,
+        buf: &mut [u8],
+        options: ProgressOptions,
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        self.inner
+            .read_image_with_progress_options(buf, options, &progress_callback)
+    }
+
+    fn read_image_with_row_hook<F: FnMut(&[u8])>(
+        self,
+        buf: &mut [u8],
+        mut hook: F,
+    ) -> ImageResult<()> {
+        self.inner.read_image_with_row_hook(buf, &mut hook)
+    }
+
+    fn read_image_with_stats(self, buf: &mut [u8]) -> ImageResult<DecodeStats> {
+        self.inner.read_image_with_stats(buf)
+    }
+
+    fn read_image_uninit(self, buf: &mut [MaybeUninit<u8>]) -> ImageResult<&mut [u8]> {
+        self.inner.read_image_uninit(buf)
+    }
+
+    fn read_image_to_vec(self) -> ImageResult<Vec<u8>> {
+        self.inner.read_image_to_vec()
+    }
+
+    fn compressed_color_type(&self) -> Option<ExtendedColorType> {
+        self.inner.compressed_color_type()
+    }
+
+    fn read_compressed(self, buf: &mut [u8]) -> ImageResult<()> {
+        self.inner.read_compressed(buf)
+    }
+
+    fn read_compressed_to_vec(self) -> ImageResult<(ExtendedColorType, Vec<u8>)> {
+        self.inner.read_compressed_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    // The methods of `ErasedDecoder` are in scope as well, so calls are qualified.
+    #[test]
+    fn forwards_to_the_boxed_decoder() {
+        let data: Vec<u8> = (0..12).collect();
+        let mut decoder =
+            BoxedDecoder::new(MemoryDecoder::new(2, 2, ColorType::Rgb8, data.clone()));
+        assert_eq!(ImageDecoder::dimensions(&decoder), (2, 2));
+        assert_eq!(ImageDecoder::color_type(&decoder), ColorType::Rgb8);
+        assert_eq!(ImageDecoder::scanline_bytes(&decoder), 6);
+        assert!(ImageDecoder::set_output_color(&mut decoder, ColorType::L8).is_err());
+        assert_eq!(decode(decoder), data);
+    }
+
+    /// Reads a different image through its overridden methods.
+    struct Overriding(MemoryDecoder);
+
+    impl<'a> ImageDecoder<'a> for Overriding {
+        type Reader = <MemoryDecoder as ImageDecoder<'a>>::Reader;
+
+        fn dimensions(&self) -> (u32, u32) {
+            ImageDecoder::dimensions(&self.0)
+        }
+
+        fn color_type(&self) -> ColorType {
+            ImageDecoder::color_type(&self.0)
+        }
+
+        fn into_reader(self) -> ImageResult<Self::Reader> {
+            ImageDecoder::into_reader(self.0)
+        }
+
+        fn read_image_with_progress<F: Fn(Progress)>(
+            self,
+            buf: &mut [u8],
+            progress_callback: F,
+        ) -> ImageResult<()> {
+            buf.fill(7);
+            progress_callback(Progress {
+                current: 1,
+                total: 1,
+            });
+            Ok(())
+        }
+
+        fn read_image_to_vec(self) -> ImageResult<Vec<u8>> {
+            Ok(vec![9; 2])
+        }
+    }
+
+    #[test]
+    fn forwards_overridden_reads() {
+        let decoder = || {
+            BoxedDecoder::new(Overriding(MemoryDecoder::new(
+                2,
+                1,
+                ColorType::L8,
+                vec![0; 2],
+            )))
+        };
+        let mut buf = [0; 2];
+        let reported = std::cell::Cell::new(None);
+        ImageDecoder::read_image_with_progress(decoder(), &mut buf, |progress| {
+            reported.set(Some(progress))
+        })
+        .unwrap();
+        assert_eq!(buf, [7, 7]);
+        assert_eq!(reported.get().map(Progress::current), Some(1));
+        assert_eq!(ImageDecoder::read_image_to_vec(decoder()).unwrap(), [9, 9]);
+    }
+}
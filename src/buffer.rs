@@ -0,0 +1,129 @@
+use std::convert::TryFrom;
+
+use crate::{ColorType, Dimensions, ImageError, ImageResult};
+use crate::{LimitError, LimitErrorKind, ParameterError, ParameterErrorKind};
+
+/// An owned buffer of pixel data with known dimensions and color type.
+///
+/// This is a minimal container for decoded results. The bytes are stored row by row, from top to
+/// bottom, without any padding between rows. Samples wider than a byte are kept in native endian,
+/// the same layout which `ImageDecoder::read_image` produces.
+///
+/// The length of the buffer is guaranteed to match the dimensions and color type.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PixelBuffer {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    color: ColorType,
+}
+
+impl PixelBuffer {
+    /// Create a zero-initialized buffer for an image of the given dimensions and color type.
+    ///
+    /// Fails with a limit error if the buffer could not be addressed on this platform.
+    pub fn new(width: u32, height: u32, color: ColorType) -> ImageResult<Self> {
+        let len = Self::byte_len(width, height, color)?;
+        Ok(PixelBuffer {
+            data: vec![0; len],
+            width,
+            height,
+            color,
+        })
+    }
+
+    /// Wrap existing pixel data.
+    ///
+    /// Fails with a `DimensionMismatch` parameter error if the length of `data` is not exactly
+    /// the number of bytes required by the dimensions and color type.
+    pub fn from_vec(width: u32, height: u32, color: ColorType, data: Vec<u8>) -> ImageResult<Self> {
+        if Self::byte_len(width, height, color)? != data.len() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        Ok(PixelBuffer {
+            data,
+            width,
+            height,
+            color,
+        })
+    }
+
+    /// The width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns a tuple containing the width and height of the image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the width and height of the image.
+    pub fn size(&self) -> Dimensions {
+        Dimensions::new(self.width, self.height)
+    }
+
+    /// The color type of the pixel data.
+    pub fn color_type(&self) -> ColorType {
+        self.color
+    }
+
+    /// The pixel data as bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The pixel data as mutable bytes.
+    ///
+    /// The length can not be changed through this reference so the invariants are preserved.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Consume the buffer, returning the underlying pixel data.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn byte_len(width: u32, height: u32, color: ColorType) -> ImageResult<usize> {
+        u64::from(width)
+            .checked_mul(u64::from(height))
+            .and_then(|pixels| pixels.checked_mul(u64::from(color.bytes_per_pixel())))
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or_else(|| {
+                ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_is_sized_by_color_type() {
+        let buffer = PixelBuffer::new(3, 2, ColorType::Rgb16).unwrap();
+        assert_eq!(buffer.as_bytes().len(), 3 * 2 * 6);
+        assert_eq!(buffer.dimensions(), (3, 2));
+    }
+
+    #[test]
+    fn from_vec_checks_length() {
+        assert!(PixelBuffer::from_vec(2, 2, ColorType::La8, vec![0; 8]).is_ok());
+
+        match PixelBuffer::from_vec(2, 2, ColorType::La8, vec![0; 7]) {
+            Err(ImageError::Parameter(err)) => {
+                assert_eq!(err.kind(), ParameterErrorKind::DimensionMismatch)
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+/// A conversion of whole pixels, applied by a `ChunkedReader`.
+pub(crate) trait ChunkConversion {
+    /// The number of bytes produced from `len` bytes of the inner reader.
+    fn output_len(&self, len: usize) -> usize;
+
+    /// Convert a chunk read from the inner reader.
+    fn convert(&mut self, source: &[u8], output: &mut [u8]);
+}
+
+/// The reader of the adapters which convert the pixels of a decoder while reading.
+///
+/// The image is read in chunks of whole pixels, holding one chunk of the source and its
+/// conversion in memory. Without a conversion the inner reader is passed through.
+pub(crate) struct ChunkedReader<R, C> {
+    inner: R,
+    conversion: Option<C>,
+    /// The bytes of the inner reader which have not been read yet.
+    remaining: u64,
+    source: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read, C: ChunkConversion> ChunkedReader<R, C> {
+    /// Convert `total_bytes` of the inner reader, `chunk` bytes at a time.
+    pub(crate) fn new(inner: R, conversion: Option<C>, total_bytes: u64, chunk: u64) -> Self {
+        let chunk = match conversion {
+            Some(_) => usize::try_from(chunk.min(total_bytes)).unwrap_or(usize::MAX),
+            None => 0,
+        };
+        ChunkedReader {
+            inner,
+            conversion,
+            remaining: total_bytes,
+            source: vec![0; chunk],
+            output: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read, C: ChunkConversion> Read for ChunkedReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let conversion = match &mut self.conversion {
+            Some(conversion) => conversion,
+            None => return self.inner.read(buf),
+        };
+
+        if self.position == self.output.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+
+            let remaining = usize::try_from(self.remaining).unwrap_or(usize::MAX);
+            let len = self.source.len().min(remaining);
+            let source = &mut self.source[..len];
+            self.inner.read_exact(source)?;
+            self.remaining -= len as u64;
+
+            self.output.resize(conversion.output_len(len), 0);
+            conversion.convert(source, &mut self.output);
+            self.position = 0;
+        }
+
+        let available = &self.output[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// The least multiple of `unit` covering a group of scanlines, at least one unit.
+///
+/// Chunks are sized in pixels, or in rows for conversions which need whole rows.
+pub(crate) fn chunk_len(scanline_bytes: u64, unit: u64) -> u64 {
+    match unit {
+        0 => 0,
+        unit => scanline_bytes.div_ceil(unit).max(1) * unit,
+    }
+}
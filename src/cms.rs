@@ -0,0 +1,246 @@
+use std::io::{self, Read};
+
+use crate::chunked::{chunk_len, ChunkConversion, ChunkedReader};
+use crate::{AlphaMode, Cicp, DecodeFidelity, HdrMetadata, ImageResult, Limits, RowOrder};
+use crate::{ColorType, ExtendedColorType, ImageDecoder};
+
+/// A description of the color space of an image.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ColorProfile {
+    /// The color space is described by an ICC profile.
+    Icc(Vec<u8>),
+    /// The color space is described by code points.
+    Cicp(Cicp),
+}
+
+impl ColorProfile {
+    /// The sRGB color space.
+    pub const SRGB: ColorProfile = ColorProfile::Cicp(Cicp::SRGB);
+
+    /// Returns the color space of the image of a decoder.
+    ///
+    /// An ICC profile takes precedence over code points. Images which describe neither are
+    /// assumed to be in sRGB.
+    pub fn of_decoder<'a, D: ImageDecoder<'a>>(decoder: &D) -> Self {
+        if let Some(icc) = decoder.icc_profile() {
+            ColorProfile::Icc(icc)
+        } else if let Some(cicp) = decoder.cicp() {
+            ColorProfile::Cicp(cicp)
+        } else {
+            ColorProfile::SRGB
+        }
+    }
+}
+
+/// A color management system which converts pixels between color spaces.
+///
+/// This crate does not implement color management itself. Implement this trait for a binding to
+/// a library such as lcms2, qcms or moxcms, and convert decoded images with
+/// `ColorManagedDecoder`.
+pub trait Cms {
+    /// Prepare the conversion of pixels of the given color type from one color space to another.
+    ///
+    /// Returns an unsupported error if the profiles or the color type can not be handled, and a
+    /// parameter error if a profile is malformed.
+    fn create_transform(
+        &self,
+        input: &ColorProfile,
+        output: &ColorProfile,
+        color: ColorType,
+    ) -> ImageResult<Box<dyn ColorTransform>>;
+}
+
+/// A prepared conversion between two color spaces.
+///
+/// Transforms are `Send`, so that color managed decodes can be moved to another thread.
+pub trait ColorTransform: Send {
+    /// Convert whole pixels in place.
+    ///
+    /// The pixels have the color type the transform was created for, in the layout produced by
+    /// `ImageDecoder::read_image`.
+    fn transform(&self, pixels: &mut [u8]);
+}
+
+/// A decoder adapter converting the pixels into another color space.
+///
+/// The color space of the image is determined with `ColorProfile::of_decoder`. If it differs
+/// from the target, the pixels are converted by a transform of the color management system while
+/// reading.
+pub struct ColorManagedDecoder<D> {
+    inner: D,
+    target: ColorProfile,
+    transform: Option<Box<dyn ColorTransform>>,
+}
+
+/// The reader of a `ColorManagedDecoder`.
+pub struct ColorManagedReader<R> {
+    inner: ChunkedReader<R, Box<dyn ColorTransform>>,
+}
+
+impl<'a, D: ImageDecoder<'a>> ColorManagedDecoder<D> {
+    /// Wrap a decoder to produce pixels in the target color space.
+    ///
+    /// Returns the error of the color management system if it can not create the transform.
+    pub fn new(decoder: D, cms: &dyn Cms, target: ColorProfile) -> ImageResult<Self> {
+        let source = ColorProfile::of_decoder(&decoder);
+        let transform = if source == target {
+            None
+        } else {
+            Some(cms.create_transform(&source, &target, decoder.color_type())?)
+        };
+
+        Ok(ColorManagedDecoder {
+            inner: decoder,
+            target,
+            transform,
+        })
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for ColorManagedDecoder<D> {
+    type Reader = ColorManagedReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        self.inner.decode_fidelity()
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        match &self.target {
+            ColorProfile::Cicp(cicp) => Some(*cicp),
+            ColorProfile::Icc(_) => None,
+        }
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        match &self.target {
+            ColorProfile::Icc(icc) => Some(icc.clone()),
+            ColorProfile::Cicp(_) => None,
+        }
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        self.inner.scanline_bytes()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        let chunk = chunk_len(self.inner.scanline_bytes(), bpp);
+        let total_bytes = self.inner.total_bytes();
+        let inner = self.inner.into_reader()?;
+        Ok(ColorManagedReader {
+            inner: ChunkedReader::new(inner, self.transform, total_bytes, chunk),
+        })
+    }
+}
+
+impl<R: Read> Read for ColorManagedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl ChunkConversion for Box<dyn ColorTransform> {
+    fn output_len(&self, len: usize) -> usize {
+        len
+    }
+
+    fn convert(&mut self, source: &[u8], output: &mut [u8]) {
+        output.copy_from_slice(source);
+        self.transform(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    /// Knows a single profile, which stores the inverse of sRGB samples.
+    struct InvertingCms;
+
+    struct Invert;
+
+    impl Cms for InvertingCms {
+        fn create_transform(
+            &self,
+            input: &ColorProfile,
+            output: &ColorProfile,
+            color: ColorType,
+        ) -> ImageResult<Box<dyn ColorTransform>> {
+            assert_eq!(input, &ColorProfile::SRGB);
+            assert_eq!(output, &ColorProfile::Icc(b"inverted".to_vec()));
+            assert_eq!(color, ColorType::L8);
+            Ok(Box::new(Invert))
+        }
+    }
+
+    impl ColorTransform for Invert {
+        fn transform(&self, pixels: &mut [u8]) {
+            pixels.iter_mut().for_each(|sample| *sample = !*sample);
+        }
+    }
+
+    #[test]
+    fn transforms_into_target() {
+        let data: Vec<u8> = (0..6).collect();
+        let image = || MemoryDecoder::new(3, 2, ColorType::L8, data.clone());
+
+        let target = ColorProfile::Icc(b"inverted".to_vec());
+        let decoder = ColorManagedDecoder::new(image(), &InvertingCms, target).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(b"inverted".to_vec()));
+        assert_eq!(decoder.cicp(), None);
+        assert_eq!(decode(decoder), [255, 254, 253, 252, 251, 250]);
+
+        let decoder = ColorManagedDecoder::new(image(), &InvertingCms, ColorProfile::SRGB).unwrap();
+        assert_eq!(decoder.cicp(), Some(Cicp::SRGB));
+        assert_eq!(decode(decoder), data);
+
+        fn assert_send<T: Send>(_: &T) {}
+        let target = ColorProfile::Icc(b"inverted".to_vec());
+        let decoder = ColorManagedDecoder::new(image(), &InvertingCms, target).unwrap();
+        assert_send(&decoder);
+        assert_send(&decoder.into_reader().unwrap());
+    }
+}
@@ -1,7 +1,8 @@
-use crate::NonExhaustiveMarker;
+use crate::convert::SampleFormat;
 
 /// An enumeration over supported color types and bit depths
 #[derive(Copy, PartialEq, Eq, Debug, Clone, Hash)]
+#[non_exhaustive]
 pub enum ColorType {
     /// Pixel is 8-bit luminance
     L8,
@@ -26,13 +27,15 @@ pub enum ColorType {
     /// Pixel is 8-bit BGR with an alpha channel
     Bgra8,
 
-    #[doc(hidden)]
-    __Nonexhaustive(NonExhaustiveMarker),
+    /// Pixel is 32-bit float RGB, with a nominal range of `0.0` to `1.0`
+    Rgb32F,
+    /// Pixel is 32-bit float RGBA, with a nominal range of `0.0` to `1.0`
+    Rgba32F,
 }
 
 impl ColorType {
     /// Returns the number of bytes contained in a pixel of `ColorType` ```c```
-    pub fn bytes_per_pixel(self) -> u8 {
+    pub const fn bytes_per_pixel(self) -> u8 {
         match self {
             ColorType::L8 => 1,
             ColorType::L16 | ColorType::La8 => 2,
@@ -40,20 +43,143 @@ impl ColorType {
             ColorType::Rgba8 | ColorType::Bgra8 | ColorType::La16 => 4,
             ColorType::Rgb16 => 6,
             ColorType::Rgba16 => 8,
-            ColorType::__Nonexhaustive(marker) => match marker._private {},
+            ColorType::Rgb32F => 12,
+            ColorType::Rgba32F => 16,
         }
     }
 
     /// Returns the number of bits contained in a pixel of `ColorType` ```c``` (which will always be
     /// a multiple of 8).
-    pub fn bits_per_pixel(self) -> u16 {
-        <u16 as From<u8>>::from(self.bytes_per_pixel()) * 8
+    pub const fn bits_per_pixel(self) -> u16 {
+        self.bytes_per_pixel() as u16 * 8
     }
 
     /// Returns the number of color channels that make up this pixel
-    pub fn channel_count(self) -> u8 {
-        let e: ExtendedColorType = self.into();
-        e.channel_count()
+    pub const fn channel_count(self) -> u8 {
+        match self {
+            ColorType::L8 | ColorType::L16 => 1,
+            ColorType::La8 | ColorType::La16 => 2,
+            ColorType::Rgb8 | ColorType::Rgb16 | ColorType::Bgr8 | ColorType::Rgb32F => 3,
+            ColorType::Rgba8 | ColorType::Rgba16 | ColorType::Bgra8 | ColorType::Rgba32F => 4,
+        }
+    }
+
+    /// Returns whether the pixel has an alpha channel.
+    pub const fn has_alpha(self) -> bool {
+        match self {
+            ColorType::La8
+            | ColorType::Rgba8
+            | ColorType::La16
+            | ColorType::Rgba16
+            | ColorType::Bgra8
+            | ColorType::Rgba32F => true,
+            ColorType::L8
+            | ColorType::Rgb8
+            | ColorType::L16
+            | ColorType::Rgb16
+            | ColorType::Bgr8
+            | ColorType::Rgb32F => false,
+        }
+    }
+
+    /// Returns the color type with the same channels and depth, but without alpha.
+    pub const fn without_alpha(self) -> ColorType {
+        match self {
+            ColorType::La8 => ColorType::L8,
+            ColorType::Rgba8 => ColorType::Rgb8,
+            ColorType::La16 => ColorType::L16,
+            ColorType::Rgba16 => ColorType::Rgb16,
+            ColorType::Bgra8 => ColorType::Bgr8,
+            ColorType::Rgba32F => ColorType::Rgb32F,
+            opaque => opaque,
+        }
+    }
+
+    /// Returns a description of the memory layout of a pixel.
+    pub const fn layout(self) -> ChannelLayout {
+        use Channel::{Alpha, Blue, Green, Luma, Red};
+        let (channels, sample_format): (&'static [Channel], _) = match self {
+            ColorType::L8 => (&[Luma], SampleFormat::U8),
+            ColorType::La8 => (&[Luma, Alpha], SampleFormat::U8),
+            ColorType::Rgb8 => (&[Red, Green, Blue], SampleFormat::U8),
+            ColorType::Rgba8 => (&[Red, Green, Blue, Alpha], SampleFormat::U8),
+            ColorType::L16 => (&[Luma], SampleFormat::U16),
+            ColorType::La16 => (&[Luma, Alpha], SampleFormat::U16),
+            ColorType::Rgb16 => (&[Red, Green, Blue], SampleFormat::U16),
+            ColorType::Rgba16 => (&[Red, Green, Blue, Alpha], SampleFormat::U16),
+            ColorType::Bgr8 => (&[Blue, Green, Red], SampleFormat::U8),
+            ColorType::Bgra8 => (&[Blue, Green, Red, Alpha], SampleFormat::U8),
+            ColorType::Rgb32F => (&[Red, Green, Blue], SampleFormat::F32),
+            ColorType::Rgba32F => (&[Red, Green, Blue, Alpha], SampleFormat::F32),
+        };
+        ChannelLayout {
+            channels,
+            sample_format,
+            planar: false,
+        }
+    }
+}
+
+/// A channel of a pixel.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Channel {
+    /// The red component of a color.
+    Red,
+    /// The green component of a color.
+    Green,
+    /// The blue component of a color.
+    Blue,
+    /// The opacity of a pixel.
+    Alpha,
+    /// The brightness of a gray pixel.
+    Luma,
+}
+
+/// A machine-readable description of the memory layout of pixels.
+///
+/// This allows mapping color types to the pixel formats of other libraries, such as texture
+/// formats of graphics APIs, without matching on every color type.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ChannelLayout {
+    channels: &'static [Channel],
+    sample_format: SampleFormat,
+    planar: bool,
+}
+
+impl ChannelLayout {
+    /// The channels of a pixel, in the order of memory.
+    pub const fn channels(&self) -> &'static [Channel] {
+        self.channels
+    }
+
+    /// The encoding of each sample.
+    pub const fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// The number of bits of each sample.
+    pub const fn bits_per_channel(&self) -> u8 {
+        self.sample_format.bytes() as u8 * 8
+    }
+
+    /// Returns whether each channel is stored in a separate plane.
+    ///
+    /// Otherwise the samples of a pixel are interleaved. All color types of this crate are
+    /// interleaved.
+    pub const fn is_planar(&self) -> bool {
+        self.planar
+    }
+
+    /// The index of a channel within a pixel, if the pixel has that channel.
+    pub fn position(&self, channel: Channel) -> Option<usize> {
+        self.channels.iter().position(|&c| c == channel)
+    }
+
+    /// The offset of a channel from the start of an interleaved pixel in bytes.
+    pub fn byte_offset(&self, channel: Channel) -> Option<usize> {
+        self.position(channel)
+            .map(|index| index * self.sample_format.bytes())
     }
 }
 
@@ -67,6 +193,7 @@ impl ColorType {
 /// decoding from and encoding to such an image format.
 #[allow(missing_docs)]
 #[derive(Copy, PartialEq, Eq, Debug, Clone, Hash)]
+#[non_exhaustive]
 pub enum ExtendedColorType {
     L1,
     La1,
@@ -90,50 +217,326 @@ pub enum ExtendedColorType {
     Rgba16,
     Bgr8,
     Bgra8,
+    Rgb32F,
+    Rgba32F,
 
-    /// Pixel is of unknown color type with the specified bits per pixel. This can apply to pixels
-    /// which are associated with an external palette. In that case, the pixel value is an index
-    /// into the palette.
-    Unknown(u8),
+    /// BC1 block compression, also known as DXT1: RGB with optional 1-bit alpha, 8 bytes for
+    /// each block of 4 by 4 pixels.
+    Bc1,
+    /// BC2 block compression, also known as DXT3: RGBA with explicit 4-bit alpha, 16 bytes for
+    /// each block.
+    Bc2,
+    /// BC3 block compression, also known as DXT5: RGBA with interpolated alpha, 16 bytes for
+    /// each block.
+    Bc3,
+    /// BC4 block compression of a single unsigned channel, 8 bytes for each block.
+    Bc4,
+    /// BC5 block compression of two unsigned channels, such as normal maps, 16 bytes for each
+    /// block.
+    Bc5,
+    /// BC6H block compression of unsigned half float RGB, 16 bytes for each block.
+    Bc6h,
+    /// BC7 block compression of RGBA, 16 bytes for each block.
+    Bc7,
 
-    #[doc(hidden)]
-    __Nonexhaustive(NonExhaustiveMarker),
+    /// Pixel is of unknown color type with the specified layout. This can apply to pixels which
+    /// are associated with an external palette. In that case, the pixel value is a single channel
+    /// holding an index into the palette.
+    Unknown {
+        /// The number of bits in each sample of a pixel.
+        bits_per_sample: u8,
+        /// The number of samples in each pixel.
+        channels: u8,
+    },
 }
 
 impl ExtendedColorType {
+    /// Returns the color type of pixels with the given number of channels and bits per sample.
+    ///
+    /// One and two channels are taken to be luma and luma with alpha, three and four channels to
+    /// be RGB and RGBA, in that order. Samples of 32 bits are taken to be floats, since there are
+    /// no variants for 32-bit integers. Combinations without a matching variant are described by
+    /// `Unknown`. Returns `None` if there are no channels or no bits per sample.
+    pub const fn from_parts(channels: u8, bits_per_sample: u8) -> Option<ExtendedColorType> {
+        if channels == 0 || bits_per_sample == 0 {
+            return None;
+        }
+
+        Some(match (channels, bits_per_sample) {
+            (1, 1) => ExtendedColorType::L1,
+            (2, 1) => ExtendedColorType::La1,
+            (3, 1) => ExtendedColorType::Rgb1,
+            (4, 1) => ExtendedColorType::Rgba1,
+            (1, 2) => ExtendedColorType::L2,
+            (2, 2) => ExtendedColorType::La2,
+            (3, 2) => ExtendedColorType::Rgb2,
+            (4, 2) => ExtendedColorType::Rgba2,
+            (1, 4) => ExtendedColorType::L4,
+            (2, 4) => ExtendedColorType::La4,
+            (3, 4) => ExtendedColorType::Rgb4,
+            (4, 4) => ExtendedColorType::Rgba4,
+            (1, 8) => ExtendedColorType::L8,
+            (2, 8) => ExtendedColorType::La8,
+            (3, 8) => ExtendedColorType::Rgb8,
+            (4, 8) => ExtendedColorType::Rgba8,
+            (1, 16) => ExtendedColorType::L16,
+            (2, 16) => ExtendedColorType::La16,
+            (3, 16) => ExtendedColorType::Rgb16,
+            (4, 16) => ExtendedColorType::Rgba16,
+            (3, 32) => ExtendedColorType::Rgb32F,
+            (4, 32) => ExtendedColorType::Rgba32F,
+            _ => ExtendedColorType::Unknown {
+                bits_per_sample,
+                channels,
+            },
+        })
+    }
+
     /// Get the number of channels for colors of this type.
     ///
-    /// Note that the `Unknown` variant returns a value of `1` since pixels can only be treated as
-    /// an opaque datum by the library.
-    pub fn channel_count(self) -> u8 {
+    /// For the `Unknown` variant this is the specified number of channels, although the library
+    /// can not interpret them.
+    pub const fn channel_count(self) -> u8 {
         match self {
             ExtendedColorType::L1
             | ExtendedColorType::L2
             | ExtendedColorType::L4
             | ExtendedColorType::L8
             | ExtendedColorType::L16
-            | ExtendedColorType::Unknown(_) => 1,
+            | ExtendedColorType::Bc4 => 1,
             ExtendedColorType::La1
             | ExtendedColorType::La2
             | ExtendedColorType::La4
             | ExtendedColorType::La8
-            | ExtendedColorType::La16 => 2,
+            | ExtendedColorType::La16
+            | ExtendedColorType::Bc5 => 2,
             ExtendedColorType::Rgb1
             | ExtendedColorType::Rgb2
             | ExtendedColorType::Rgb4
             | ExtendedColorType::Rgb8
             | ExtendedColorType::Rgb16
-            | ExtendedColorType::Bgr8 => 3,
+            | ExtendedColorType::Bgr8
+            | ExtendedColorType::Rgb32F
+            | ExtendedColorType::Bc6h => 3,
             ExtendedColorType::Rgba1
             | ExtendedColorType::Rgba2
             | ExtendedColorType::Rgba4
             | ExtendedColorType::Rgba8
             | ExtendedColorType::Rgba16
-            | ExtendedColorType::Bgra8 => 4,
-            ExtendedColorType::__Nonexhaustive(marker) => match marker._private {},
+            | ExtendedColorType::Bgra8
+            | ExtendedColorType::Rgba32F
+            | ExtendedColorType::Bc1
+            | ExtendedColorType::Bc2
+            | ExtendedColorType::Bc3
+            | ExtendedColorType::Bc7 => 4,
+            ExtendedColorType::Unknown { channels, .. } => channels,
+        }
+    }
+
+    /// Returns whether the pixel has an alpha channel, see `ColorType::has_alpha`.
+    ///
+    /// BC1 counts as having alpha, as it can encode transparent pixels. The channels of the
+    /// `Unknown` variant have no meaning, so it never has alpha.
+    pub(crate) const fn has_alpha(self) -> bool {
+        match self.to_color_type() {
+            Some(color) => color.has_alpha(),
+            None => matches!(
+                self,
+                ExtendedColorType::La1
+                    | ExtendedColorType::La2
+                    | ExtendedColorType::La4
+                    | ExtendedColorType::Rgba1
+                    | ExtendedColorType::Rgba2
+                    | ExtendedColorType::Rgba4
+                    | ExtendedColorType::Bc1
+                    | ExtendedColorType::Bc2
+                    | ExtendedColorType::Bc3
+                    | ExtendedColorType::Bc7
+            ),
+        }
+    }
+
+    /// Returns the number of bits contained in a pixel of this color type.
+    ///
+    /// For the `Unknown` variant these are the bits per sample times the number of channels. For
+    /// block compressed color types these are the bits of a block divided by its pixels.
+    pub const fn bits_per_pixel(self) -> u16 {
+        if let Some(bytes) = self.block_bytes() {
+            return bytes as u16 * 8 / 16;
+        }
+
+        let bits_per_sample = match self {
+            ExtendedColorType::L1
+            | ExtendedColorType::La1
+            | ExtendedColorType::Rgb1
+            | ExtendedColorType::Rgba1 => 1,
+            ExtendedColorType::L2
+            | ExtendedColorType::La2
+            | ExtendedColorType::Rgb2
+            | ExtendedColorType::Rgba2 => 2,
+            ExtendedColorType::L4
+            | ExtendedColorType::La4
+            | ExtendedColorType::Rgb4
+            | ExtendedColorType::Rgba4 => 4,
+            ExtendedColorType::L8
+            | ExtendedColorType::La8
+            | ExtendedColorType::Rgb8
+            | ExtendedColorType::Rgba8
+            | ExtendedColorType::Bgr8
+            | ExtendedColorType::Bgra8 => 8,
+            ExtendedColorType::L16
+            | ExtendedColorType::La16
+            | ExtendedColorType::Rgb16
+            | ExtendedColorType::Rgba16 => 16,
+            ExtendedColorType::Rgb32F | ExtendedColorType::Rgba32F => 32,
+            ExtendedColorType::Unknown {
+                bits_per_sample, ..
+            } => bits_per_sample as u16,
+            ExtendedColorType::Bc1
+            | ExtendedColorType::Bc2
+            | ExtendedColorType::Bc3
+            | ExtendedColorType::Bc4
+            | ExtendedColorType::Bc5
+            | ExtendedColorType::Bc6h
+            | ExtendedColorType::Bc7 => 0,
+        };
+
+        bits_per_sample * self.channel_count() as u16
+    }
+
+    /// Returns the corresponding `ColorType`, if there is one.
+    pub const fn to_color_type(self) -> Option<ColorType> {
+        Some(match self {
+            ExtendedColorType::L8 => ColorType::L8,
+            ExtendedColorType::La8 => ColorType::La8,
+            ExtendedColorType::Rgb8 => ColorType::Rgb8,
+            ExtendedColorType::Rgba8 => ColorType::Rgba8,
+            ExtendedColorType::L16 => ColorType::L16,
+            ExtendedColorType::La16 => ColorType::La16,
+            ExtendedColorType::Rgb16 => ColorType::Rgb16,
+            ExtendedColorType::Rgba16 => ColorType::Rgba16,
+            ExtendedColorType::Bgr8 => ColorType::Bgr8,
+            ExtendedColorType::Bgra8 => ColorType::Bgra8,
+            ExtendedColorType::Rgb32F => ColorType::Rgb32F,
+            ExtendedColorType::Rgba32F => ColorType::Rgba32F,
+            _ => return None,
+        })
+    }
+
+    /// Returns the number of bytes in a row of `width` pixels of this color type.
+    ///
+    /// Pixels are packed without padding, starting with the most significant bits of each byte,
+    /// but every row starts at a byte boundary. The last byte of a row is therefore padded when
+    /// its bits are not a multiple of 8.
+    ///
+    /// For block compressed color types this is the size of a row of blocks, which covers as many
+    /// rows of pixels as a block is high. Partial blocks at the right edge are stored whole.
+    pub const fn row_bytes(self, width: u32) -> u64 {
+        match self.block_bytes() {
+            Some(bytes) => self.blocks(width, 0).0 as u64 * bytes as u64,
+            None => (width as u64 * self.bits_per_pixel() as u64).div_ceil(8),
+        }
+    }
+
+    /// Returns the number of bytes of an image with the given dimensions, in this color type.
+    ///
+    /// Rows are padded to whole bytes as documented for `row_bytes`. Images of block compressed
+    /// color types consist of whole blocks. Returns `None` if the size does not fit into a `u64`.
+    pub const fn buffer_size(self, width: u32, height: u32) -> Option<u64> {
+        let rows = match self.block_bytes() {
+            Some(_) => self.blocks(width, height).1,
+            None => height,
+        };
+        self.row_bytes(width).checked_mul(rows as u64)
+    }
+
+    /// Returns whether pixels are stored in compressed blocks, such as those of BC1 to BC7.
+    ///
+    /// Such data can be uploaded to graphics APIs as it is, but is not a `ColorType`.
+    pub const fn is_block_compressed(self) -> bool {
+        self.block_bytes().is_some()
+    }
+
+    /// Returns the width and height of a compressed block in pixels, or `None` if this color type
+    /// is not block compressed.
+    ///
+    /// Every BCn format uses blocks of 4 by 4 pixels.
+    pub const fn block_dimensions(self) -> Option<(u32, u32)> {
+        match self.block_bytes() {
+            Some(_) => Some((4, 4)),
+            None => None,
+        }
+    }
+
+    /// Returns the number of bytes of a compressed block, or `None` if this color type is not
+    /// block compressed.
+    pub const fn block_bytes(self) -> Option<u8> {
+        match self {
+            ExtendedColorType::Bc1 | ExtendedColorType::Bc4 => Some(8),
+            ExtendedColorType::Bc2
+            | ExtendedColorType::Bc3
+            | ExtendedColorType::Bc5
+            | ExtendedColorType::Bc6h
+            | ExtendedColorType::Bc7 => Some(16),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of blocks covering an image horizontally and vertically.
+    ///
+    /// Partial blocks at the edges are counted, color types which are not block compressed have
+    /// blocks of a single pixel.
+    pub const fn blocks(self, width: u32, height: u32) -> (u32, u32) {
+        match self.block_dimensions() {
+            Some((block_width, block_height)) => {
+                (width.div_ceil(block_width), height.div_ceil(block_height))
+            }
+            None => (width, height),
         }
     }
 }
+
+/// All variants of `ExtendedColorType` with uncompressed samples, which is all except for
+/// `Unknown` and those of `BLOCK_COMPRESSED`.
+pub(crate) const KNOWN_EXTENDED: [ExtendedColorType; 24] = [
+    ExtendedColorType::L1,
+    ExtendedColorType::La1,
+    ExtendedColorType::Rgb1,
+    ExtendedColorType::Rgba1,
+    ExtendedColorType::L2,
+    ExtendedColorType::La2,
+    ExtendedColorType::Rgb2,
+    ExtendedColorType::Rgba2,
+    ExtendedColorType::L4,
+    ExtendedColorType::La4,
+    ExtendedColorType::Rgb4,
+    ExtendedColorType::Rgba4,
+    ExtendedColorType::L8,
+    ExtendedColorType::La8,
+    ExtendedColorType::Rgb8,
+    ExtendedColorType::Rgba8,
+    ExtendedColorType::L16,
+    ExtendedColorType::La16,
+    ExtendedColorType::Rgb16,
+    ExtendedColorType::Rgba16,
+    ExtendedColorType::Bgr8,
+    ExtendedColorType::Bgra8,
+    ExtendedColorType::Rgb32F,
+    ExtendedColorType::Rgba32F,
+];
+
+/// All block compressed variants of `ExtendedColorType`.
+#[cfg(any(test, feature = "arbitrary"))]
+pub(crate) const BLOCK_COMPRESSED: [ExtendedColorType; 7] = [
+    ExtendedColorType::Bc1,
+    ExtendedColorType::Bc2,
+    ExtendedColorType::Bc3,
+    ExtendedColorType::Bc4,
+    ExtendedColorType::Bc5,
+    ExtendedColorType::Bc6h,
+    ExtendedColorType::Bc7,
+];
+
 impl From<ColorType> for ExtendedColorType {
     fn from(c: ColorType) -> Self {
         match c {
@@ -147,7 +550,203 @@ impl From<ColorType> for ExtendedColorType {
             ColorType::Rgba16 => ExtendedColorType::Rgba16,
             ColorType::Bgr8 => ExtendedColorType::Bgr8,
             ColorType::Bgra8 => ExtendedColorType::Bgra8,
-            ColorType::__Nonexhaustive(marker) => match marker._private {},
+            ColorType::Rgb32F => ExtendedColorType::Rgb32F,
+            ColorType::Rgba32F => ExtendedColorType::Rgba32F,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for ColorType {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        const ALL: [ColorType; 12] = [
+            ColorType::L8,
+            ColorType::La8,
+            ColorType::Rgb8,
+            ColorType::Rgba8,
+            ColorType::L16,
+            ColorType::La16,
+            ColorType::Rgb16,
+            ColorType::Rgba16,
+            ColorType::Bgr8,
+            ColorType::Bgra8,
+            ColorType::Rgb32F,
+            ColorType::Rgba32F,
+        ];
+        u.choose(&ALL).copied()
+    }
+
+    fn size_hint(_: usize) -> (usize, Option<usize>) {
+        (1, Some(4))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for ExtendedColorType {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // One additional choice for the `Unknown` variant.
+        let known = KNOWN_EXTENDED.len() + BLOCK_COMPRESSED.len();
+        let index = u.choose_index(known + 1)?;
+        let color = KNOWN_EXTENDED.get(index).or_else(|| {
+            index
+                .checked_sub(KNOWN_EXTENDED.len())
+                .and_then(|index| BLOCK_COMPRESSED.get(index))
+        });
+        match color {
+            Some(&color) => Ok(color),
+            None => Ok(ExtendedColorType::Unknown {
+                bits_per_sample: u.arbitrary()?,
+                channels: u.arbitrary()?,
+            }),
+        }
+    }
+
+    fn size_hint(_: usize) -> (usize, Option<usize>) {
+        (1, Some(6))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_in_const_context() {
+        const BUFFER: [u8; 4 * 2 * ColorType::Rgba16.bytes_per_pixel() as usize] = [0; 64];
+        const CHANNELS: u8 = ExtendedColorType::La4.channel_count();
+
+        assert_eq!(BUFFER.len(), 64);
+        assert_eq!(CHANNELS, 2);
+        assert_eq!(ColorType::Bgr8.bits_per_pixel(), 24);
+    }
+
+    #[test]
+    fn describes_layout() {
+        let bgra = ColorType::Bgra8.layout();
+        assert_eq!(
+            bgra.channels(),
+            [Channel::Blue, Channel::Green, Channel::Red, Channel::Alpha]
+        );
+        assert_eq!(bgra.byte_offset(Channel::Red), Some(2));
+        assert_eq!(bgra.position(Channel::Luma), None);
+        assert!(!bgra.is_planar());
+
+        let la = ColorType::La16.layout();
+        assert_eq!(la.sample_format(), SampleFormat::U16);
+        assert_eq!(la.byte_offset(Channel::Alpha), Some(2));
+    }
+
+    #[test]
+    fn packed_rows_are_padded_to_bytes() {
+        assert_eq!(ExtendedColorType::L1.row_bytes(9), 2);
+        assert_eq!(ExtendedColorType::Rgb4.row_bytes(3), 5);
+        let palette = ExtendedColorType::Unknown {
+            bits_per_sample: 2,
+            channels: 1,
+        };
+        assert_eq!(palette.buffer_size(5, 3), Some(6));
+
+        let exotic = ExtendedColorType::Unknown {
+            bits_per_sample: 10,
+            channels: 3,
+        };
+        assert_eq!(exotic.channel_count(), 3);
+        assert_eq!(exotic.bits_per_pixel(), 30);
+        assert_eq!(exotic.row_bytes(4), 15);
+        assert_eq!(ExtendedColorType::Rgba16.buffer_size(2, 2), Some(32));
+        assert_eq!(
+            ExtendedColorType::La16.buffer_size(u32::MAX, u32::MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn sizes_compressed_blocks() {
+        let bc1 = ExtendedColorType::Bc1;
+        assert!(bc1.is_block_compressed());
+        assert_eq!(bc1.bits_per_pixel(), 4);
+        assert_eq!(bc1.blocks(5, 4), (2, 1));
+        assert_eq!(bc1.row_bytes(5), 16);
+        assert_eq!(bc1.buffer_size(5, 5), Some(32));
+        assert_eq!(bc1.buffer_size(0, 0), Some(0));
+
+        let bc7 = ExtendedColorType::Bc7;
+        assert_eq!(bc7.bits_per_pixel(), 8);
+        assert_eq!(bc7.channel_count(), 4);
+        assert_eq!(bc7.buffer_size(16, 12), Some(16 * 12));
+        assert_eq!(bc7.to_color_type(), None);
+        assert_eq!(bc7.buffer_size(u32::MAX, u32::MAX), None);
+
+        assert!(BLOCK_COMPRESSED
+            .iter()
+            .all(|color| color.block_dimensions() == Some((4, 4))));
+        assert!(!ExtendedColorType::Rgba8.is_block_compressed());
+        assert_eq!(ExtendedColorType::Rgba8.blocks(3, 2), (3, 2));
+    }
+
+    #[test]
+    fn from_parts_inverts_counts() {
+        for &color in &KNOWN_EXTENDED {
+            let channels = color.channel_count();
+            let bits = (color.bits_per_pixel() / u16::from(channels)) as u8;
+            let expected = match color {
+                ExtendedColorType::Bgr8 => ExtendedColorType::Rgb8,
+                ExtendedColorType::Bgra8 => ExtendedColorType::Rgba8,
+                other => other,
+            };
+            assert_eq!(
+                ExtendedColorType::from_parts(channels, bits),
+                Some(expected)
+            );
         }
+
+        let exotic = ExtendedColorType::from_parts(3, 10);
+        let expected = ExtendedColorType::Unknown {
+            bits_per_sample: 10,
+            channels: 3,
+        };
+        assert_eq!(exotic, Some(expected));
+        assert_eq!(ExtendedColorType::from_parts(0, 8), None);
+        assert_eq!(ExtendedColorType::from_parts(1, 0), None);
+    }
+
+    #[test]
+    fn channel_count_matches_extended() {
+        for &color in &[
+            ColorType::L8,
+            ColorType::La8,
+            ColorType::Rgb8,
+            ColorType::Rgba8,
+            ColorType::L16,
+            ColorType::La16,
+            ColorType::Rgb16,
+            ColorType::Rgba16,
+            ColorType::Bgr8,
+            ColorType::Bgra8,
+            ColorType::Rgb32F,
+            ColorType::Rgba32F,
+        ] {
+            let extended: ExtendedColorType = color.into();
+            assert_eq!(extended.to_color_type(), Some(color));
+            assert_eq!(color.channel_count(), extended.channel_count());
+            assert_eq!(color.bits_per_pixel(), extended.bits_per_pixel());
+
+            let layout = color.layout();
+            assert_eq!(layout.channels().len(), usize::from(color.channel_count()));
+            assert_eq!(
+                u16::from(layout.bits_per_channel()) * u16::from(color.channel_count()),
+                color.bits_per_pixel()
+            );
+
+            let opaque = color.without_alpha();
+            assert!(!opaque.has_alpha());
+            assert_eq!(layout.position(Channel::Alpha).is_some(), color.has_alpha());
+            assert_eq!(opaque.layout().sample_format(), layout.sample_format());
+            assert_eq!(extended.has_alpha(), color.has_alpha());
+        }
+
+        assert!(ExtendedColorType::La4.has_alpha());
+        assert!(ExtendedColorType::Bc1.has_alpha());
+        assert!(!ExtendedColorType::Bc5.has_alpha());
     }
 }
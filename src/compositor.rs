@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+
+use crate::convert::convert_color;
+use crate::{BlendMode, ColorType, DisposalMethod, Frame, ImageResult, PixelBuffer, Rect};
+
+/// Composites the partial frames of an animation into full canvases.
+///
+/// Formats such as GIF and APNG store frames which only cover a part of the canvas and have to be
+/// combined with the frames before them, according to their disposal method and blend mode. The
+/// compositor keeps the canvas as 8-bit RGBA with straight alpha, frames of other color types are
+/// converted. Parts of frames outside of the canvas are cut off.
+pub struct FrameCompositor {
+    canvas: PixelBuffer,
+    /// The disposal of the frame drawn last, which is applied before the next one is drawn.
+    pending: Option<PendingDisposal>,
+}
+
+struct PendingDisposal {
+    rect: Rect,
+    method: DisposalMethod,
+    /// The pixels under the frame before it was drawn, for `DisposalMethod::Previous`.
+    saved: Vec<u8>,
+}
+
+impl FrameCompositor {
+    /// Create a compositor for a canvas of the given size, which starts transparent black.
+    pub fn new(width: u32, height: u32) -> ImageResult<Self> {
+        Ok(FrameCompositor {
+            canvas: PixelBuffer::new(width, height, ColorType::Rgba8)?,
+            pending: None,
+        })
+    }
+
+    /// Draw the next frame, returning the canvas as it is displayed with the frame.
+    pub fn composite(&mut self, frame: &Frame) -> &PixelBuffer {
+        self.dispose();
+
+        let buffer = frame.buffer();
+        let (frame_width, frame_height) = buffer.dimensions();
+        let placed = Rect::new(frame.left(), frame.top(), frame_width, frame_height);
+        let visible = Rect::from_dimensions(self.canvas.dimensions())
+            .intersection(&placed)
+            .unwrap_or_default();
+
+        let pixels = match buffer.color_type() {
+            ColorType::Rgba8 => Cow::Borrowed(buffer.as_bytes()),
+            color => {
+                let mut rgba = vec![0; frame_width as usize * frame_height as usize * 4];
+                convert_color(buffer.as_bytes(), color, &mut rgba, ColorType::Rgba8);
+                Cow::Owned(rgba)
+            }
+        };
+
+        let saved = match frame.disposal() {
+            DisposalMethod::Previous => self.rows(visible).concat(),
+            _ => Vec::new(),
+        };
+
+        let row_len = visible.width as usize * 4;
+        for (y, target) in (visible.y..).zip(self.rows_mut(visible)) {
+            let start = ((y - placed.y) as usize * frame_width as usize
+                + (visible.x - placed.x) as usize)
+                * 4;
+            let source = &pixels[start..][..row_len];
+            match frame.blend() {
+                BlendMode::Over => blend_over(source, target),
+                _ => target.copy_from_slice(source),
+            }
+        }
+
+        self.pending = Some(PendingDisposal {
+            rect: visible,
+            method: frame.disposal(),
+            saved,
+        });
+        &self.canvas
+    }
+
+    /// The canvas as it was last displayed.
+    pub fn canvas(&self) -> &PixelBuffer {
+        &self.canvas
+    }
+
+    /// Returns the canvas, discarding the state of the animation.
+    pub fn into_canvas(self) -> PixelBuffer {
+        self.canvas
+    }
+
+    /// Clear the canvas to restart the animation from its first frame.
+    pub fn reset(&mut self) {
+        self.canvas
+            .as_bytes_mut()
+            .iter_mut()
+            .for_each(|byte| *byte = 0);
+        self.pending = None;
+    }
+
+    /// Apply the disposal method of the frame drawn last.
+    fn dispose(&mut self) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let row_len = pending.rect.width as usize * 4;
+        let rows = self.rows_mut(pending.rect);
+        match pending.method {
+            DisposalMethod::Background => rows.for_each(|row| row.iter_mut().for_each(|b| *b = 0)),
+            DisposalMethod::Previous => rows
+                .zip(pending.saved.chunks_exact(row_len.max(1)))
+                .for_each(|(row, saved)| row.copy_from_slice(saved)),
+            _ => {}
+        }
+    }
+
+    /// The parts of the rows of the canvas covered by a rectangle within it.
+    fn rows(&self, rect: Rect) -> Vec<&[u8]> {
+        let canvas_row = self.canvas.width() as usize * 4;
+        let (start, len) = (rect.x as usize * 4, rect.width as usize * 4);
+        self.canvas
+            .as_bytes()
+            .chunks_exact(canvas_row.max(1))
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+            .map(|row| &row[start..][..len])
+            .collect()
+    }
+
+    /// Same as `rows`, but mutable.
+    fn rows_mut(&mut self, rect: Rect) -> impl Iterator<Item = &mut [u8]> {
+        let canvas_row = self.canvas.width() as usize * 4;
+        let (start, len) = (rect.x as usize * 4, rect.width as usize * 4);
+        self.canvas
+            .as_bytes_mut()
+            .chunks_exact_mut(canvas_row.max(1))
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+            .map(move |row| &mut row[start..][..len])
+    }
+}
+
+/// Composite straight alpha RGBA pixels over others.
+fn blend_over(source: &[u8], target: &mut [u8]) {
+    for (src, dst) in source.chunks_exact(4).zip(target.chunks_exact_mut(4)) {
+        let (src_alpha, dst_alpha) = (u32::from(src[3]), u32::from(dst[3]));
+        if src_alpha == 255 || dst_alpha == 0 {
+            dst.copy_from_slice(src);
+            continue;
+        }
+        if src_alpha == 0 {
+            continue;
+        }
+
+        // The weights of both pixels, scaled by 255 * 255.
+        let src_weight = src_alpha * 255;
+        let dst_weight = dst_alpha * (255 - src_alpha);
+        let alpha = src_weight + dst_weight;
+        for channel in 0..3 {
+            let sum = u32::from(src[channel]) * src_weight + u32::from(dst[channel]) * dst_weight;
+            dst[channel] = ((sum + alpha / 2) / alpha) as u8;
+        }
+        dst[3] = ((alpha + 127) / 255) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, color: ColorType, data: Vec<u8>) -> Frame {
+        Frame::new(PixelBuffer::from_vec(width, height, color, data).unwrap())
+    }
+
+    #[test]
+    fn applies_disposal() {
+        let mut compositor = FrameCompositor::new(2, 1).unwrap();
+        let red = frame(2, 1, ColorType::Rgb8, vec![255, 0, 0, 255, 0, 0]);
+        compositor.composite(&red);
+
+        let green = frame(1, 1, ColorType::Rgba8, vec![0, 255, 0, 255])
+            .with_offset(1, 0)
+            .with_disposal(DisposalMethod::Previous);
+        let canvas = compositor.composite(&green);
+        assert_eq!(canvas.as_bytes(), [255, 0, 0, 255, 0, 255, 0, 255]);
+
+        let blue = frame(1, 1, ColorType::Rgba8, vec![0, 0, 255, 255])
+            .with_disposal(DisposalMethod::Background);
+        let canvas = compositor.composite(&blue);
+        assert_eq!(canvas.as_bytes(), [0, 0, 255, 255, 255, 0, 0, 255]);
+
+        // Cut off at the edge of the canvas.
+        let gray = frame(2, 2, ColorType::L8, vec![9; 4]).with_offset(1, 0);
+        let canvas = compositor.composite(&gray);
+        assert_eq!(canvas.as_bytes(), [0, 0, 0, 0, 9, 9, 9, 255]);
+    }
+
+    #[test]
+    fn blends_over_the_canvas() {
+        let mut compositor = FrameCompositor::new(3, 1).unwrap();
+        compositor.composite(&frame(3, 1, ColorType::Rgba8, vec![200; 12]));
+
+        let pixels = vec![0, 0, 0, 0, 100, 0, 0, 128, 0, 50, 0, 255];
+        let over = frame(3, 1, ColorType::Rgba8, pixels).with_blend(BlendMode::Over);
+        let canvas = compositor.composite(&over);
+        assert_eq!(
+            canvas.as_bytes(),
+            [200, 200, 200, 200, 144, 88, 88, 228, 0, 50, 0, 255]
+        );
+
+        compositor.reset();
+        assert!(compositor.canvas().as_bytes().iter().all(|&b| b == 0));
+    }
+}
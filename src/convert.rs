@@ -0,0 +1,1039 @@
+//! Conversions between the color types of raw pixel buffers.
+//!
+//! All functions operate on the byte layout produced by `ImageDecoder::read_image`: pixels of
+//! `color.bytes_per_pixel()` bytes each, with 16-bit and float samples in native endian. Integer
+//! samples span their full range while float samples have a nominal range of `0.0` to `1.0`, they
+//! are clamped to that range when converted to integers.
+
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use crate::chunked::{chunk_len, ChunkConversion, ChunkedReader};
+use crate::{Cicp, DecodeFidelity, HdrMetadata, ImageError, ImageResult, Limits, RowOrder};
+use crate::{ColorType, ExtendedColorType, ImageDecoder};
+
+/// The weights of red, green and blue in the luma of a pixel, as defined by Rec. 709.
+const LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Convert the pixels of `src` from one color type to another, writing them to `dst`.
+///
+/// Color is reduced to luma with the coefficients of Rec. 709, luma is replicated to all color
+/// channels. A missing alpha channel is taken to be opaque, a removed one is discarded.
+///
+/// # Panics
+///
+/// Panics if the buffers do not hold whole pixels, or not the same number of them.
+pub fn convert_color(src: &[u8], from: ColorType, dst: &mut [u8], to: ColorType) {
+    let from_bpp = usize::from(from.bytes_per_pixel());
+    let to_bpp = usize::from(to.bytes_per_pixel());
+    assert!(
+        src.len().is_multiple_of(from_bpp) && src.len() / from_bpp * to_bpp == dst.len(),
+        "buffers of {} and {} bytes do not hold the same pixels",
+        src.len(),
+        dst.len(),
+    );
+
+    let (from_format, from_channels) = layout(from);
+    let (to_format, to_channels) = layout(to);
+    if from_channels == to_channels {
+        convert_samples(src, from_format, dst, to_format);
+        return;
+    }
+
+    for (source, target) in src.chunks_exact(from_bpp).zip(dst.chunks_exact_mut(to_bpp)) {
+        write_pixel(read_pixel_for(source, from, to), target, to);
+    }
+}
+
+/// How to distribute the rounding error when reducing the depth of samples.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DitherMode {
+    /// Round every sample to the nearest value.
+    #[default]
+    None,
+    /// Add the threshold of a 4x4 Bayer matrix before rounding.
+    ///
+    /// The pattern does not depend on neighbouring pixels, so that rows can be converted in any
+    /// order.
+    Ordered,
+    /// Diffuse the rounding error of each sample to its neighbours to the right and below.
+    FloydSteinberg,
+}
+
+/// Same as `convert_color`, but dithers samples whose depth is reduced.
+///
+/// The buffers hold rows of `width` pixels. Dithering applies to conversions from 16-bit or float
+/// samples to 8 bits and from float samples to 16 bits, all other conversions are the same as
+/// with `convert_color`. Every channel is dithered independently, including alpha.
+///
+/// # Panics
+///
+/// Panics if the buffers do not hold whole rows, or not the same number of them.
+pub fn convert_color_dithered(
+    src: &[u8],
+    from: ColorType,
+    dst: &mut [u8],
+    to: ColorType,
+    width: u32,
+    dither: DitherMode,
+) {
+    Ditherer::new(dither, width, to).convert_rows(src, from, dst, to);
+}
+
+/// The 4x4 Bayer matrix, with thresholds from 0 to 15.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The dithering state of a sequence of rows.
+struct Ditherer {
+    mode: DitherMode,
+    width: usize,
+    /// The next row to convert.
+    row: usize,
+    /// The error diffused into the current row, and into the one after it.
+    errors: Vec<f32>,
+    next_errors: Vec<f32>,
+}
+
+impl Ditherer {
+    fn new(mode: DitherMode, width: u32, to: ColorType) -> Self {
+        let len = match mode {
+            DitherMode::FloydSteinberg => width as usize * usize::from(to.channel_count()),
+            _ => 0,
+        };
+        Ditherer {
+            mode,
+            width: width as usize,
+            row: 0,
+            errors: vec![0.0; len],
+            next_errors: vec![0.0; len],
+        }
+    }
+
+    /// Convert the next rows of the image.
+    fn convert_rows(&mut self, src: &[u8], from: ColorType, dst: &mut [u8], to: ColorType) {
+        let (from_format, _) = layout(from);
+        let (to_format, to_channels) = layout(to);
+        let levels = match (from_format, to_format) {
+            _ if self.mode == DitherMode::None => return convert_color(src, from, dst, to),
+            (SampleFormat::U16, SampleFormat::U8) | (SampleFormat::F32, SampleFormat::U8) => 255.0,
+            (SampleFormat::F32, SampleFormat::U16) => 65535.0,
+            _ => return convert_color(src, from, dst, to),
+        };
+
+        let src_row = self.width * usize::from(from.bytes_per_pixel());
+        let dst_row = self.width * usize::from(to.bytes_per_pixel());
+        let rows = src.len().checked_div(src_row).unwrap_or_default();
+        assert!(
+            src.len() == rows * src_row && dst.len() == rows * dst_row,
+            "buffers of {} and {} bytes do not hold the same rows",
+            src.len(),
+            dst.len(),
+        );
+        if src_row == 0 {
+            return;
+        }
+
+        let channels = to_channels.len();
+        let from_bpp = usize::from(from.bytes_per_pixel());
+        let to_bpp = usize::from(to.bytes_per_pixel());
+        for (source, target) in src.chunks_exact(src_row).zip(dst.chunks_exact_mut(dst_row)) {
+            let pixels = source
+                .chunks_exact(from_bpp)
+                .zip(target.chunks_exact_mut(to_bpp));
+            for (x, (source, target)) in pixels.enumerate() {
+                let rgba = read_pixel_for(source, from, to);
+                let samples = target.chunks_exact_mut(to_format.bytes()).zip(to_channels);
+                for (index, (sample, &channel)) in samples.enumerate() {
+                    let value = self.dither(rgba[channel], x, x * channels + index, levels);
+                    write_sample(value, sample, to_format);
+                }
+            }
+
+            self.row += 1;
+            std::mem::swap(&mut self.errors, &mut self.next_errors);
+            self.next_errors.iter_mut().for_each(|error| *error = 0.0);
+        }
+    }
+
+    /// Adjust a sample at column `x` of the current row before it is rounded.
+    fn dither(&mut self, value: f32, x: usize, index: usize, levels: f32) -> f32 {
+        match self.mode {
+            DitherMode::Ordered => {
+                let threshold = f32::from(BAYER[self.row % 4][x % 4]);
+                value + ((threshold + 0.5) / 16.0 - 0.5) / levels
+            }
+            DitherMode::FloydSteinberg => {
+                let value = (value + self.errors[index]).clamp(0.0, 1.0);
+                let error = value - (value * levels).round() / levels;
+                let channels = self.errors.len() / self.width;
+                if x + 1 < self.width {
+                    self.errors[index + channels] += error * 7.0 / 16.0;
+                    self.next_errors[index + channels] += error / 16.0;
+                }
+                if x > 0 {
+                    self.next_errors[index - channels] += error * 3.0 / 16.0;
+                }
+                self.next_errors[index] += error * 5.0 / 16.0;
+                value
+            }
+            _ => value,
+        }
+    }
+}
+
+/// The encoding of the samples of a color type.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SampleFormat {
+    /// Unsigned 8-bit integers.
+    U8,
+    /// Unsigned 16-bit integers in native endian.
+    U16,
+    /// 32-bit floats in native endian, with a nominal range of `0.0` to `1.0`.
+    F32,
+}
+
+impl SampleFormat {
+    /// Returns the format of the samples of a color type.
+    pub fn of(color: ColorType) -> Self {
+        layout(color).0
+    }
+
+    /// Returns the number of bytes of a sample.
+    pub const fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::U16 => 2,
+            SampleFormat::F32 => 4,
+        }
+    }
+}
+
+/// Scale an 8-bit sample to 16 bits, mapping the maximum to the maximum.
+pub const fn u8_to_u16(value: u8) -> u16 {
+    value as u16 * 257
+}
+
+/// Scale a 16-bit sample to 8 bits, rounding to the nearest value.
+pub const fn u16_to_u8(value: u16) -> u8 {
+    ((value as u32 + 128) / 257) as u8
+}
+
+/// Normalize an 8-bit sample.
+pub fn u8_to_f32(value: u8) -> f32 {
+    f32::from(value) / 255.0
+}
+
+/// Normalize a 16-bit sample.
+pub fn u16_to_f32(value: u16) -> f32 {
+    f32::from(value) / 65535.0
+}
+
+/// Scale a float sample to 8 bits, clamping it to the nominal range and mapping NaN to zero.
+pub fn f32_to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Scale a float sample to 16 bits, clamping it to the nominal range and mapping NaN to zero.
+pub fn f32_to_u16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+/// Convert a buffer of samples from one format to another, writing them to `dst`.
+///
+/// Integer samples are scaled to the full range of the target, 16-bit samples are reduced to 8
+/// bits with rounding and float samples are clamped when converted to integers, see
+/// `u16_to_u8` and `f32_to_u8`.
+///
+/// # Panics
+///
+/// Panics if the buffers do not hold whole samples, or not the same number of them.
+pub fn convert_samples(src: &[u8], from: SampleFormat, dst: &mut [u8], to: SampleFormat) {
+    assert!(
+        src.len().is_multiple_of(from.bytes())
+            && src.len() / from.bytes() * to.bytes() == dst.len(),
+        "buffers of {} and {} bytes do not hold the same samples",
+        src.len(),
+        dst.len(),
+    );
+
+    if from == to {
+        dst.copy_from_slice(src);
+        return;
+    }
+
+    let samples = src.chunks_exact(from.bytes());
+    for (source, target) in samples.zip(dst.chunks_exact_mut(to.bytes())) {
+        match (from, to) {
+            (SampleFormat::U8, SampleFormat::U16) => {
+                target.copy_from_slice(&u8_to_u16(source[0]).to_ne_bytes())
+            }
+            (SampleFormat::U16, SampleFormat::U8) => target[0] = u16_to_u8(read_u16(source)),
+            _ => write_sample(read_sample(source, from), target, to),
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_ne_bytes([bytes[0], bytes[1]])
+}
+
+/// Read a sample as a normalized value.
+pub(crate) fn read_sample(bytes: &[u8], format: SampleFormat) -> f32 {
+    match format {
+        SampleFormat::U8 => u8_to_f32(bytes[0]),
+        SampleFormat::U16 => u16_to_f32(read_u16(bytes)),
+        SampleFormat::F32 => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+/// Write a normalized value as a sample.
+pub(crate) fn write_sample(value: f32, bytes: &mut [u8], format: SampleFormat) {
+    match format {
+        SampleFormat::U8 => bytes[0] = f32_to_u8(value),
+        SampleFormat::U16 => bytes.copy_from_slice(&f32_to_u16(value).to_ne_bytes()),
+        SampleFormat::F32 => bytes.copy_from_slice(&value.to_ne_bytes()),
+    }
+}
+
+/// The encoding of the samples and the position of each stored channel in an RGBA pixel.
+fn layout(color: ColorType) -> (SampleFormat, &'static [usize]) {
+    match color {
+        ColorType::L8 => (SampleFormat::U8, &[0]),
+        ColorType::La8 => (SampleFormat::U8, &[0, 3]),
+        ColorType::Rgb8 => (SampleFormat::U8, &[0, 1, 2]),
+        ColorType::Rgba8 => (SampleFormat::U8, &[0, 1, 2, 3]),
+        ColorType::Bgr8 => (SampleFormat::U8, &[2, 1, 0]),
+        ColorType::Bgra8 => (SampleFormat::U8, &[2, 1, 0, 3]),
+        ColorType::L16 => (SampleFormat::U16, &[0]),
+        ColorType::La16 => (SampleFormat::U16, &[0, 3]),
+        ColorType::Rgb16 => (SampleFormat::U16, &[0, 1, 2]),
+        ColorType::Rgba16 => (SampleFormat::U16, &[0, 1, 2, 3]),
+        ColorType::Rgb32F => (SampleFormat::F32, &[0, 1, 2]),
+        ColorType::Rgba32F => (SampleFormat::F32, &[0, 1, 2, 3]),
+    }
+}
+
+/// Read a pixel as normalized RGBA samples.
+fn read_pixel(pixel: &[u8], color: ColorType) -> [f32; 4] {
+    let (format, channels) = layout(color);
+    let mut rgba = [0.0, 0.0, 0.0, 1.0];
+    for (sample, &channel) in pixel.chunks_exact(format.bytes()).zip(channels) {
+        rgba[channel] = read_sample(sample, format);
+    }
+
+    if channels.len() < 3 {
+        rgba[1] = rgba[0];
+        rgba[2] = rgba[0];
+    }
+    rgba
+}
+
+/// Read a pixel as normalized RGBA samples, computing luma if the target has no color.
+fn read_pixel_for(pixel: &[u8], from: ColorType, to: ColorType) -> [f32; 4] {
+    let mut rgba = read_pixel(pixel, from);
+    if from.channel_count() >= 3 && to.channel_count() < 3 {
+        rgba[0] = LUMA_WEIGHTS
+            .iter()
+            .zip(&rgba[..3])
+            .map(|(weight, sample)| weight * sample)
+            .sum();
+    }
+    rgba
+}
+
+/// Write normalized RGBA samples as a pixel, taking luma from the red channel.
+fn write_pixel(rgba: [f32; 4], pixel: &mut [u8], color: ColorType) {
+    let (format, channels) = layout(color);
+    for (sample, &channel) in pixel.chunks_exact_mut(format.bytes()).zip(channels) {
+        write_sample(rgba[channel], sample, format);
+    }
+}
+
+/// Whether the color samples of an image are multiplied by its alpha.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlphaMode {
+    /// Color samples are independent of alpha, as stored by most file formats.
+    #[default]
+    Straight,
+    /// Color samples have been multiplied by alpha, as preferred for compositing.
+    Premultiplied,
+}
+
+/// Multiply the color samples of a buffer by their alpha, in place.
+///
+/// Integer samples are rounded to the nearest value. Color types without alpha are left
+/// untouched.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels.
+pub fn premultiply_alpha(buf: &mut [u8], color: ColorType) {
+    map_with_alpha(
+        buf,
+        color,
+        |value, alpha| ((u32::from(value) * u32::from(alpha) + 127) / 255) as u8,
+        |value, alpha| ((u32::from(value) * u32::from(alpha) + 32767) / 65535) as u16,
+        |value, alpha| value * alpha,
+    );
+}
+
+/// Divide the color samples of a buffer by their alpha, in place.
+///
+/// This is the inverse of `premultiply_alpha`, up to the precision lost by the multiplication.
+/// Integer samples are rounded to the nearest value and clamped to the maximum, fully
+/// transparent pixels become black.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels.
+pub fn unpremultiply_alpha(buf: &mut [u8], color: ColorType) {
+    map_with_alpha(
+        buf,
+        color,
+        |value, alpha| match u32::from(alpha) {
+            0 => 0,
+            alpha => ((u32::from(value) * 255 + alpha / 2) / alpha).min(255) as u8,
+        },
+        |value, alpha| match u32::from(alpha) {
+            0 => 0,
+            alpha => ((u32::from(value) * 65535 + alpha / 2) / alpha).min(65535) as u16,
+        },
+        |value, alpha| if alpha == 0.0 { 0.0 } else { value / alpha },
+    );
+}
+
+/// Apply a function of each color sample and the alpha of its pixel, skipping color types
+/// without alpha.
+fn map_with_alpha(
+    buf: &mut [u8],
+    color: ColorType,
+    map_u8: impl Fn(u8, u8) -> u8,
+    map_u16: impl Fn(u16, u16) -> u16,
+    map_f32: impl Fn(f32, f32) -> f32,
+) {
+    let bpp = usize::from(color.bytes_per_pixel());
+    assert!(
+        buf.len().is_multiple_of(bpp),
+        "a buffer of {} bytes does not hold whole pixels",
+        buf.len(),
+    );
+
+    let (format, channels) = layout(color);
+    if channels.last() != Some(&3) {
+        return;
+    }
+
+    for pixel in buf.chunks_exact_mut(bpp) {
+        let (samples, alpha) = pixel.split_at_mut(bpp - format.bytes());
+        for sample in samples.chunks_exact_mut(format.bytes()) {
+            match format {
+                SampleFormat::U8 => sample[0] = map_u8(sample[0], alpha[0]),
+                SampleFormat::U16 => {
+                    let value = map_u16(read_u16(sample), read_u16(alpha));
+                    sample.copy_from_slice(&value.to_ne_bytes());
+                }
+                SampleFormat::F32 => {
+                    let value = map_f32(read_sample(sample, format), read_sample(alpha, format));
+                    sample.copy_from_slice(&value.to_ne_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Decode a sample with the sRGB transfer function into linear light.
+///
+/// Values outside of `0.0` to `1.0` are extended symmetrically around zero.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    let magnitude = value.abs();
+    let linear = if magnitude <= 0.04045 {
+        magnitude / 12.92
+    } else {
+        ((magnitude + 0.055) / 1.055).powf(2.4)
+    };
+    linear.copysign(value)
+}
+
+/// Encode a sample in linear light with the sRGB transfer function.
+///
+/// This is the inverse of `srgb_to_linear`.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    let magnitude = value.abs();
+    let encoded = if magnitude <= 0.0031308 {
+        magnitude * 12.92
+    } else {
+        1.055 * magnitude.powf(1.0 / 2.4) - 0.055
+    };
+    encoded.copysign(value)
+}
+
+/// Decode an 8-bit sRGB sample into linear light, using a lookup table.
+pub fn srgb_u8_to_linear(value: u8) -> f32 {
+    srgb_tables().to_linear[usize::from(value)]
+}
+
+/// Decode the color samples of a buffer from sRGB into linear light, in place.
+///
+/// Alpha samples are left untouched. Samples of 8 bits are converted with a lookup table, they
+/// lose precision in the dark tones which linear light can not represent with 8 bits.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels.
+pub fn srgb_to_linear_in_place(buf: &mut [u8], color: ColorType) {
+    let table = &srgb_tables().to_linear_u8;
+    map_color_samples(
+        buf,
+        color,
+        |value| table[usize::from(value)],
+        srgb_to_linear,
+    );
+}
+
+/// Encode the color samples of a buffer in linear light with the sRGB transfer function, in place.
+///
+/// Alpha samples are left untouched. This is the inverse of `srgb_to_linear_in_place`.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels.
+pub fn linear_to_srgb_in_place(buf: &mut [u8], color: ColorType) {
+    let table = &srgb_tables().to_srgb_u8;
+    map_color_samples(
+        buf,
+        color,
+        |value| table[usize::from(value)],
+        linear_to_srgb,
+    );
+}
+
+/// Lookup tables of the sRGB transfer function for 8-bit samples.
+struct SrgbTables {
+    to_linear: [f32; 256],
+    to_linear_u8: [u8; 256],
+    to_srgb_u8: [u8; 256],
+}
+
+fn srgb_tables() -> &'static SrgbTables {
+    static TABLES: OnceLock<SrgbTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = SrgbTables {
+            to_linear: [0.0; 256],
+            to_linear_u8: [0; 256],
+            to_srgb_u8: [0; 256],
+        };
+        for value in 0..256 {
+            let normalized = value as f32 / 255.0;
+            tables.to_linear[value] = srgb_to_linear(normalized);
+            tables.to_linear_u8[value] = (srgb_to_linear(normalized) * 255.0).round() as u8;
+            tables.to_srgb_u8[value] = (linear_to_srgb(normalized) * 255.0).round() as u8;
+        }
+        tables
+    })
+}
+
+/// Apply a function to every color sample of a buffer, skipping alpha.
+///
+/// 8-bit samples are mapped directly, all others as normalized values.
+fn map_color_samples(
+    buf: &mut [u8],
+    color: ColorType,
+    map_u8: impl Fn(u8) -> u8,
+    map: impl Fn(f32) -> f32,
+) {
+    let bpp = usize::from(color.bytes_per_pixel());
+    assert!(
+        buf.len().is_multiple_of(bpp),
+        "a buffer of {} bytes does not hold whole pixels",
+        buf.len(),
+    );
+
+    let (format, channels) = layout(color);
+    for pixel in buf.chunks_exact_mut(bpp) {
+        let samples = pixel.chunks_exact_mut(format.bytes()).zip(channels);
+        for (sample, _) in samples.filter(|(_, &channel)| channel != 3) {
+            match format {
+                SampleFormat::U8 => sample[0] = map_u8(sample[0]),
+                _ => write_sample(map(read_sample(sample, format)), sample, format),
+            }
+        }
+    }
+}
+
+/// A decoder adapter producing pixels of a requested color type.
+///
+/// The inner decoder is first asked to produce the color type itself, see
+/// `ImageDecoder::set_output_color`. If it can not, the pixels are converted with `convert_color`
+/// while reading.
+pub struct ColorConvertingDecoder<D> {
+    inner: D,
+    color: ColorType,
+    dither: DitherMode,
+    alpha_mode: Option<AlphaMode>,
+}
+
+/// The reader of a `ColorConvertingDecoder`.
+pub struct ColorConvertingReader<R> {
+    inner: ChunkedReader<R, Converter>,
+}
+
+struct Converter {
+    from: ColorType,
+    to: ColorType,
+    ditherer: Ditherer,
+    /// The change of the alpha mode after the conversion.
+    alpha_change: Option<AlphaMode>,
+}
+
+impl<'a, D: ImageDecoder<'a>> ColorConvertingDecoder<D> {
+    /// Wrap a decoder to produce pixels of the given color type.
+    ///
+    /// Returns an error if the decoder fails to change its output for a reason other than not
+    /// supporting the color type.
+    pub fn new(mut decoder: D, color: ColorType) -> ImageResult<Self> {
+        request_output_color(&mut decoder, color)?;
+        Ok(ColorConvertingDecoder {
+            inner: decoder,
+            color,
+            dither: DitherMode::None,
+            alpha_mode: None,
+        })
+    }
+
+    /// Dither samples whose depth is reduced by the conversion, see `convert_color_dithered`.
+    ///
+    /// With dithering the pixels are converted in groups of whole rows.
+    pub fn with_dither(mut self, dither: DitherMode) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Produce color samples in the given relation to alpha.
+    ///
+    /// Samples are premultiplied or unpremultiplied after the conversion if the alpha mode of the
+    /// inner decoder differs. This only affects color types with alpha, the alpha mode of the
+    /// inner decoder is kept otherwise.
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = Some(alpha_mode);
+        self
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns whether the pixels of the inner decoder have to be converted.
+    fn converts(&self) -> bool {
+        self.inner.color_type() != self.color || self.alpha_change().is_some()
+    }
+
+    /// The alpha mode to change to after converting the color type.
+    fn alpha_change(&self) -> Option<AlphaMode> {
+        let has_alpha = layout(self.color).1.last() == Some(&3);
+        self.alpha_mode
+            .filter(|&mode| has_alpha && mode != self.inner.alpha_mode())
+    }
+
+    /// The number of bytes of the inner decoder which are converted at once.
+    fn source_chunk(&self) -> u64 {
+        let unit = match self.dither {
+            DitherMode::None => u64::from(self.inner.color_type().bytes_per_pixel()),
+            _ => self.inner.total_bytes() / u64::from(self.inner.dimensions().1.max(1)),
+        };
+        chunk_len(self.inner.scanline_bytes(), unit)
+    }
+}
+
+/// Ask a decoder to produce a color type, accepting a refusal.
+fn request_output_color<'a, D: ImageDecoder<'a>>(
+    decoder: &mut D,
+    color: ColorType,
+) -> ImageResult<()> {
+    match decoder.set_output_color(color) {
+        Ok(()) | Err(ImageError::Unsupported(_)) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for ColorConvertingDecoder<D> {
+    type Reader = ColorConvertingReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        request_output_color(&mut self.inner, color)?;
+        self.color = color;
+        Ok(())
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        let inner = self.inner.decode_fidelity();
+        if !self.converts() {
+            return inner;
+        }
+
+        match DecodeFidelity::estimate(self.inner.color_type().into(), self.color) {
+            DecodeFidelity::LossyConversion => DecodeFidelity::LossyConversion,
+            _ if inner == DecodeFidelity::LossyConversion => inner,
+            _ => DecodeFidelity::LosslessConversion,
+        }
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_change()
+            .unwrap_or_else(|| self.inner.alpha_mode())
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        if !self.converts() {
+            return self.inner.scanline_bytes();
+        }
+
+        let from_bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        self.source_chunk() / from_bpp * u64::from(self.color.bytes_per_pixel())
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let converter = self.converts().then(|| Converter {
+            from: self.inner.color_type(),
+            to: self.color,
+            ditherer: Ditherer::new(self.dither, self.inner.dimensions().0, self.color),
+            alpha_change: self.alpha_change(),
+        });
+        let (total_bytes, chunk) = (self.inner.total_bytes(), self.source_chunk());
+        let inner = self.inner.into_reader()?;
+        Ok(ColorConvertingReader {
+            inner: ChunkedReader::new(inner, converter, total_bytes, chunk),
+        })
+    }
+}
+
+impl<R: Read> Read for ColorConvertingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl ChunkConversion for Converter {
+    fn output_len(&self, len: usize) -> usize {
+        len / usize::from(self.from.bytes_per_pixel()) * usize::from(self.to.bytes_per_pixel())
+    }
+
+    fn convert(&mut self, source: &[u8], output: &mut [u8]) {
+        self.ditherer
+            .convert_rows(source, self.from, output, self.to);
+        match self.alpha_change {
+            Some(AlphaMode::Premultiplied) => premultiply_alpha(output, self.to),
+            Some(_) => unpremultiply_alpha(output, self.to),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    fn convert(src: &[u8], from: ColorType, to: ColorType) -> Vec<u8> {
+        let pixels = src.len() / usize::from(from.bytes_per_pixel());
+        let mut dst = vec![0; pixels * usize::from(to.bytes_per_pixel())];
+        convert_color(src, from, &mut dst, to);
+        dst
+    }
+
+    #[test]
+    fn converts_channels_and_depth() {
+        let rgb = [255, 0, 0, 10, 20, 30];
+        assert_eq!(convert(&rgb, ColorType::Rgb8, ColorType::L8), [54, 19]);
+        assert_eq!(
+            convert(&rgb, ColorType::Rgb8, ColorType::Bgra8),
+            [0, 0, 255, 255, 30, 20, 10, 255]
+        );
+        assert_eq!(
+            convert(&[7, 128], ColorType::La8, ColorType::Rgba8),
+            [7, 7, 7, 128]
+        );
+
+        let wide: Vec<u8> = [0x1234u16, 0xffff]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        assert_eq!(convert(&wide, ColorType::La16, ColorType::La8), [18, 255]);
+        assert_eq!(
+            convert(&[0x12, 0xff], ColorType::La8, ColorType::La16),
+            [0x1212u16, 0xffff]
+                .iter()
+                .flat_map(|sample| sample.to_ne_bytes())
+                .collect::<Vec<_>>()
+        );
+
+        let float: Vec<u8> = [1.5f32, -0.5, 0.5]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        assert_eq!(
+            convert(&float, ColorType::Rgb32F, ColorType::Rgb8),
+            [255, 0, 128]
+        );
+    }
+
+    #[test]
+    fn converts_sample_depth() {
+        assert_eq!(u8_to_u16(0x12), 0x1212);
+        assert_eq!(u16_to_u8(u8_to_u16(200)), 200);
+        assert_eq!(u16_to_u8(385), 1);
+        assert_eq!(u16_to_u8(386), 2);
+        assert_eq!(u16_to_u8(u16::MAX), u8::MAX);
+        assert_eq!(f32_to_u8(0.5), 128);
+        assert_eq!(f32_to_u8(f32::NAN), 0);
+        assert_eq!(f32_to_u16(2.0), u16::MAX);
+
+        for value in 0..=u16::MAX {
+            let exact = (f64::from(value) * 255.0 / 65535.0).round() as u8;
+            assert_eq!(u16_to_u8(value), exact);
+        }
+
+        let mut wide = [0; 4];
+        convert_samples(&[1, 255], SampleFormat::U8, &mut wide, SampleFormat::U16);
+        assert_eq!(wide[..2], 257u16.to_ne_bytes());
+        assert_eq!(wide[2..], u16::MAX.to_ne_bytes());
+
+        let mut float = [0; 8];
+        convert_samples(&wide, SampleFormat::U16, &mut float, SampleFormat::F32);
+        let mut narrow = [0; 2];
+        convert_samples(&float, SampleFormat::F32, &mut narrow, SampleFormat::U8);
+        assert_eq!(narrow, [1, 255]);
+        assert_eq!(SampleFormat::of(ColorType::Rgba32F), SampleFormat::F32);
+    }
+
+    #[test]
+    fn srgb_transfer_function() {
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-6);
+        assert!((linear_to_srgb(0.214_041) - 0.5).abs() < 1e-6);
+        assert_eq!(srgb_to_linear(-0.5), -srgb_to_linear(0.5));
+        assert_eq!(srgb_u8_to_linear(255), 1.0);
+
+        for value in 0..=255u8 {
+            let linear = srgb_u8_to_linear(value);
+            assert_eq!(srgb_to_linear(f32::from(value) / 255.0), linear);
+            assert_eq!((linear_to_srgb(linear) * 255.0).round() as u8, value);
+        }
+
+        let mut rgba = [255, 128, 0, 128];
+        srgb_to_linear_in_place(&mut rgba, ColorType::Rgba8);
+        assert_eq!(rgba, [255, 55, 0, 128]);
+        linear_to_srgb_in_place(&mut rgba, ColorType::Rgba8);
+        assert_eq!(rgba, [255, 128, 0, 128]);
+
+        let mut wide: Vec<u8> = [0x8000u16, 0x8000]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        srgb_to_linear_in_place(&mut wide, ColorType::La16);
+        let linear = u16::from_ne_bytes([wide[0], wide[1]]);
+        assert_eq!(linear, 14_028);
+        assert_eq!(&wide[2..], &0x8000u16.to_ne_bytes());
+    }
+
+    #[test]
+    fn dithers_reduced_depth() {
+        let gray: Vec<u8> = std::iter::repeat_n(0x8080u16 + 128, 16)
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        let mut plain = [0; 16];
+        convert_color(&gray, ColorType::L16, &mut plain, ColorType::L8);
+        assert!(plain.iter().all(|&sample| sample == 128));
+
+        for dither in [DitherMode::Ordered, DitherMode::FloydSteinberg] {
+            let mut dithered = [0; 16];
+            convert_color_dithered(
+                &gray,
+                ColorType::L16,
+                &mut dithered,
+                ColorType::L8,
+                4,
+                dither,
+            );
+            let sum: u32 = dithered.iter().map(|&sample| u32::from(sample)).sum();
+            assert!(dithered
+                .iter()
+                .all(|&sample| sample == 128 || sample == 129));
+            assert!((2050..=2056).contains(&sum), "{:?} {:?}", dither, dithered);
+        }
+
+        let mut expanded = [0; 8];
+        convert_color_dithered(
+            &[1, 2, 3, 4],
+            ColorType::L8,
+            &mut expanded,
+            ColorType::L16,
+            2,
+            DitherMode::FloydSteinberg,
+        );
+        assert_eq!(expanded[..2], 257u16.to_ne_bytes());
+    }
+
+    #[test]
+    fn decoder_dithers_rows() {
+        let data: Vec<u8> = std::iter::repeat_n(0x4000u16, 12)
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        let decoder = ColorConvertingDecoder::new(
+            MemoryDecoder::new(3, 4, ColorType::L16, data.clone()),
+            ColorType::L8,
+        )
+        .unwrap()
+        .with_dither(DitherMode::FloydSteinberg);
+        assert_eq!(decoder.scanline_bytes(), 3);
+
+        let mut expected = [0; 12];
+        convert_color_dithered(
+            &data,
+            ColorType::L16,
+            &mut expected,
+            ColorType::L8,
+            3,
+            DitherMode::FloydSteinberg,
+        );
+        assert_eq!(decode(decoder), expected);
+
+        for mode in [DitherMode::Ordered, DitherMode::FloydSteinberg] {
+            convert_color_dithered(&[], ColorType::Rgb16, &mut [], ColorType::Rgb8, 0, mode);
+        }
+    }
+
+    #[test]
+    fn premultiplies_alpha() {
+        let mut rgba = [255, 128, 3, 128, 9, 9, 9, 0];
+        premultiply_alpha(&mut rgba, ColorType::Rgba8);
+        assert_eq!(rgba, [128, 64, 2, 128, 0, 0, 0, 0]);
+        unpremultiply_alpha(&mut rgba, ColorType::Rgba8);
+        assert_eq!(rgba, [255, 128, 4, 128, 0, 0, 0, 0]);
+
+        for value in 0..=255u8 {
+            for alpha in 0..=255u8 {
+                let mut pixel = [value, alpha];
+                premultiply_alpha(&mut pixel, ColorType::La8);
+                let exact = (f64::from(value) * f64::from(alpha) / 255.0).round();
+                assert_eq!(f64::from(pixel[0]), exact);
+            }
+        }
+
+        let mut wide: Vec<u8> = [0xffffu16, 0x8000]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        premultiply_alpha(&mut wide, ColorType::La16);
+        assert_eq!(wide[..2], 0x8000u16.to_ne_bytes());
+
+        let mut float: Vec<u8> = [0.5f32, 0.25, 1.0, 0.5]
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect();
+        premultiply_alpha(&mut float, ColorType::Rgba32F);
+        assert_eq!(float[..4], 0.25f32.to_ne_bytes());
+        unpremultiply_alpha(&mut float, ColorType::Rgba32F);
+        assert_eq!(float[..4], 0.5f32.to_ne_bytes());
+
+        let mut rgb = [1, 2, 3];
+        premultiply_alpha(&mut rgb, ColorType::Rgb8);
+        assert_eq!(rgb, [1, 2, 3]);
+    }
+
+    #[test]
+    fn decoder_normalizes_alpha() {
+        let decoder = ColorConvertingDecoder::new(
+            MemoryDecoder::new(1, 1, ColorType::La8, vec![200, 51]),
+            ColorType::La8,
+        )
+        .unwrap()
+        .with_alpha_mode(AlphaMode::Premultiplied);
+        assert_eq!(decoder.alpha_mode(), AlphaMode::Premultiplied);
+        assert_eq!(decode(decoder), [40, 51]);
+
+        let decoder = ColorConvertingDecoder::new(
+            MemoryDecoder::new(1, 1, ColorType::La8, vec![200, 51]),
+            ColorType::L8,
+        )
+        .unwrap()
+        .with_alpha_mode(AlphaMode::Premultiplied);
+        assert_eq!(decoder.alpha_mode(), AlphaMode::Straight);
+        assert_eq!(decode(decoder), [200]);
+    }
+
+    #[test]
+    fn decoder_converts_while_reading() {
+        let data: Vec<u8> = (0..12).collect();
+        let decoder = ColorConvertingDecoder::new(
+            MemoryDecoder::new(2, 2, ColorType::Rgb8, data.clone()),
+            ColorType::Rgba8,
+        )
+        .unwrap();
+
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        assert_eq!(decoder.total_bytes(), 16);
+        assert_eq!(decoder.scanline_bytes(), 8);
+        assert_eq!(
+            decoder.decode_fidelity(),
+            DecodeFidelity::LosslessConversion
+        );
+        assert_eq!(
+            decode(decoder),
+            [0, 1, 2, 255, 3, 4, 5, 255, 6, 7, 8, 255, 9, 10, 11, 255]
+        );
+
+        let mut decoder = ColorConvertingDecoder::new(
+            MemoryDecoder::new(2, 2, ColorType::Rgb8, data.clone()),
+            ColorType::L8,
+        )
+        .unwrap();
+        assert_eq!(decoder.decode_fidelity(), DecodeFidelity::LossyConversion);
+
+        decoder.set_output_color(ColorType::Rgb8).unwrap();
+        assert_eq!(decoder.decode_fidelity(), DecodeFidelity::Native);
+        assert_eq!(decode(decoder), data);
+    }
+}
@@ -1,7 +1,12 @@
-use crate::ImageResult;
-use crate::{ColorType, ExtendedColorType};
+use crate::convert::SampleFormat;
+use crate::limits::insufficient_memory;
+use crate::{AlphaMode, Cicp, ColorType, Dimensions, ExtendedColorType, HdrMetadata, RowOrder};
+use crate::{ImageError, ImageResult, Limits, ParameterError, ParameterErrorKind, Rect};
+use crate::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
 use std::convert::TryFrom;
-use std::io::Read;
+use std::io::{self, Read};
+use std::mem::MaybeUninit;
+use std::time::{Duration, Instant};
 
 /// Represents the progress of an image operation.
 ///
@@ -35,6 +40,186 @@ impl Progress {
     }
 }
 
+/// Controls how often progress is reported while reading an image.
+///
+/// The provided read loop of `ImageDecoder` reads groups of scanlines, as sized by
+/// `ImageDecoder::scanline_bytes`, and reports progress after each group. A group is the smallest
+/// number of scanlines satisfying both minimums. By default a group spans at least 4096 bytes and
+/// one scanline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProgressOptions {
+    min_interval_bytes: u64,
+    min_interval_rows: u32,
+}
+
+impl ProgressOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        ProgressOptions::default()
+    }
+
+    /// Set the minimum number of bytes read between two progress reports.
+    pub fn with_min_interval_bytes(mut self, bytes: u64) -> Self {
+        self.min_interval_bytes = bytes;
+        self
+    }
+
+    /// Set the minimum number of scanlines read between two progress reports.
+    ///
+    /// A value of zero is treated as one.
+    pub fn with_min_interval_rows(mut self, rows: u32) -> Self {
+        self.min_interval_rows = rows;
+        self
+    }
+
+    /// The minimum number of bytes read between two progress reports.
+    pub fn min_interval_bytes(&self) -> u64 {
+        self.min_interval_bytes
+    }
+
+    /// The minimum number of scanlines read between two progress reports.
+    pub fn min_interval_rows(&self) -> u32 {
+        self.min_interval_rows
+    }
+
+    /// The number of bytes to read in each step, for a decoder with the given scanline size.
+    pub(crate) fn group_bytes(&self, scanline_bytes: u64) -> usize {
+        let scanline_bytes = scanline_bytes.max(1);
+        let rows = self
+            .min_interval_bytes
+            .div_ceil(scanline_bytes)
+            .max(u64::from(self.min_interval_rows))
+            .max(1);
+        usize::try_from(rows.saturating_mul(scanline_bytes)).unwrap_or(usize::MAX)
+    }
+}
+
+impl Default for ProgressOptions {
+    fn default() -> Self {
+        ProgressOptions {
+            min_interval_bytes: 4096,
+            min_interval_rows: 1,
+        }
+    }
+}
+
+/// Statistics about a completed decode, see `ImageDecoder::read_image_with_stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecodeStats {
+    compressed_bytes: Option<u64>,
+    decoded_bytes: u64,
+    scanlines: u32,
+    duration: Duration,
+}
+
+impl DecodeStats {
+    /// Describe a decode which produced the given bytes and scanlines in the given time.
+    pub fn new(decoded_bytes: u64, scanlines: u32, duration: Duration) -> Self {
+        DecodeStats {
+            compressed_bytes: None,
+            decoded_bytes,
+            scanlines,
+            duration,
+        }
+    }
+
+    /// Record the number of bytes consumed from the file.
+    pub fn with_compressed_bytes(mut self, bytes: u64) -> Self {
+        self.compressed_bytes = Some(bytes);
+        self
+    }
+
+    /// The number of bytes consumed from the file, if the decoder tracks it.
+    pub fn compressed_bytes(&self) -> Option<u64> {
+        self.compressed_bytes
+    }
+
+    /// The number of bytes of pixel data produced.
+    pub fn decoded_bytes(&self) -> u64 {
+        self.decoded_bytes
+    }
+
+    /// The number of scanlines produced.
+    pub fn scanlines(&self) -> u32 {
+        self.scanlines
+    }
+
+    /// The wall time spent decoding.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The ratio of decoded to compressed bytes, if the compressed size is known.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.compressed_bytes {
+            Some(0) | None => None,
+            Some(compressed) => Some(self.decoded_bytes as f64 / compressed as f64),
+        }
+    }
+
+    /// The number of decoded bytes produced per second, if any time was measured.
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let seconds = self.duration.as_secs_f64();
+        if seconds > 0.0 {
+            Some(self.decoded_bytes as f64 / seconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// How faithfully the decoded pixels represent the samples stored in the file.
+///
+/// This only describes the color representation, such as the sample depth and the channels, not
+/// any loss that is inherent to the compression of the file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodeFidelity {
+    /// The output is the native representation of the file.
+    Native,
+    /// The output was converted, but the original samples can be restored exactly.
+    ///
+    /// Examples are the expansion of a palette or the promotion of 12-bit samples to 16 bits.
+    LosslessConversion,
+    /// The output was converted in a way that discarded information.
+    ///
+    /// Examples are the reduction of 16-bit samples to 8 bits or dropping an alpha channel.
+    LossyConversion,
+}
+
+impl DecodeFidelity {
+    /// Estimate the fidelity of a conversion between two color types.
+    ///
+    /// A conversion is considered lossless if it neither reduces the bits of each sample nor the
+    /// number of channels. Block compressed sources count with the samples they decode to, 8-bit
+    /// integers for most of them and half floats for BC6H, which only floats hold exactly.
+    pub fn estimate(original: ExtendedColorType, decoded: ColorType) -> Self {
+        let decoded_extended = ExtendedColorType::from(decoded);
+        if original == decoded_extended {
+            return DecodeFidelity::Native;
+        }
+        if original == ExtendedColorType::Bc6h && SampleFormat::of(decoded) != SampleFormat::F32 {
+            return DecodeFidelity::LossyConversion;
+        }
+
+        let bits_per_sample = |color: ExtendedColorType| match color {
+            ExtendedColorType::Bc6h => 16,
+            color if color.is_block_compressed() => 8,
+            color => color
+                .bits_per_pixel()
+                .checked_div(color.channel_count().into())
+                .unwrap_or_default(),
+        };
+        if bits_per_sample(original) <= bits_per_sample(decoded_extended)
+            && original.channel_count() <= decoded.channel_count()
+        {
+            DecodeFidelity::LosslessConversion
+        } else {
+            DecodeFidelity::LossyConversion
+        }
+    }
+}
+
 /// The trait that all decoders implement
 pub trait ImageDecoder<'a>: Sized {
     /// The type of reader produced by `into_reader`.
@@ -46,11 +231,120 @@ pub trait ImageDecoder<'a>: Sized {
     /// Returns the color type of the image data produced by this decoder
     fn color_type(&self) -> ColorType;
 
+    /// Returns the width and height of the image, see `dimensions`.
+    fn size(&self) -> Dimensions {
+        self.dimensions().into()
+    }
+
+    /// Request that the decoder produces pixels of the given color type.
+    ///
+    /// Decoders which can produce a color type cheaply, such as only the luma of a JPEG or 8-bit
+    /// samples of a 16-bit PNG, override this and report the color type from `color_type`
+    /// afterwards. The provided implementation only accepts the current color type and returns an
+    /// unsupported error otherwise. `ColorConvertingDecoder` falls back to converting the pixels
+    /// for decoders which refuse.
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        if color == self.color_type() {
+            Ok(())
+        } else {
+            Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Unknown,
+                    UnsupportedErrorKind::Color(color.into()),
+                ),
+            ))
+        }
+    }
+
     /// Retuns the color type of the image file before decoding
     fn original_color_type(&self) -> ExtendedColorType {
         self.color_type().into()
     }
 
+    /// Returns whether the output of this decoder is the native representation of the file.
+    ///
+    /// The provided implementation estimates the fidelity from `original_color_type` and
+    /// `color_type`, see `DecodeFidelity::estimate`. Decoders which know better should override it.
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        DecodeFidelity::estimate(self.original_color_type(), self.color_type())
+    }
+
+    /// Returns whether the samples of the file can be restored exactly from the output.
+    ///
+    /// Archival tools can use this to detect when bit-exact round-tripping is impossible.
+    fn is_lossless_decode(&self) -> bool {
+        self.decode_fidelity() != DecodeFidelity::LossyConversion
+    }
+
+    /// Returns the HDR mastering metadata of the image, if the file contains any.
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        None
+    }
+
+    /// Returns the color space of the image as code points, if the file describes it that way.
+    ///
+    /// Files may describe their colors both as code points and with an ICC profile.
+    fn cicp(&self) -> Option<Cicp> {
+        None
+    }
+
+    /// Returns the ICC profile embedded in the image, if any.
+    ///
+    /// Images without a profile or code points are usually assumed to be in sRGB. See
+    /// `ColorManagedDecoder` to convert the pixels to another profile.
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns whether the color samples produced are premultiplied by alpha.
+    ///
+    /// The provided implementation returns `AlphaMode::Straight`. See `ColorConvertingDecoder` to
+    /// normalize the alpha mode.
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Straight
+    }
+
+    /// Returns the order in which the rows of the image are produced.
+    ///
+    /// Decoders of formats which store rows bottom-up may produce them in that order, which saves
+    /// buffering for callers that can handle it. Use `TopDownDecoder` to always get top-down rows.
+    /// The provided implementation returns `RowOrder::TopDown`.
+    fn row_order(&self) -> RowOrder {
+        RowOrder::TopDown
+    }
+
+    /// Hint that the image is only needed at a reduced scale of `numerator / denominator`.
+    ///
+    /// Decoders which can decode at a reduced size much faster than at full size, such as JPEG at
+    /// 1/2, 1/4 or 1/8 of the size, choose the smallest scale they support that is not smaller
+    /// than the hint. They return the scale they will produce, `dimensions` reports the scaled
+    /// size afterwards with partial pixels rounded up. Hints of zero or above one are ignored.
+    /// The provided implementation does not scale and returns `(1, 1)`.
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        let _ = (numerator, denominator);
+        (1, 1)
+    }
+
+    /// Configure the resource limits the decoder has to respect.
+    ///
+    /// The provided implementation can not store the limits. It only checks the dimensions and the
+    /// size of the decoded image against them, returning an error if they are exceeded. Decoders
+    /// should override it to also honor the limits for their internal allocations, and return
+    /// them from `limits`. The provided read methods of other decoders check against the default
+    /// limits, also after a change of the output color with `set_output_color`.
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        check_limits(self, &limits)
+    }
+
+    /// Returns the limits configured with `set_limits`.
+    ///
+    /// The provided read methods validate the image against these limits before reading. The
+    /// provided implementation returns `Limits::default`, so that the image is checked against the
+    /// default allocation limit even by decoders which do not store their limits.
+    fn limits(&self) -> Limits {
+        Limits::default()
+    }
+
     /// Returns a reader that can be used to obtain the bytes of the image. For the best
     /// performance, always try to read at least `scanline_bytes` from the reader at a time. Reading
     /// fewer bytes will cause the reader to perform internal buffering.
@@ -61,7 +355,7 @@ pub trait ImageDecoder<'a>: Sized {
     /// This is the size of the buffer that must be passed to `read_image` or
     /// `read_image_with_progress`. The returned value may exceed usize::MAX, in
     /// which case it isn't actually possible to construct a buffer to decode all the image data
-    /// into.
+    /// into. See the `streaming` module for processing such images row by row or in bands.
     fn total_bytes(&self) -> u64 {
         let dimensions = self.dimensions();
         u64::from(dimensions.0)
@@ -105,16 +399,24 @@ pub trait ImageDecoder<'a>: Sized {
         self,
         buf: &mut [u8],
         progress_callback: F,
+    ) -> ImageResult<()> {
+        self.read_image_with_progress_options(buf, ProgressOptions::default(), progress_callback)
+    }
+
+    /// Same as `read_image_with_progress` but with control over how often progress is reported.
+    fn read_image_with_progress_options<F: Fn(Progress)>(
+        self,
+        buf: &mut [u8],
+        options: ProgressOptions,
+        progress_callback: F,
     ) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        #[cfg(feature = "tracing")]
+        let _span = decode_span(&self);
+        check_limits(&self, &self.limits())?;
 
         let total_bytes = self.total_bytes() as usize;
-        let scanline_bytes = self.scanline_bytes() as usize;
-        let target_read_size = if scanline_bytes < 4096 {
-            (4096 / scanline_bytes) * scanline_bytes
-        } else {
-            scanline_bytes
-        };
+        let target_read_size = options.group_bytes(self.scanline_bytes());
 
         let mut reader = self.into_reader()?;
 
@@ -132,30 +434,484 @@ pub trait ImageDecoder<'a>: Sized {
 
         Ok(())
     }
+
+    /// Same as `read_image` but passes every row to a callback as soon as it is decoded.
+    ///
+    /// This allows verifying the decoded image against stored checksums while it is read, for
+    /// example by feeding the rows to a hasher, see `streaming::digest`. Each row contains
+    /// `width * bytes_per_pixel` bytes and the rows are passed in the order given by `row_order`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if buf.len() != self.total_bytes().
+    fn read_image_with_row_hook<F: FnMut(&[u8])>(
+        self,
+        buf: &mut [u8],
+        mut hook: F,
+    ) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        #[cfg(feature = "tracing")]
+        let _span = decode_span(&self);
+        check_limits(&self, &self.limits())?;
+
+        let row_len =
+            self.dimensions().0 as usize * usize::from(self.color_type().bytes_per_pixel());
+        let target_read_size = ProgressOptions::default().group_bytes(self.scanline_bytes());
+        let mut reader = self.into_reader()?;
+
+        let (mut bytes_read, mut hooked) = (0, 0);
+        while bytes_read < buf.len() {
+            let read_size = target_read_size.min(buf.len() - bytes_read);
+            reader.read_exact(&mut buf[bytes_read..][..read_size])?;
+            bytes_read += read_size;
+
+            while row_len > 0 && hooked + row_len <= bytes_read {
+                hook(&buf[hooked..][..row_len]);
+                hooked += row_len;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as `read_image` but returns statistics about the decode.
+    ///
+    /// The provided implementation measures the wall time of `read_image` and reports the whole
+    /// image as decoded, but does not know the number of bytes consumed from the file. Decoders
+    /// which track their input should override it to add them with
+    /// `DecodeStats::with_compressed_bytes`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if buf.len() != self.total_bytes().
+    fn read_image_with_stats(self, buf: &mut [u8]) -> ImageResult<DecodeStats> {
+        let (_, height) = self.dimensions();
+        let start = Instant::now();
+        self.read_image(buf)?;
+
+        let stats = DecodeStats::new(buf.len() as u64, height, start.elapsed());
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            bytes = stats.decoded_bytes(),
+            scanlines = stats.scanlines(),
+            duration = ?stats.duration(),
+            "decoded image"
+        );
+        Ok(stats)
+    }
+
+    /// Same as `read_image` but writes into a buffer which need not be initialized.
+    ///
+    /// This avoids clearing the buffer before decoding. On success, the returned slice is the
+    /// whole buffer which is then fully initialized with the pixel data. The provided
+    /// implementation reads from `into_reader` through an intermediate buffer of a few
+    /// kilobytes.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if buf.len() != self.total_bytes().
+    fn read_image_uninit(self, buf: &mut [MaybeUninit<u8>]) -> ImageResult<&mut [u8]> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        #[cfg(feature = "tracing")]
+        let _span = decode_span(&self);
+        check_limits(&self, &self.limits())?;
+
+        let reader = self.into_reader()?;
+        Ok(read_exact_uninit(reader, buf)?)
+    }
+
+    /// Read the whole image into a newly allocated vector.
+    ///
+    /// The image is validated against `limits` before the vector is allocated. An image which
+    /// does not fit into memory results in a limit error instead of a panic.
+    fn read_image_to_vec(self) -> ImageResult<Vec<u8>> {
+        check_limits(&self, &self.limits())?;
+        let len = usize::try_from(self.total_bytes()).map_err(|_| insufficient_memory())?;
+        let mut buf = vec![0; len];
+        self.read_image(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns the block compressed color type of the file, if its blocks can be read as they are.
+    ///
+    /// Decoders of texture formats such as DDS override this together with `read_compressed`, so
+    /// that texture pipelines can pass the blocks to a graphics API without decoding them.
+    /// Adapters which change the pixels do not forward it. The provided implementation returns
+    /// `None`.
+    fn compressed_color_type(&self) -> Option<ExtendedColorType> {
+        None
+    }
+
+    /// Read the compressed blocks of the image instead of decoding them.
+    ///
+    /// Rows of blocks are stored from top to bottom, regardless of `row_order`, each block as it
+    /// is stored in the file. The size of the blocks is given by `ExtendedColorType::buffer_size`
+    /// for the `compressed_color_type`. The provided implementation returns an unsupported
+    /// error.
+    ///
+    /// # Panics
+    ///
+    /// Implementations may panic if `buf.len()` is not the size of the blocks.
+    fn read_compressed(self, buf: &mut [u8]) -> ImageResult<()> {
+        let _ = buf;
+        Err(compressed_blocks_unsupported())
+    }
+
+    /// Read the compressed blocks of the image into a newly allocated vector.
+    ///
+    /// Returns the color type of the blocks along with them. Their size is validated against
+    /// `limits` before the vector is allocated. Results in an unsupported error if the decoder
+    /// can not provide compressed blocks.
+    fn read_compressed_to_vec(self) -> ImageResult<(ExtendedColorType, Vec<u8>)> {
+        let color = self
+            .compressed_color_type()
+            .ok_or_else(compressed_blocks_unsupported)?;
+
+        let (width, height) = self.dimensions();
+        let limits = self.limits();
+        limits.check_dimensions(width, height)?;
+        let size = color
+            .buffer_size(width, height)
+            .ok_or_else(insufficient_memory)?;
+        limits.check_alloc(size)?;
+
+        let len = usize::try_from(size).map_err(|_| insufficient_memory())?;
+        let mut buf = vec![0; len];
+        self.read_compressed(&mut buf)?;
+        Ok((color, buf))
+    }
+}
+
+fn compressed_blocks_unsupported() -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Unknown,
+        UnsupportedErrorKind::GenericFeature("reading compressed blocks".into()),
+    ))
+}
+
+/// Validate the dimensions and decoded size of the image of a decoder.
+fn check_limits<'a, D: ImageDecoder<'a>>(decoder: &D, limits: &Limits) -> ImageResult<()> {
+    let (width, height) = decoder.dimensions();
+    let result = limits
+        .check_dimensions(width, height)
+        .and_then(|()| limits.check_alloc(decoder.total_bytes()));
+
+    #[cfg(feature = "tracing")]
+    if let Err(err) = &result {
+        tracing::warn!(
+            width,
+            height,
+            bytes = decoder.total_bytes(),
+            error = %err,
+            "image exceeds the decoding limits"
+        );
+    }
+    result
+}
+
+/// Enter a span describing the image of a decoder.
+///
+/// Decoders do not know their format, `ImageReader` records it in an enclosing span.
+#[cfg(feature = "tracing")]
+fn decode_span<'a, D: ImageDecoder<'a>>(decoder: &D) -> tracing::span::EnteredSpan {
+    let (width, height) = decoder.dimensions();
+    tracing::debug_span!(
+        "decode",
+        width,
+        height,
+        color = ?decoder.color_type(),
+        bytes = decoder.total_bytes()
+    )
+    .entered()
+}
+
+/// The size of the intermediate buffer of `read_exact_uninit`.
+const UNINIT_CHUNK_BYTES: usize = 4096;
+
+/// Fill the whole buffer from the reader, returning the then initialized bytes.
+fn read_exact_uninit(mut reader: impl Read, buf: &mut [MaybeUninit<u8>]) -> io::Result<&mut [u8]> {
+    let mut chunk = vec![0; UNINIT_CHUNK_BYTES.min(buf.len())];
+    for target in buf.chunks_mut(UNINIT_CHUNK_BYTES) {
+        let source = &mut chunk[..target.len()];
+        reader.read_exact(source)?;
+        for (byte, &value) in target.iter_mut().zip(source.iter()) {
+            *byte = MaybeUninit::new(value);
+        }
+    }
+
+    // SAFETY: every byte of the buffer was written by the loop above, which only ends early by
+    // returning an error. `MaybeUninit<u8>` has the same layout as `u8`.
+    #[allow(unsafe_code)]
+    let init = unsafe { &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]) };
+    Ok(init)
 }
 
 /// ImageDecoderExt trait
 pub trait ImageDecoderExt<'a>: ImageDecoder<'a> + Sized {
     /// Read a rectangular section of the image.
-    fn read_rect(
-        &mut self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
-        buf: &mut [u8],
-    ) -> ImageResult<()> {
-        self.read_rect_with_progress(x, y, width, height, buf, |_| {})
+    ///
+    /// A rectangle which does not lie within the image results in a
+    /// `ParameterErrorKind::RectOutOfBounds` error, before `read_rect_with_progress` is called.
+    fn read_rect(&mut self, rect: Rect, buf: &mut [u8]) -> ImageResult<()> {
+        check_rect_bounds(self.dimensions(), rect)?;
+        self.read_rect_with_progress(rect, buf, |_| {})
     }
 
     /// Read a rectangular section of the image, periodically reporting progress.
+    ///
+    /// Implementations must return a `ParameterErrorKind::RectOutOfBounds` error for a rectangle
+    /// which does not lie within the image, see `check_rect_bounds`.
     fn read_rect_with_progress<F: Fn(Progress)>(
         &mut self,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
+        rect: Rect,
         buf: &mut [u8],
         progress_callback: F,
     ) -> ImageResult<()>;
 }
+
+/// Check that a rectangle lies within an image of the given dimensions.
+///
+/// Returns a `ParameterErrorKind::RectOutOfBounds` error otherwise. Empty rectangles are allowed
+/// anywhere within or on the edge of the image.
+pub fn check_rect_bounds(dimensions: (u32, u32), rect: Rect) -> ImageResult<()> {
+    if Rect::from_dimensions(dimensions).contains(&rect) {
+        return Ok(());
+    }
+
+    let (image_width, image_height) = dimensions;
+    Err(ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::RectOutOfBounds {
+            rect,
+            image_width,
+            image_height,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryDecoder;
+    use std::cell::Cell;
+
+    #[test]
+    fn progress_groups() {
+        assert_eq!(ProgressOptions::default().group_bytes(100), 4100);
+        assert_eq!(ProgressOptions::default().group_bytes(5000), 5000);
+        assert_eq!(ProgressOptions::default().group_bytes(0), 4096);
+
+        let options = ProgressOptions::new()
+            .with_min_interval_bytes(0)
+            .with_min_interval_rows(3);
+        assert_eq!(options.group_bytes(100), 300);
+    }
+
+    #[test]
+    fn read_paths_enforce_limits() {
+        let mut limits = Limits::no_limits();
+        limits.max_alloc = Some(99);
+
+        let mut decoder = MemoryDecoder::new(10, 10, ColorType::L8, vec![0; 100]);
+        assert!(decoder.set_limits(limits.clone()).is_err());
+
+        // A decoder which stores the limits without checking them.
+        decoder.limits = limits;
+        assert!(decoder.read_image_to_vec().is_err());
+
+        let decoder = MemoryDecoder::new(10, 10, ColorType::L8, vec![7; 100]);
+        assert_eq!(decoder.read_image_to_vec().unwrap(), vec![7; 100]);
+
+        /// Does not store any limits, its image exceeds the default allocation limit.
+        struct Unlimited;
+
+        impl<'a> ImageDecoder<'a> for Unlimited {
+            type Reader = io::Empty;
+
+            fn dimensions(&self) -> (u32, u32) {
+                (1 << 15, 1 << 15)
+            }
+
+            fn color_type(&self) -> ColorType {
+                ColorType::Rgba8
+            }
+
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                Ok(io::empty())
+            }
+        }
+
+        match Unlimited.read_image_to_vec() {
+            Err(ImageError::Limits(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn hooks_every_row() {
+        let data: Vec<u8> = (0..12).collect();
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        let mut rows = Vec::new();
+        let mut buf = [0; 12];
+        decoder
+            .read_image_with_row_hook(&mut buf, |row| rows.push(row.to_vec()))
+            .unwrap();
+        assert_eq!(buf[..], data[..]);
+        assert_eq!(rows, [[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11]]);
+    }
+
+    #[test]
+    fn collects_stats() {
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, vec![0; 12]);
+        let stats = decoder.read_image_with_stats(&mut [0; 12]).unwrap();
+        assert_eq!(stats.decoded_bytes(), 12);
+        assert_eq!(stats.scanlines(), 3);
+        assert_eq!(stats.compressed_bytes(), None);
+        assert_eq!(stats.compression_ratio(), None);
+
+        let stats = DecodeStats::new(12, 3, Duration::from_millis(500)).with_compressed_bytes(4);
+        assert_eq!(stats.compression_ratio(), Some(3.0));
+        assert_eq!(stats.bytes_per_second(), Some(24.0));
+    }
+
+    #[test]
+    fn reads_compressed_blocks() {
+        /// Holds a single BC4 block.
+        struct Bc4Decoder(MemoryDecoder);
+
+        impl<'a> ImageDecoder<'a> for Bc4Decoder {
+            type Reader = <MemoryDecoder as ImageDecoder<'a>>::Reader;
+
+            fn dimensions(&self) -> (u32, u32) {
+                self.0.dimensions()
+            }
+
+            fn color_type(&self) -> ColorType {
+                self.0.color_type()
+            }
+
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                self.0.into_reader()
+            }
+
+            fn compressed_color_type(&self) -> Option<ExtendedColorType> {
+                Some(ExtendedColorType::Bc4)
+            }
+
+            fn read_compressed(self, buf: &mut [u8]) -> ImageResult<()> {
+                buf.copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+                Ok(())
+            }
+
+            fn limits(&self) -> Limits {
+                self.0.limits()
+            }
+        }
+
+        let decoder = Bc4Decoder(MemoryDecoder::new(3, 2, ColorType::L8, vec![0; 6]));
+        let (color, blocks) = decoder.read_compressed_to_vec().unwrap();
+        assert_eq!(color, ExtendedColorType::Bc4);
+        assert_eq!(blocks, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut decoder = Bc4Decoder(MemoryDecoder::new(3, 2, ColorType::L8, vec![0; 6]));
+        decoder.0.limits.max_alloc = Some(7);
+        assert!(matches!(
+            decoder.read_compressed_to_vec(),
+            Err(ImageError::Limits(_))
+        ));
+
+        let decoder = MemoryDecoder::new(3, 2, ColorType::L8, vec![0; 6]);
+        assert!(matches!(
+            decoder.read_compressed_to_vec(),
+            Err(ImageError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn checks_rect_bounds() {
+        assert!(check_rect_bounds((10, 5), Rect::new(0, 0, 10, 5)).is_ok());
+        assert!(check_rect_bounds((10, 5), Rect::new(10, 5, 0, 0)).is_ok());
+        assert!(check_rect_bounds((10, 5), Rect::new(9, 0, 2, 1)).is_err());
+
+        let rect = Rect::new(1, 2, 3, 4);
+        match check_rect_bounds((10, 5), rect) {
+            Err(ImageError::Parameter(err)) => assert_eq!(
+                err.kind(),
+                ParameterErrorKind::RectOutOfBounds {
+                    rect,
+                    image_width: 10,
+                    image_height: 5,
+                }
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn estimates_fidelity() {
+        use DecodeFidelity::*;
+
+        let palette = ExtendedColorType::Unknown {
+            bits_per_sample: 8,
+            channels: 1,
+        };
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Rgb8, ColorType::Rgb8),
+            Native
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(palette, ColorType::Rgba8),
+            LosslessConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::L4, ColorType::L8),
+            LosslessConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Rgb16, ColorType::Rgb8),
+            LossyConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::La8, ColorType::L16),
+            LossyConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Bc6h, ColorType::Rgb8),
+            LossyConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Bc6h, ColorType::Rgb16),
+            LossyConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Bc6h, ColorType::Rgb32F),
+            LosslessConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Bc7, ColorType::Rgba8),
+            LosslessConversion
+        );
+        assert_eq!(
+            DecodeFidelity::estimate(ExtendedColorType::Bc1, ColorType::L8),
+            LossyConversion
+        );
+
+        let decoder = MemoryDecoder::new(1, 1, ColorType::L8, vec![0]);
+        assert!(decoder.is_lossless_decode());
+    }
+
+    #[test]
+    fn progress_options_bound_callbacks() {
+        let decoder = MemoryDecoder::new(10, 10, ColorType::L8, vec![0; 100]);
+        let options = ProgressOptions::new()
+            .with_min_interval_bytes(25)
+            .with_min_interval_rows(1);
+        let calls = Cell::new(0);
+
+        let mut buf = vec![0; 100];
+        decoder
+            .read_image_with_progress_options(&mut buf, options, |_| calls.set(calls.get() + 1))
+            .unwrap();
+        // Groups of three rows.
+        assert_eq!(calls.get(), 4);
+    }
+}
@@ -0,0 +1,235 @@
+use std::cmp::Reverse;
+
+use crate::error::{ImageError, ImageFormatHint, UnsupportedError};
+use crate::{ImageFormat, ImageResult};
+
+/// The TIFF tag holding the DNG version, present in the first IFD of every DNG file.
+const DNG_VERSION_TAG: u16 = 50706;
+
+/// How certain a format detection is.
+///
+/// Confidences are ordered, `High` is the greatest.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Confidence {
+    /// The data is plausible for the format, but it has no signature to check.
+    Low,
+    /// A short or shared signature matches, other data could match as well.
+    Medium,
+    /// A distinctive signature matches.
+    High,
+}
+
+/// Signatures that identify a format by a fixed prefix.
+const MAGIC_BYTES: &[(&[u8], ImageFormat, Confidence)] = &[
+    (b"\x89PNG\r\n\x1a\n", ImageFormat::Png, Confidence::High),
+    (&[0xff, 0xd8, 0xff], ImageFormat::Jpeg, Confidence::High),
+    (b"GIF89a", ImageFormat::Gif, Confidence::High),
+    (b"GIF87a", ImageFormat::Gif, Confidence::High),
+    (b"DDS ", ImageFormat::Dds, Confidence::High),
+    (b"#?RADIANCE", ImageFormat::Hdr, Confidence::High),
+    (b"#?RGBE", ImageFormat::Hdr, Confidence::High),
+    (b"BM", ImageFormat::Bmp, Confidence::Medium),
+    (&[0, 0, 1, 0], ImageFormat::Ico, Confidence::Medium),
+];
+
+/// Guess the format of an image from the first bytes of its file.
+///
+/// This returns the most likely candidate of `guess_format_candidates`, ignoring those with a
+/// `Low` confidence. TIFF files that carry the DNG version tag in their first image file directory
+/// are reported as `ImageFormat::Dng`. If the buffer ends before that directory could be
+/// inspected, the file is reported as `ImageFormat::Tiff`, so pass the first few kilobytes of a
+/// file to tell them apart reliably. Formats without a signature, such as TGA, are never detected.
+pub fn guess_format(buffer: &[u8]) -> ImageResult<ImageFormat> {
+    guess_format_candidates(buffer)
+        .into_iter()
+        .find(|&(_, confidence)| confidence > Confidence::Low)
+        .map(|(format, _)| format)
+        .ok_or_else(|| ImageError::Unsupported(UnsupportedError::from(ImageFormatHint::Unknown)))
+}
+
+/// List the formats the first bytes of a file could belong to, the most likely first.
+///
+/// Some signatures are ambiguous, and some formats have no signature at all. This lets callers
+/// apply their own policy, such as trying each candidate in order or preferring the one matching
+/// a file extension. Candidates of equal confidence are in no particular order.
+pub fn guess_format_candidates(buffer: &[u8]) -> Vec<(ImageFormat, Confidence)> {
+    let mut candidates = Vec::new();
+    guess_tiff(buffer, &mut candidates);
+
+    if buffer.len() >= 12 && &buffer[..4] == b"RIFF" && &buffer[8..12] == b"WEBP" {
+        candidates.push((ImageFormat::WebP, Confidence::High));
+    }
+
+    if let [b'P', b'1'..=b'7', ..] = buffer {
+        candidates.push((ImageFormat::Pnm, Confidence::Medium));
+    }
+
+    candidates.extend(
+        MAGIC_BYTES
+            .iter()
+            .filter(|(magic, _, _)| buffer.starts_with(magic))
+            .map(|&(_, format, confidence)| (format, confidence)),
+    );
+
+    if is_plausible_tga(buffer) {
+        candidates.push((ImageFormat::Tga, Confidence::Low));
+    }
+
+    candidates.sort_by_key(|&(_, confidence)| Reverse(confidence));
+    candidates
+}
+
+/// Detect TIFF based files, distinguishing DNG by its version tag.
+fn guess_tiff(buffer: &[u8], candidates: &mut Vec<(ImageFormat, Confidence)>) {
+    let little_endian = match buffer.get(..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        // BigTIFF is not used for DNG.
+        Some(b"II+\0") | Some(b"MM\0+") => {
+            candidates.push((ImageFormat::Tiff, Confidence::High));
+            return;
+        }
+        _ => return,
+    };
+
+    match has_tag(buffer, little_endian, DNG_VERSION_TAG) {
+        Some(true) => {
+            candidates.push((ImageFormat::Dng, Confidence::High));
+            candidates.push((ImageFormat::Tiff, Confidence::Low));
+        }
+        Some(false) => candidates.push((ImageFormat::Tiff, Confidence::High)),
+        None => {
+            candidates.push((ImageFormat::Tiff, Confidence::Medium));
+            candidates.push((ImageFormat::Dng, Confidence::Low));
+        }
+    }
+}
+
+/// Check whether the buffer starts with a TGA header of a known image type.
+fn is_plausible_tga(buffer: &[u8]) -> bool {
+    let header = match buffer.get(..18) {
+        Some(header) => header,
+        None => return false,
+    };
+
+    let color_map_type = header[1];
+    let image_type = header[2];
+    let bits_per_pixel = header[16];
+    matches!(color_map_type, 0 | 1)
+        && matches!(image_type, 1 | 2 | 3 | 9 | 10 | 11)
+        && matches!(bits_per_pixel, 8 | 15 | 16 | 24 | 32)
+}
+
+/// Search the first IFD for a tag, `None` if the buffer ends before the IFD does.
+fn has_tag(buffer: &[u8], little_endian: bool, tag: u16) -> Option<bool> {
+    let read_u16 = |at: usize| {
+        let bytes = [*buffer.get(at)?, *buffer.get(at.checked_add(1)?)?];
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+
+    let (high, low) = (read_u16(4)?, read_u16(6)?);
+    let offset = if little_endian {
+        u32::from(low) << 16 | u32::from(high)
+    } else {
+        u32::from(high) << 16 | u32::from(low)
+    } as usize;
+
+    let count = usize::from(read_u16(offset)?);
+    for index in 0..count {
+        if read_u16(offset.saturating_add(2 + index * 12))? == tag {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little endian TIFF header followed by the first IFD with the given tags.
+    fn tiff(tags: &[u16]) -> Vec<u8> {
+        let mut data = b"II*\0".to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&(tags.len() as u16).to_le_bytes());
+        for &tag in tags {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&[0; 10]);
+        }
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    #[test]
+    fn detects_signatures() {
+        assert_eq!(
+            guess_format(b"\x89PNG\r\n\x1a\n\0\0").unwrap(),
+            ImageFormat::Png
+        );
+        assert_eq!(
+            guess_format(b"RIFF\0\0\0\0WEBPVP8 ").unwrap(),
+            ImageFormat::WebP
+        );
+        assert_eq!(guess_format(b"P6\n1 1\n255\n").unwrap(), ImageFormat::Pnm);
+        assert_eq!(guess_format(b"GIF89a").unwrap(), ImageFormat::Gif);
+        assert!(guess_format(b"not an image").is_err());
+        assert!(guess_format(b"").is_err());
+    }
+
+    #[test]
+    fn distinguishes_dng_from_tiff() {
+        assert_eq!(guess_format(&tiff(&[256, 257])).unwrap(), ImageFormat::Tiff);
+        assert_eq!(
+            guess_format(&tiff(&[256, DNG_VERSION_TAG])).unwrap(),
+            ImageFormat::Dng
+        );
+
+        let mut big_endian = b"MM\0*".to_vec();
+        big_endian.extend_from_slice(&8u32.to_be_bytes());
+        big_endian.extend_from_slice(&1u16.to_be_bytes());
+        big_endian.extend_from_slice(&DNG_VERSION_TAG.to_be_bytes());
+        big_endian.extend_from_slice(&[0; 10]);
+        assert_eq!(guess_format(&big_endian).unwrap(), ImageFormat::Dng);
+    }
+
+    #[test]
+    fn ranks_candidates() {
+        let dng = tiff(&[256, DNG_VERSION_TAG]);
+        assert_eq!(
+            guess_format_candidates(&dng),
+            [
+                (ImageFormat::Dng, Confidence::High),
+                (ImageFormat::Tiff, Confidence::Low)
+            ]
+        );
+        assert_eq!(
+            guess_format_candidates(&dng[..12]),
+            [
+                (ImageFormat::Tiff, Confidence::Medium),
+                (ImageFormat::Dng, Confidence::Low)
+            ]
+        );
+
+        let mut tga = vec![0; 18];
+        tga[2] = 2;
+        tga[16] = 24;
+        assert_eq!(
+            guess_format_candidates(&tga),
+            [(ImageFormat::Tga, Confidence::Low)]
+        );
+        assert!(guess_format(&tga).is_err());
+        assert!(guess_format_candidates(b"not an image").is_empty());
+    }
+
+    #[test]
+    fn truncated_tiff_is_not_dng() {
+        let data = tiff(&[256, DNG_VERSION_TAG]);
+        assert_eq!(guess_format(&data[..20]).unwrap(), ImageFormat::Tiff);
+        assert_eq!(guess_format(&data[..4]).unwrap(), ImageFormat::Tiff);
+    }
+}
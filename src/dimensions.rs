@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::{ColorType, ImageError, ImageResult, ParameterError, ParameterErrorKind};
+
+/// The width and height of an image, in pixels.
+///
+/// Unlike a tuple, the fields are named, so the two can not be swapped by accident. Types which
+/// return a `(width, height)` tuple from `dimensions` hand out this type from `size`.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Dimensions {
+    /// The number of columns.
+    pub width: u32,
+    /// The number of rows.
+    pub height: u32,
+}
+
+impl Dimensions {
+    /// Create dimensions from a width and a height.
+    pub const fn new(width: u32, height: u32) -> Self {
+        Dimensions { width, height }
+    }
+
+    /// Returns the number of pixels.
+    pub const fn pixel_count(self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Returns the number of bytes of an image of the color type, in the layout produced by
+    /// `ImageDecoder::read_image`.
+    ///
+    /// Returns `None` if the number does not fit into a `u64`.
+    pub fn byte_len(self, color: ColorType) -> Option<u64> {
+        self.pixel_count()
+            .checked_mul(u64::from(color.bytes_per_pixel()))
+    }
+
+    /// Returns whether the width or the height is zero.
+    pub const fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Check that neither the width nor the height is zero.
+    ///
+    /// Returns a `ParameterErrorKind::DimensionMismatch` error otherwise, for formats which can
+    /// not store empty images.
+    pub fn check_non_empty(self) -> ImageResult<()> {
+        if self.is_empty() {
+            Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the dimensions with width and height swapped, as after a rotation by 90 degrees.
+    pub const fn transposed(self) -> Self {
+        Dimensions::new(self.height, self.width)
+    }
+
+    /// Returns a tuple containing the width and height.
+    pub const fn to_tuple(self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl From<(u32, u32)> for Dimensions {
+    fn from((width, height): (u32, u32)) -> Self {
+        Dimensions::new(width, height)
+    }
+}
+
+impl From<Dimensions> for (u32, u32) {
+    fn from(dimensions: Dimensions) -> Self {
+        dimensions.to_tuple()
+    }
+}
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_sizes() {
+        let dimensions = Dimensions::from((3, 2));
+        assert_eq!(dimensions.pixel_count(), 6);
+        assert_eq!(dimensions.byte_len(ColorType::Rgba16), Some(48));
+        assert_eq!(dimensions.transposed().to_tuple(), (2, 3));
+        assert_eq!(dimensions.to_string(), "3x2");
+        assert!(dimensions.check_non_empty().is_ok());
+
+        let huge = Dimensions::new(u32::MAX, u32::MAX);
+        assert_eq!(huge.byte_len(ColorType::L8), Some(huge.pixel_count()));
+        assert_eq!(huge.byte_len(ColorType::Rgba32F), None);
+
+        assert!(Dimensions::new(0, 5).is_empty());
+        assert!(Dimensions::new(5, 0).check_non_empty().is_err());
+    }
+}
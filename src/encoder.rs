@@ -0,0 +1,802 @@
+use crate::colortype::KNOWN_EXTENDED;
+use crate::quantize::Palette;
+use crate::{Cicp, ExtendedColorType, HdrMetadata, ImageError, ImageFormat, ImageResult, Progress};
+use crate::{ImageFormatHint, ParameterError, ParameterErrorKind};
+use crate::{ImageMetadata, MetadataKind};
+use crate::{UnsupportedError, UnsupportedErrorKind};
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// Format independent options for tuning an encoder.
+///
+/// Every setting is optional and unset by default, in which case the encoder chooses. Settings
+/// are hints in a common vocabulary, an encoder will interpret them on its own scale and silently
+/// ignore those that have no meaning for its format. Use `ImageEncoder::supports_setting` to find
+/// out which settings are actually honored.
+///
+/// # Examples
+///
+/// ```
+/// use image_core::{EncoderSettings, ScanMode};
+///
+/// let settings = EncoderSettings::new()
+///     .with_quality(80)
+///     .with_scan_mode(ScanMode::Progressive);
+/// assert_eq!(settings.quality(), Some(80));
+/// assert_eq!(settings.compression_level(), None);
+/// ```
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct EncoderSettings {
+    quality: Option<u8>,
+    compression_level: Option<u8>,
+    scan_mode: Option<ScanMode>,
+    deterministic: bool,
+}
+
+/// Identifies one of the well-known settings of `EncoderSettings`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncoderSettingKey {
+    /// The quality of lossy compression, see `EncoderSettings::with_quality`.
+    Quality,
+    /// The effort spent on compression, see `EncoderSettings::with_compression_level`.
+    CompressionLevel,
+    /// The order in which the image data is stored, see `EncoderSettings::with_scan_mode`.
+    ScanMode,
+    /// Byte-identical output for identical input, see `EncoderSettings::with_deterministic`.
+    Deterministic,
+}
+
+/// The order in which an encoder stores the image data.
+///
+/// Other orders than `Baseline` let viewers display a preview before the whole file loaded, at
+/// the cost of a slightly larger file.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScanMode {
+    /// The image is stored once, from top to bottom.
+    #[default]
+    Baseline,
+    /// The whole image is stored in several scans of increasing detail, as in progressive JPEG.
+    Progressive,
+    /// Subsets of the pixels are stored in several passes, as in interlaced PNG and GIF.
+    Interlaced,
+}
+
+/// The intended use of an encoded image, used to pick recommended settings.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncodingIntent {
+    /// Small files that display well in browsers, at a slight loss of quality.
+    Web,
+    /// Preserve as much of the image as the format allows, regardless of file size.
+    Archival,
+    /// Spend as little time as possible encoding.
+    Fastest,
+}
+
+impl EncoderSettings {
+    /// The highest value accepted by `with_quality`.
+    pub const MAX_QUALITY: u8 = 100;
+
+    /// The highest value accepted by `with_compression_level`.
+    pub const MAX_COMPRESSION_LEVEL: u8 = 9;
+
+    /// Create settings where every option is left to the encoder.
+    pub fn new() -> Self {
+        EncoderSettings::default()
+    }
+
+    /// Recommended settings for encoding an image in a format for the given purpose.
+    ///
+    /// The result only contains settings which are meaningful for the format, everything else is
+    /// left to the encoder. The exact values are not considered stable and may be tuned in
+    /// future versions.
+    pub fn recommended_for(format: ImageFormat, intent: EncodingIntent) -> Self {
+        let settings = EncoderSettings::new();
+        match (format, intent) {
+            (ImageFormat::Jpeg, EncodingIntent::Web) => settings
+                .with_quality(80)
+                .with_scan_mode(ScanMode::Progressive),
+            (ImageFormat::Jpeg, EncodingIntent::Archival) => {
+                settings.with_quality(95).with_scan_mode(ScanMode::Baseline)
+            }
+            (ImageFormat::Jpeg, EncodingIntent::Fastest) => {
+                settings.with_quality(75).with_scan_mode(ScanMode::Baseline)
+            }
+            (ImageFormat::WebP, EncodingIntent::Web) => {
+                settings.with_quality(80).with_compression_level(4)
+            }
+            (ImageFormat::WebP, EncodingIntent::Archival) => settings
+                .with_quality(Self::MAX_QUALITY)
+                .with_compression_level(Self::MAX_COMPRESSION_LEVEL),
+            (ImageFormat::WebP, EncodingIntent::Fastest) => {
+                settings.with_quality(75).with_compression_level(0)
+            }
+            // Interlacing makes PNG files noticeably larger.
+            (ImageFormat::Png, EncodingIntent::Web) => settings
+                .with_compression_level(Self::MAX_COMPRESSION_LEVEL)
+                .with_scan_mode(ScanMode::Baseline),
+            (ImageFormat::Png, EncodingIntent::Archival) => {
+                settings.with_compression_level(Self::MAX_COMPRESSION_LEVEL)
+            }
+            (ImageFormat::Png, EncodingIntent::Fastest) => settings.with_compression_level(1),
+            (ImageFormat::Tiff, EncodingIntent::Web) => settings.with_compression_level(6),
+            (ImageFormat::Tiff, EncodingIntent::Archival) => {
+                settings.with_compression_level(Self::MAX_COMPRESSION_LEVEL)
+            }
+            (ImageFormat::Tiff, EncodingIntent::Fastest) => settings.with_compression_level(0),
+            _ => settings,
+        }
+    }
+
+    /// Set the quality of lossy compression, from `0` (worst) to `100` (best).
+    ///
+    /// Larger values are clamped to `100`.
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = Some(quality.min(Self::MAX_QUALITY));
+        self
+    }
+
+    /// Set the effort spent on compression, from `0` (fastest) to `9` (smallest output).
+    ///
+    /// Larger values are clamped to `9`. This does not affect the quality of the image.
+    pub fn with_compression_level(mut self, level: u8) -> Self {
+        self.compression_level = Some(level.min(Self::MAX_COMPRESSION_LEVEL));
+        self
+    }
+
+    /// Set the order in which the image data is stored.
+    ///
+    /// Check `ImageEncoder::supports_scan_mode` for the modes an encoder can produce.
+    pub fn with_scan_mode(mut self, scan_mode: ScanMode) -> Self {
+        self.scan_mode = Some(scan_mode);
+        self
+    }
+
+    /// Request output which only depends on the image and the settings.
+    ///
+    /// Encoding the same input again then produces the same bytes, regardless of the time, the
+    /// machine or the number of threads. Encoders honoring this write fixed timestamps, order
+    /// chunks stably and compress in a way which does not depend on scheduling. Unlike other
+    /// settings this is a guarantee rather than a hint, check `ImageEncoder::supports_setting`
+    /// with `EncoderSettingKey::Deterministic` before relying on it.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// The requested quality of lossy compression, if any.
+    pub fn quality(&self) -> Option<u8> {
+        self.quality
+    }
+
+    /// The requested compression level, if any.
+    pub fn compression_level(&self) -> Option<u8> {
+        self.compression_level
+    }
+
+    /// The requested order of the image data, if any.
+    pub fn scan_mode(&self) -> Option<ScanMode> {
+        self.scan_mode
+    }
+
+    /// Whether deterministic output was requested.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Check if a setting has been specified.
+    ///
+    /// `EncoderSettingKey::Deterministic` counts as specified when deterministic output was
+    /// requested.
+    pub fn is_set(&self, key: EncoderSettingKey) -> bool {
+        match key {
+            EncoderSettingKey::Quality => self.quality.is_some(),
+            EncoderSettingKey::CompressionLevel => self.compression_level.is_some(),
+            EncoderSettingKey::ScanMode => self.scan_mode.is_some(),
+            EncoderSettingKey::Deterministic => self.deterministic,
+        }
+    }
+}
+
+/// The trait all encoders implement.
+pub trait ImageEncoder: Sized {
+    /// Writes all the bytes in an image to the writer.
+    ///
+    /// The pixel data is expected in the same layout as produced by `ImageDecoder::read_image`,
+    /// i.e. rows from top to bottom and samples wider than a byte in native endian. Rows of color
+    /// types with less than 8 bits per pixel are packed and padded to whole bytes, as documented
+    /// for `ExtendedColorType::row_bytes`. This allows passing data such as palette indices
+    /// through without expanding it.
+    ///
+    /// Color types the encoder can not write, see `supports_color`, result in an unsupported
+    /// error.
+    ///
+    /// # Panics
+    ///
+    /// Implementations may panic if `buf.len()` is not `color_type.buffer_size(width, height)`.
+    fn write_image<W: Write>(
+        self,
+        writer: W,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<()>;
+
+    /// Returns whether the encoder can write images of the given color type.
+    ///
+    /// The provided implementation accepts exactly those color types which correspond to a
+    /// `ColorType`.
+    fn supports_color(&self, color_type: ExtendedColorType) -> bool {
+        color_type.to_color_type().is_some()
+    }
+
+    /// Returns the color type closest to `color_type` which the encoder can write.
+    ///
+    /// This is `color_type` itself if it is supported. Otherwise conversions which keep alpha and
+    /// color are preferred, then those which keep the depth of samples, then the one that adds the
+    /// least data. Unknown and block compressed color types are only returned if supported
+    /// exactly. Returns `None` if no color type is supported.
+    fn nearest_supported_color(&self, color_type: ExtendedColorType) -> Option<ExtendedColorType> {
+        if self.supports_color(color_type) {
+            return Some(color_type);
+        }
+        if let ExtendedColorType::Unknown { .. } = color_type {
+            return None;
+        }
+        if color_type.is_block_compressed() {
+            return None;
+        }
+
+        KNOWN_EXTENDED
+            .iter()
+            .copied()
+            .filter(|&candidate| self.supports_color(candidate))
+            .min_by_key(|&candidate| conversion_cost(color_type, candidate))
+    }
+
+    /// Returns an upper bound of the number of bytes written by encoding an image.
+    ///
+    /// This lets applications reserve buffers or enforce quotas before encoding. Encoders of
+    /// uncompressed formats should return the exact size, those of compressed formats a bound
+    /// derived from the worst case of their compression, ignoring metadata. The provided
+    /// implementation returns `None`, meaning the size can not be determined up front.
+    fn estimate_output_size(
+        &self,
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> Option<u64> {
+        let _ = (width, height, color_type);
+        None
+    }
+
+    /// Encodes the image into a newly allocated vector of bytes.
+    ///
+    /// See `write_image` for the expected layout of `buf`.
+    fn encode_to_vec(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+    ) -> ImageResult<Vec<u8>> {
+        #[cfg(feature = "tracing")]
+        let _span = encode_span(width, height, color_type);
+        let mut encoded = Vec::new();
+        self.write_image(&mut encoded, buf, width, height, color_type)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(bytes = encoded.len(), "encoded image");
+        Ok(encoded)
+    }
+
+    /// Writes an image which is provided row by row.
+    ///
+    /// Each row must contain exactly `color_type.row_bytes(width)` bytes, in the layout
+    /// described for `write_image`, and exactly `height` rows must be provided. Rows can be
+    /// borrowed slices as well as owned buffers produced on demand, for example by a decoder.
+    ///
+    /// Block compressed color types are provided in rows of blocks instead, as many as
+    /// `color_type.blocks(width, height).1`.
+    ///
+    /// Encoders that can compress incrementally should override this to encode in constant
+    /// memory. The provided implementation collects all rows into a single buffer and then calls
+    /// `write_image`.
+    fn write_rows<W, I>(
+        self,
+        writer: W,
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+        rows: I,
+    ) -> ImageResult<()>
+    where
+        W: Write,
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = encode_span(width, height, color_type);
+        let row_len = color_type.row_bytes(width);
+        let (_, row_total) = color_type.blocks(width, height);
+        let mut buf = Vec::new();
+        let mut row_count = 0u32;
+
+        for row in rows {
+            let row = row.as_ref();
+            if row_count == row_total || row.len() as u64 != row_len {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(row = row_count, len = row.len(), "unexpected row");
+                return Err(dimension_mismatch());
+            }
+            buf.extend_from_slice(row);
+            row_count += 1;
+        }
+
+        if row_count != row_total {
+            return Err(dimension_mismatch());
+        }
+
+        self.write_image(writer, &buf, width, height, color_type)
+    }
+
+    /// Same as `write_image` but periodically calls the provided callback to give updates on
+    /// encoding progress.
+    ///
+    /// Progress is measured in bytes of `buf` handed to the encoder. The provided implementation
+    /// passes the image to `write_rows` and reports progress after each group of rows, of
+    /// roughly 4096 bytes, has been taken by the encoder.
+    fn write_image_with_progress<W: Write, F: Fn(Progress)>(
+        self,
+        writer: W,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ExtendedColorType,
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let total = buf.len() as u64;
+        let row_len =
+            usize::try_from(color_type.row_bytes(width)).map_err(|_| dimension_mismatch())?;
+        if row_len == 0 {
+            self.write_image(writer, buf, width, height, color_type)?;
+            progress_callback(Progress { current: 0, total });
+            return Ok(());
+        }
+
+        let rows_per_group = (4096 / row_len).max(1);
+        let rows = buf.chunks(row_len).enumerate().map(|(index, row)| {
+            let rows_taken = index + 1;
+            if rows_taken % rows_per_group == 0 || rows_taken * row_len == buf.len() {
+                progress_callback(Progress {
+                    current: (rows_taken * row_len) as u64,
+                    total,
+                });
+            }
+            row
+        });
+
+        self.write_rows(writer, width, height, color_type, rows)
+    }
+
+    /// Configure the encoder with format independent settings.
+    ///
+    /// Settings that are not supported are ignored. The provided implementation ignores all of
+    /// them. An error should only be returned when a supported setting can not be applied.
+    fn apply_settings(&mut self, settings: &EncoderSettings) -> ImageResult<()> {
+        let _ = settings;
+        Ok(())
+    }
+
+    /// Returns whether the encoder honors a setting passed to `apply_settings`.
+    ///
+    /// For `EncoderSettingKey::Deterministic` this reports whether the encoder guarantees
+    /// byte-identical output when it is requested.
+    fn supports_setting(&self, key: EncoderSettingKey) -> bool {
+        let _ = key;
+        false
+    }
+
+    /// Returns whether the encoder can store the image data in the given order.
+    ///
+    /// Encoders which honor `EncoderSettingKey::ScanMode` return an unsupported error from
+    /// `apply_settings` for the modes they can not produce. The provided implementation only
+    /// supports `ScanMode::Baseline`.
+    fn supports_scan_mode(&self, mode: ScanMode) -> bool {
+        mode == ScanMode::Baseline
+    }
+
+    /// Returns whether the encoder can embed a kind of metadata into the encoded image.
+    ///
+    /// The provided implementation supports no metadata. Encoders overriding any of the methods
+    /// setting metadata should override this as well.
+    fn supports_metadata(&self, kind: MetadataKind) -> bool {
+        let _ = kind;
+        false
+    }
+
+    /// Embed all supported metadata into the encoded image.
+    ///
+    /// Kinds of metadata for which `supports_metadata` is `false` are skipped, so that the metadata
+    /// of a decoded image can be passed on without checking the capabilities of the encoder. The
+    /// provided implementation calls the method setting each kind that is present and supported.
+    fn set_metadata(&mut self, metadata: &ImageMetadata) -> ImageResult<()> {
+        if let Some(profile) = metadata.icc_profile() {
+            if self.supports_metadata(MetadataKind::IccProfile) {
+                self.set_icc_profile(profile.to_vec())?;
+            }
+        }
+        if let Some(exif) = metadata.exif() {
+            if self.supports_metadata(MetadataKind::Exif) {
+                self.set_exif(exif.to_vec())?;
+            }
+        }
+        if let Some(xmp) = metadata.xmp() {
+            if self.supports_metadata(MetadataKind::Xmp) {
+                self.set_xmp(xmp.to_vec())?;
+            }
+        }
+        if let Some(cicp) = metadata.cicp() {
+            if self.supports_metadata(MetadataKind::Cicp) {
+                self.set_cicp(cicp)?;
+            }
+        }
+        if let Some(hdr) = metadata.hdr_metadata() {
+            if self.supports_metadata(MetadataKind::Hdr) {
+                self.set_hdr_metadata(hdr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Embed an ICC profile into the encoded image.
+    ///
+    /// The provided implementation returns an unsupported error, for encoders of formats which
+    /// can not store a profile.
+    fn set_icc_profile(&mut self, profile: Vec<u8>) -> ImageResult<()> {
+        let _ = profile;
+        Err(unsupported_feature("ICC profiles"))
+    }
+
+    /// Embed an EXIF block into the encoded image.
+    ///
+    /// The block is stored as given, starting with the TIFF header. The provided implementation
+    /// returns an unsupported error, for encoders of formats which can not store EXIF.
+    fn set_exif(&mut self, exif: Vec<u8>) -> ImageResult<()> {
+        let _ = exif;
+        Err(unsupported_feature("EXIF metadata"))
+    }
+
+    /// Embed an XMP packet into the encoded image.
+    ///
+    /// The provided implementation returns an unsupported error, for encoders of formats which
+    /// can not store XMP.
+    fn set_xmp(&mut self, xmp: Vec<u8>) -> ImageResult<()> {
+        let _ = xmp;
+        Err(unsupported_feature("XMP metadata"))
+    }
+
+    /// Embed HDR mastering metadata into the encoded image.
+    ///
+    /// The provided implementation returns an unsupported error, for encoders of formats which
+    /// can not store the metadata.
+    fn set_hdr_metadata(&mut self, metadata: &HdrMetadata) -> ImageResult<()> {
+        let _ = metadata;
+        Err(unsupported_feature("HDR metadata"))
+    }
+
+    /// Describe the color space of the encoded image by code points.
+    ///
+    /// The provided implementation returns an unsupported error, for encoders of formats which
+    /// can not store code points.
+    fn set_cicp(&mut self, cicp: Cicp) -> ImageResult<()> {
+        let _ = cicp;
+        Err(unsupported_feature("CICP color description"))
+    }
+
+    /// Use a palette for the encoded image.
+    ///
+    /// Afterwards `write_image` accepts indices into the palette of `quantize::INDEX_COLOR_TYPE`,
+    /// as produced by `quantize::map_to_palette`. The provided implementation returns an
+    /// unsupported error, for encoders of formats without palettes.
+    fn set_palette(&mut self, palette: &Palette) -> ImageResult<()> {
+        let _ = palette;
+        Err(unsupported_feature("palettes"))
+    }
+}
+
+/// Orders conversions by the information they lose, then by the data they add.
+fn conversion_cost(from: ExtendedColorType, to: ExtendedColorType) -> (bool, bool, u8, u16) {
+    let has_color = |color: ExtendedColorType| color.channel_count() >= 3;
+    let bits_per_sample =
+        |color: ExtendedColorType| color.bits_per_pixel() / u16::from(color.channel_count());
+
+    let drops_channels =
+        (from.has_alpha() && !to.has_alpha()) || (has_color(from) && !has_color(to));
+    let loses_depth = bits_per_sample(to) < bits_per_sample(from);
+    let added_channels = to.channel_count().saturating_sub(from.channel_count());
+    let bits_difference = bits_per_sample(to).abs_diff(bits_per_sample(from));
+    (drops_channels, loses_depth, added_channels, bits_difference)
+}
+
+/// Enter a span describing an image that is encoded.
+#[cfg(feature = "tracing")]
+fn encode_span(width: u32, height: u32, color: ExtendedColorType) -> tracing::span::EnteredSpan {
+    tracing::debug_span!("encode", width, height, color = ?color).entered()
+}
+
+fn unsupported_feature(feature: &str) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormatHint::Unknown,
+        UnsupportedErrorKind::GenericFeature(feature.into()),
+    ))
+}
+
+fn dimension_mismatch() -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::DimensionMismatch,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the raw pixel data.
+    struct RawEncoder;
+
+    impl ImageEncoder for RawEncoder {
+        fn write_image<W: Write>(
+            self,
+            mut writer: W,
+            buf: &[u8],
+            width: u32,
+            height: u32,
+            color_type: ExtendedColorType,
+        ) -> ImageResult<()> {
+            assert_eq!(
+                Some(buf.len() as u64),
+                color_type.buffer_size(width, height)
+            );
+            writer.write_all(buf)?;
+            Ok(())
+        }
+
+        fn supports_color(&self, _: ExtendedColorType) -> bool {
+            true
+        }
+
+        fn estimate_output_size(
+            &self,
+            width: u32,
+            height: u32,
+            color_type: ExtendedColorType,
+        ) -> Option<u64> {
+            color_type.buffer_size(width, height)
+        }
+    }
+
+    #[test]
+    fn write_rows_collects_rows() {
+        let rows = [[1u8, 2, 3], [4, 5, 6]];
+        let mut encoded = Vec::new();
+        RawEncoder
+            .write_rows(&mut encoded, 1, 2, ExtendedColorType::Rgb8, rows.iter())
+            .unwrap();
+        assert_eq!(encoded, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn write_image_with_progress_reports_all_bytes() {
+        use std::cell::RefCell;
+
+        let buf = vec![0u8; 300 * 20];
+        let reported = RefCell::new(Vec::new());
+        RawEncoder
+            .write_image_with_progress(Vec::new(), &buf, 100, 20, ExtendedColorType::Rgb8, |p| {
+                reported.borrow_mut().push(p.current)
+            })
+            .unwrap();
+        assert_eq!(reported.into_inner(), [3900, 6000]);
+    }
+
+    #[test]
+    fn writes_rows_of_blocks() {
+        let buf: Vec<u8> = (0..32).collect();
+        let mut encoded = Vec::new();
+        RawEncoder
+            .write_image_with_progress(&mut encoded, &buf, 8, 8, ExtendedColorType::Bc1, |_| {})
+            .unwrap();
+        assert_eq!(encoded, buf);
+
+        // A partial block row covers the last rows of pixels.
+        let rows = buf.chunks(16).take(2);
+        assert!(RawEncoder
+            .write_rows(Vec::new(), 8, 5, ExtendedColorType::Bc1, rows.clone())
+            .is_ok());
+        assert!(RawEncoder
+            .write_rows(Vec::new(), 8, 9, ExtendedColorType::Bc1, rows)
+            .is_err());
+    }
+
+    #[test]
+    fn write_rows_passes_packed_rows() {
+        let rows = [[0b1010_1010u8, 0b1000_0000], [0b0101_0101, 0]];
+        let mut encoded = Vec::new();
+        RawEncoder
+            .write_rows(&mut encoded, 9, 2, ExtendedColorType::L1, rows.iter())
+            .unwrap();
+        assert_eq!(encoded.len(), 4);
+
+        struct ByteEncoder;
+        impl ImageEncoder for ByteEncoder {
+            fn write_image<W: Write>(
+                self,
+                _: W,
+                _: &[u8],
+                _: u32,
+                _: u32,
+                _: ExtendedColorType,
+            ) -> ImageResult<()> {
+                Ok(())
+            }
+        }
+        assert!(ByteEncoder.supports_color(ExtendedColorType::Rgba16));
+        assert!(!ByteEncoder.supports_color(ExtendedColorType::L1));
+    }
+
+    #[test]
+    fn finds_nearest_supported_color() {
+        struct Rgb8Encoder;
+        impl ImageEncoder for Rgb8Encoder {
+            fn write_image<W: Write>(
+                self,
+                _: W,
+                _: &[u8],
+                _: u32,
+                _: u32,
+                _: ExtendedColorType,
+            ) -> ImageResult<()> {
+                Ok(())
+            }
+
+            fn supports_color(&self, color_type: ExtendedColorType) -> bool {
+                matches!(
+                    color_type,
+                    ExtendedColorType::Rgb8 | ExtendedColorType::Rgba8
+                )
+            }
+        }
+
+        let nearest = |color| Rgb8Encoder.nearest_supported_color(color);
+        assert_eq!(
+            nearest(ExtendedColorType::Rgb8),
+            Some(ExtendedColorType::Rgb8)
+        );
+        assert_eq!(
+            nearest(ExtendedColorType::L8),
+            Some(ExtendedColorType::Rgb8)
+        );
+        assert_eq!(
+            nearest(ExtendedColorType::La8),
+            Some(ExtendedColorType::Rgba8)
+        );
+        assert_eq!(
+            nearest(ExtendedColorType::Rgba16),
+            Some(ExtendedColorType::Rgba8)
+        );
+        assert_eq!(
+            nearest(ExtendedColorType::Rgb32F),
+            Some(ExtendedColorType::Rgb8)
+        );
+        assert_eq!(
+            nearest(ExtendedColorType::Unknown {
+                bits_per_sample: 8,
+                channels: 1
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn write_rows_checks_row_count() {
+        let rows = vec![vec![0u8; 2]; 3];
+        assert!(RawEncoder
+            .write_rows(Vec::new(), 1, 2, ExtendedColorType::La8, rows.iter())
+            .is_err());
+        assert!(RawEncoder
+            .write_rows(Vec::new(), 1, 4, ExtendedColorType::La8, rows.iter())
+            .is_err());
+    }
+
+    #[test]
+    fn estimates_output_size() {
+        let color = ExtendedColorType::Rgb4;
+        let estimate = RawEncoder.estimate_output_size(3, 2, color);
+        let encoded = RawEncoder.encode_to_vec(&[0; 10], 3, 2, color).unwrap();
+        assert_eq!(estimate, Some(encoded.len() as u64));
+    }
+
+    #[test]
+    fn metadata_is_unsupported_by_default() {
+        let err = RawEncoder
+            .set_hdr_metadata(&HdrMetadata::default())
+            .unwrap_err();
+        assert!(matches!(err, ImageError::Unsupported(_)));
+
+        let err = RawEncoder.set_palette(&Palette::default()).unwrap_err();
+        assert!(matches!(err, ImageError::Unsupported(_)));
+    }
+
+    #[test]
+    fn recommends_scan_modes() {
+        let web = EncoderSettings::recommended_for(ImageFormat::Jpeg, EncodingIntent::Web);
+        assert_eq!(web.scan_mode(), Some(ScanMode::Progressive));
+        assert!(web.is_set(EncoderSettingKey::ScanMode));
+
+        let png = EncoderSettings::recommended_for(ImageFormat::Png, EncodingIntent::Archival);
+        assert!(!png.is_set(EncoderSettingKey::ScanMode));
+
+        assert!(RawEncoder.supports_scan_mode(ScanMode::Baseline));
+        assert!(!RawEncoder.supports_scan_mode(ScanMode::Interlaced));
+        assert!(!RawEncoder.supports_setting(EncoderSettingKey::ScanMode));
+    }
+
+    #[test]
+    fn requests_deterministic_output() {
+        let settings = EncoderSettings::new();
+        assert!(!settings.is_deterministic());
+        assert!(!settings.is_set(EncoderSettingKey::Deterministic));
+
+        let settings = settings.with_quality(90).with_deterministic(true);
+        assert!(settings.is_deterministic());
+        assert!(settings.is_set(EncoderSettingKey::Deterministic));
+        assert_ne!(settings, EncoderSettings::new().with_quality(90));
+        assert!(!RawEncoder.supports_setting(EncoderSettingKey::Deterministic));
+    }
+
+    #[test]
+    fn embeds_supported_metadata() {
+        /// Keeps an ICC profile, and nothing else.
+        #[derive(Default)]
+        struct IccEncoder(Option<Vec<u8>>);
+
+        impl ImageEncoder for IccEncoder {
+            fn write_image<W: Write>(
+                self,
+                _: W,
+                _: &[u8],
+                _: u32,
+                _: u32,
+                _: ExtendedColorType,
+            ) -> ImageResult<()> {
+                Ok(())
+            }
+
+            fn supports_metadata(&self, kind: MetadataKind) -> bool {
+                kind == MetadataKind::IccProfile
+            }
+
+            fn set_icc_profile(&mut self, profile: Vec<u8>) -> ImageResult<()> {
+                self.0 = Some(profile);
+                Ok(())
+            }
+        }
+
+        let metadata = ImageMetadata::new()
+            .with_icc_profile(b"profile".to_vec())
+            .with_exif(b"MM\0*".to_vec())
+            .with_cicp(Cicp::SRGB);
+        let mut encoder = IccEncoder::default();
+        encoder.set_metadata(&metadata).unwrap();
+        assert_eq!(encoder.0.as_deref(), Some(&b"profile"[..]));
+        assert!(encoder.set_exif(b"MM\0*".to_vec()).is_err());
+
+        let mut encoder = RawEncoder;
+        encoder.set_metadata(&metadata).unwrap();
+        assert!(encoder.set_icc_profile(Vec::new()).is_err());
+    }
+}
@@ -14,11 +14,12 @@
 //! [`ImageError`]: enum.ImageError.html
 
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::{fmt, io};
 
 use crate::ExtendedColorType;
 use crate::ImageFormat;
-use crate::NonExhaustiveMarker;
+use crate::Rect;
 
 /// The generic error type for image operations.
 ///
@@ -65,6 +66,28 @@ pub enum ImageError {
     IoError(io::Error),
 }
 
+/// A coarse classification of an `ImageError`.
+///
+/// Unlike the messages and sources of errors, the kind of an error is part of the stable
+/// interface of this crate. Every variant of `ImageError` maps to a fixed kind, so matching on it
+/// keeps working across versions. New kinds may be added for new variants of `ImageError`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input is not a valid image of its format, see `ImageError::Decoding`.
+    CorruptData,
+    /// The image could not be encoded, see `ImageError::Encoding`.
+    EncodingFailed,
+    /// The arguments to an operation were invalid, see `ImageError::Parameter`.
+    InvalidParameter,
+    /// A resource limit would have been exceeded, see `ImageError::Limits`.
+    LimitExceeded,
+    /// The operation is not supported, see `ImageError::Unsupported`.
+    UnsupportedFeature,
+    /// Interacting with the environment failed, see `ImageError::IoError`.
+    Io,
+}
+
 /// The implementation for an operation was not provided.
 ///
 /// See the variant [`Unsupported`] for more documentation.
@@ -78,6 +101,7 @@ pub struct UnsupportedError {
 
 /// Details what feature is not supported.
 #[derive(Clone, Debug, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum UnsupportedErrorKind {
     /// The required color type can not be handled.
     Color(ExtendedColorType),
@@ -86,8 +110,6 @@ pub enum UnsupportedErrorKind {
     /// Some feature specified by string.
     /// This is discouraged and is likely to get deprecated (but not removed).
     GenericFeature(String),
-    #[doc(hidden)]
-    __NonExhaustive(NonExhaustiveMarker),
 }
 
 /// An error was encountered while encoding an image.
@@ -116,6 +138,7 @@ pub struct ParameterError {
 
 /// Details how a parameter is malformed.
 #[derive(Clone, Debug, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum ParameterErrorKind {
     /// The dimensions passed are wrong.
     DimensionMismatch,
@@ -126,9 +149,15 @@ pub enum ParameterErrorKind {
     Generic(String),
     /// The end of the image has been reached.
     NoMoreData,
-    #[doc(hidden)]
-    /// Do not use this, not part of stability guarantees.
-    __NonExhaustive(NonExhaustiveMarker),
+    /// A rectangle does not lie within the bounds of the image.
+    RectOutOfBounds {
+        /// The requested rectangle.
+        rect: Rect,
+        /// The width of the image.
+        image_width: u32,
+        /// The height of the image.
+        image_height: u32,
+    },
 }
 
 /// An error was encountered while decoding an image.
@@ -162,18 +191,52 @@ pub struct LimitError {
 /// detailed information or to incorporate other resources types.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 #[allow(missing_copy_implementations)] // Might be non-Copy in the future.
+#[non_exhaustive]
 pub enum LimitErrorKind {
     /// The resulting image exceed dimension limits in either direction.
     DimensionError,
     /// The operation would have performed an allocation larger than allowed.
     InsufficientMemory,
-    #[doc(hidden)]
-    /// Do not use this, not part of stability guarantees.
-    __NonExhaustive(NonExhaustiveMarker),
+    /// The input is longer than the number of bytes allowed to be read.
+    InputTooLarge,
+}
+
+/// An `ImageError` together with the input and the operation that caused it.
+///
+/// The context is kept beside the error, which can be inspected with `error` or recovered with
+/// `into_error`. Converting it into an `ImageError` drops the context. See
+/// `ImageError::with_context`.
+#[derive(Debug)]
+pub struct ContextError {
+    // Boxed to keep results with a context as small as other results.
+    inner: Box<Context>,
+}
+
+#[derive(Debug)]
+struct Context {
+    format: ImageFormatHint,
+    operation: Operation,
+    path: Option<PathBuf>,
+    error: ImageError,
+}
+
+/// The operation which was performed on an input when an error occurred.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Opening the input, such as a file.
+    Open,
+    /// Detecting the format of the input.
+    DetectFormat,
+    /// Decoding the image.
+    Decode,
+    /// Encoding the image.
+    Encode,
 }
 
 /// A best effort representation for image formats.
 #[derive(Clone, Debug, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum ImageFormatHint {
     /// The format is known exactly.
     Exact(ImageFormat),
@@ -186,9 +249,95 @@ pub enum ImageFormatHint {
 
     /// The format is not known or could not be determined.
     Unknown,
+}
+
+impl ImageError {
+    /// Returns the stable classification of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ImageError::Decoding(_) => ErrorKind::CorruptData,
+            ImageError::Encoding(_) => ErrorKind::EncodingFailed,
+            ImageError::Parameter(_) => ErrorKind::InvalidParameter,
+            ImageError::Limits(_) => ErrorKind::LimitExceeded,
+            ImageError::Unsupported(_) => ErrorKind::UnsupportedFeature,
+            ImageError::IoError(_) => ErrorKind::Io,
+        }
+    }
+
+    /// Attach the format of the input and the operation that failed to the error.
+    pub fn with_context(self, format: ImageFormatHint, operation: Operation) -> ContextError {
+        ContextError {
+            inner: Box::new(Context {
+                format,
+                operation,
+                path: None,
+                error: self,
+            }),
+        }
+    }
+
+    /// Returns the IO error that caused this error, if any.
+    ///
+    /// This is the error of an `IoError` variant or, for other variants, the first IO error in the
+    /// chain of sources. IO errors are passed on unchanged as a guarantee of this crate, so their
+    /// `kind` can be inspected to decide whether retrying an operation may succeed.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        if let ImageError::IoError(err) = self {
+            return Some(err);
+        }
 
-    #[doc(hidden)]
-    __NonExhaustive(NonExhaustiveMarker),
+        let mut source = self.source();
+        while let Some(err) = source {
+            if let Some(err) = err.downcast_ref::<io::Error>() {
+                return Some(err);
+            }
+            source = err.source();
+        }
+        None
+    }
+}
+
+impl ContextError {
+    /// Attach the path of the input, if it was read from a file.
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.inner.path = Some(path.into());
+        self
+    }
+
+    /// The format of the input, as far as it was known.
+    pub fn format_hint(&self) -> &ImageFormatHint {
+        &self.inner.format
+    }
+
+    /// The operation that failed.
+    pub fn operation(&self) -> Operation {
+        self.inner.operation
+    }
+
+    /// The path of the input, if it was read from a file.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// The error that occurred.
+    pub fn error(&self) -> &ImageError {
+        &self.inner.error
+    }
+
+    /// Returns the error without its context.
+    pub fn into_error(self) -> ImageError {
+        self.inner.error
+    }
+
+    /// Returns the stable classification of the error, see `ImageError::kind`.
+    pub fn kind(&self) -> ErrorKind {
+        self.inner.error.kind()
+    }
+
+    /// Returns the IO error that caused the error, if any, see `ImageError::io_error`.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.inner.error.io_error()
+    }
 }
 
 impl UnsupportedError {
@@ -298,8 +447,25 @@ impl LimitError {
 }
 
 impl From<io::Error> for ImageError {
+    /// Wrap an I/O error.
+    ///
+    /// Errors carrying a `LimitError`, such as those of a `LimitedReader`, are unwrapped into
+    /// `ImageError::Limits` instead.
     fn from(err: io::Error) -> ImageError {
-        ImageError::IoError(err)
+        match err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<LimitError>())
+        {
+            Some(limit) => ImageError::Limits(LimitError::from_kind(limit.kind())),
+            None => ImageError::IoError(err),
+        }
+    }
+}
+
+impl From<ContextError> for ImageError {
+    /// Returns the error without its context.
+    fn from(err: ContextError) -> ImageError {
+        err.inner.error
     }
 }
 
@@ -356,6 +522,34 @@ impl Error for ImageError {
     }
 }
 
+impl fmt::Display for ContextError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.inner.operation {
+            Operation::Open => write!(fmt, "Failed to open ")?,
+            Operation::DetectFormat => write!(fmt, "Failed to detect the format of ")?,
+            Operation::Decode => write!(fmt, "Failed to decode ")?,
+            Operation::Encode => write!(fmt, "Failed to encode ")?,
+        }
+        match &self.inner.path {
+            Some(path) => write!(fmt, "{}", path.display())?,
+            None => write!(fmt, "the image")?,
+        }
+        // The extension of a path is already part of the message.
+        match (&self.inner.format, &self.inner.path) {
+            (ImageFormatHint::Unknown, _) | (ImageFormatHint::PathExtension(_), Some(_)) => {}
+            (format, _) => write!(fmt, " as {}", format)?,
+        }
+        write!(fmt, ": {}", self.inner.error)
+    }
+}
+
+impl Error for ContextError {
+    /// Returns the source of the wrapped error, whose message is part of that of the context.
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.inner.error.source()
+    }
+}
+
 impl fmt::Display for UnsupportedError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match &self.kind {
@@ -387,7 +581,6 @@ impl fmt::Display for UnsupportedError {
                     other, message,
                 ),
             },
-            UnsupportedErrorKind::__NonExhaustive(marker) => match marker._private {},
         }
     }
 }
@@ -410,7 +603,15 @@ impl fmt::Display for ParameterError {
                 write!(fmt, "The parameter is malformed: {}", message,)
             }
             ParameterErrorKind::NoMoreData => write!(fmt, "The end of the image has been reached",),
-            ParameterErrorKind::__NonExhaustive(marker) => match marker._private {},
+            ParameterErrorKind::RectOutOfBounds {
+                rect,
+                image_width,
+                image_height,
+            } => write!(
+                fmt,
+                "The rectangle of {}x{} pixels at ({}, {}) exceeds the image of {}x{} pixels",
+                rect.width, rect.height, rect.x, rect.y, image_width, image_height,
+            ),
         }?;
 
         if let Some(underlying) = &self.underlying {
@@ -491,7 +692,7 @@ impl fmt::Display for LimitError {
         match self.kind {
             LimitErrorKind::InsufficientMemory => write!(fmt, "Insufficient memory"),
             LimitErrorKind::DimensionError => write!(fmt, "Image is too large"),
-            LimitErrorKind::__NonExhaustive(marker) => match marker._private {},
+            LimitErrorKind::InputTooLarge => write!(fmt, "Input is too large"),
         }
     }
 }
@@ -505,7 +706,6 @@ impl fmt::Display for ImageFormatHint {
             ImageFormatHint::Name(name) => write!(fmt, "`{}`", name),
             ImageFormatHint::PathExtension(ext) => write!(fmt, "`.{:?}`", ext),
             ImageFormatHint::Unknown => write!(fmt, "`Unknown`"),
-            ImageFormatHint::__NonExhaustive(marker) => match marker._private {},
         }
     }
 }
@@ -525,4 +725,62 @@ mod tests {
 
         assert_send_sync::<ImageError>();
     }
+
+    #[test]
+    fn error_kinds() {
+        let err = ImageError::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert_eq!(err.kind(), ErrorKind::Io);
+
+        let err = ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory));
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+
+        let limit = LimitError::from_kind(LimitErrorKind::InputTooLarge);
+        let err = ImageError::from(io::Error::other(limit));
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+
+        let err = ImageError::Unsupported(ImageFormatHint::Unknown.into());
+        assert_eq!(err.kind(), ErrorKind::UnsupportedFeature);
+    }
+
+    #[test]
+    fn io_error_is_preserved() {
+        let err = ImageError::from(io::Error::from(io::ErrorKind::UnexpectedEof));
+        assert_eq!(
+            err.io_error().map(io::Error::kind),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+
+        let interrupted = io::Error::from(io::ErrorKind::Interrupted);
+        let err = ImageError::Decoding(DecodingError::new(ImageFormatHint::Unknown, interrupted));
+        assert_eq!(
+            err.io_error().map(io::Error::kind),
+            Some(io::ErrorKind::Interrupted)
+        );
+
+        let err = ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            "not io".into(),
+        )));
+        assert!(err.io_error().is_none());
+    }
+
+    #[test]
+    fn context_wraps_errors() {
+        let err = ImageError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+            .with_context(ImageFormat::Png.into(), Operation::Decode)
+            .with_path("image.png");
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(err.io_error().is_some());
+        assert_eq!(err.operation(), Operation::Decode);
+        assert_eq!(err.path(), Some(Path::new("image.png")));
+        assert_eq!(
+            err.to_string(),
+            "Failed to decode image.png as Png: unexpected end of file"
+        );
+        assert!(matches!(ImageError::from(err), ImageError::IoError(_)));
+
+        let err = ImageError::Limits(LimitError::from_kind(LimitErrorKind::InputTooLarge))
+            .with_context(ImageFormatHint::Unknown, Operation::Encode);
+        assert_eq!(err.kind(), ErrorKind::LimitExceeded);
+        assert!(err.to_string().starts_with("Failed to encode the image: "));
+    }
 }
@@ -0,0 +1,408 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::mem;
+
+use crate::convert::convert_color;
+use crate::error::{ImageError, ParameterError, ParameterErrorKind};
+use crate::limits::insufficient_memory;
+use crate::{AlphaMode, Cicp, DecodeFidelity, HdrMetadata, ImageResult, Limits};
+use crate::{Channel, ColorType, ExtendedColorType, ImageDecoder, RowOrder};
+
+/// A transformation of the pixels of an image which is applied row by row.
+///
+/// Filters are chained in a `FilteredDecoder`. Each filter may change the width and the color
+/// type of the rows, but produces exactly one row for each row it is given. Filters are `Send`, so
+/// that filtered decodes can be moved to another thread.
+pub trait ScanlineFilter: Send {
+    /// The width and color type of the rows produced from rows of `width` pixels of `color`.
+    ///
+    /// Returns an error if the filter can not process such rows.
+    fn output_format(&self, width: u32, color: ColorType) -> ImageResult<(u32, ColorType)>;
+
+    /// Filter a row of pixels of the color type `color`.
+    ///
+    /// The input has the width the filter was configured for, `output` has the size of a row in
+    /// the format returned by `output_format`.
+    fn filter_row(&mut self, input: &[u8], color: ColorType, output: &mut [u8]);
+}
+
+/// A decoder adapter applying a chain of `ScanlineFilter`s to every row.
+///
+/// The filters are applied in the order they are added, while reading. Besides the row of the
+/// inner decoder only a single intermediate row is held in memory, regardless of the length of
+/// the chain. Without filters the rows are passed through.
+pub struct FilteredDecoder<D> {
+    inner: D,
+    stages: Vec<Stage>,
+    width: u32,
+    color: ColorType,
+}
+
+/// The reader of a `FilteredDecoder`.
+pub struct FilteredReader<R> {
+    inner: R,
+    chain: Option<Chain>,
+    /// The rows of the inner decoder which have not been read yet.
+    remaining_rows: u32,
+    /// The length of the filtered row at the start of `Chain::row`.
+    output_len: usize,
+    position: usize,
+}
+
+struct Stage {
+    filter: Box<dyn ScanlineFilter>,
+    /// The color type of the rows passed to the filter.
+    color: ColorType,
+    /// The length of the rows produced by the filter.
+    output_len: u64,
+}
+
+struct Chain {
+    stages: Vec<Stage>,
+    source_len: usize,
+    /// The current row, swapped with `scratch` after every filter.
+    row: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl<'a, D: ImageDecoder<'a>> FilteredDecoder<D> {
+    /// Wrap a decoder without any filters.
+    pub fn new(decoder: D) -> Self {
+        let (width, _) = decoder.dimensions();
+        let color = decoder.color_type();
+        FilteredDecoder {
+            inner: decoder,
+            stages: Vec::new(),
+            width,
+            color,
+        }
+    }
+
+    /// Append a filter to the chain.
+    ///
+    /// Returns the error of `ScanlineFilter::output_format` if the filter can not process the
+    /// rows produced by the chain so far.
+    pub fn with_filter<F: ScanlineFilter + 'static>(mut self, filter: F) -> ImageResult<Self> {
+        let (width, color) = filter.output_format(self.width, self.color)?;
+        self.stages.push(Stage {
+            filter: Box::new(filter),
+            color: self.color,
+            output_len: row_len(width, color),
+        });
+        self.width = width;
+        self.color = color;
+        Ok(self)
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Whether any filter produces gray rows from color rows, or color rows from gray ones.
+    fn changes_color_model(&self) -> bool {
+        let gray = |color: ColorType| color.layout().position(Channel::Luma).is_some();
+        let source = gray(self.inner.color_type());
+        let outputs = self.stages.iter().skip(1).map(|stage| stage.color);
+        outputs
+            .chain(Some(self.color))
+            .any(|color| gray(color) != source)
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for FilteredDecoder<D> {
+    type Reader = FilteredReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.inner.dimensions().1)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    /// Returns a lossy conversion if any filter reduces the color type it is given.
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        let colors = self.stages.iter().map(|stage| stage.color);
+        let outputs = colors.clone().skip(1).chain(Some(self.color));
+        let mut fidelity = self.inner.decode_fidelity();
+        for (from, to) in colors.zip(outputs).filter(|(from, to)| from != to) {
+            match DecodeFidelity::estimate(from.into(), to) {
+                DecodeFidelity::LossyConversion => return DecodeFidelity::LossyConversion,
+                _ if fidelity == DecodeFidelity::Native => {
+                    fidelity = DecodeFidelity::LosslessConversion;
+                }
+                _ => {}
+            }
+        }
+        fidelity
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    /// Returns `None` if a filter converts between gray and color pixels.
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp().filter(|_| !self.changes_color_model())
+    }
+
+    /// Returns `None` if a filter converts between gray and color pixels, the profile of the
+    /// inner decoder does not describe such rows.
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner
+            .icc_profile()
+            .filter(|_| !self.changes_color_model())
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        match self.stages.last() {
+            Some(stage) => stage.output_len,
+            None => self.inner.scanline_bytes(),
+        }
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let (width, height) = self.inner.dimensions();
+        let chain = if self.stages.is_empty() {
+            None
+        } else {
+            let source_len = row_len(width, self.inner.color_type());
+            let lengths = self.stages.iter().map(|stage| stage.output_len);
+            let longest = lengths.fold(source_len, u64::max);
+            self.inner.limits().check_alloc(longest.saturating_mul(2))?;
+            let longest = usize::try_from(longest).map_err(|_| insufficient_memory())?;
+            Some(Chain {
+                stages: self.stages,
+                source_len: source_len as usize,
+                row: vec![0; longest],
+                scratch: vec![0; longest],
+            })
+        };
+
+        Ok(FilteredReader {
+            inner: self.inner.into_reader()?,
+            chain,
+            remaining_rows: height,
+            output_len: 0,
+            position: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for FilteredReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chain = match &mut self.chain {
+            Some(chain) => chain,
+            None => return self.inner.read(buf),
+        };
+
+        while self.position == self.output_len {
+            if self.remaining_rows == 0 {
+                return Ok(0);
+            }
+
+            self.inner.read_exact(&mut chain.row[..chain.source_len])?;
+            self.remaining_rows -= 1;
+            self.output_len = chain.apply();
+            self.position = 0;
+        }
+
+        let available = &chain.row[self.position..self.output_len];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Chain {
+    /// Filter the source row, returns the length of the result at the start of `row`.
+    fn apply(&mut self) -> usize {
+        let mut len = self.source_len;
+        for stage in &mut self.stages {
+            // Every length fits into the buffers, which hold the longest of them.
+            let output_len = stage.output_len as usize;
+            let output = &mut self.scratch[..output_len];
+            stage
+                .filter
+                .filter_row(&self.row[..len], stage.color, output);
+            mem::swap(&mut self.row, &mut self.scratch);
+            len = output_len;
+        }
+        len
+    }
+}
+
+fn row_len(width: u32, color: ColorType) -> u64 {
+    u64::from(width) * u64::from(color.bytes_per_pixel())
+}
+
+/// A filter converting rows to another color type with `convert::convert_color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConvertColor {
+    color: ColorType,
+}
+
+impl ConvertColor {
+    /// Create a filter producing pixels of the color type.
+    pub fn new(color: ColorType) -> Self {
+        ConvertColor { color }
+    }
+}
+
+impl ScanlineFilter for ConvertColor {
+    fn output_format(&self, width: u32, _: ColorType) -> ImageResult<(u32, ColorType)> {
+        Ok((width, self.color))
+    }
+
+    fn filter_row(&mut self, input: &[u8], color: ColorType, output: &mut [u8]) {
+        convert_color(input, color, output, self.color);
+    }
+}
+
+/// A filter mirroring rows, so that the left edge becomes the right one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlipHorizontal;
+
+impl ScanlineFilter for FlipHorizontal {
+    fn output_format(&self, width: u32, color: ColorType) -> ImageResult<(u32, ColorType)> {
+        Ok((width, color))
+    }
+
+    fn filter_row(&mut self, input: &[u8], color: ColorType, output: &mut [u8]) {
+        let bpp = usize::from(color.bytes_per_pixel());
+        let pixels = input.chunks_exact(bpp).rev();
+        for (pixel, target) in pixels.zip(output.chunks_exact_mut(bpp)) {
+            target.copy_from_slice(pixel);
+        }
+    }
+}
+
+/// A filter keeping a range of columns of each row.
+///
+/// Combine it with `ImageDecoderExt::read_rect` or `streaming::rows` to crop rows as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CropColumns {
+    x: u32,
+    width: u32,
+}
+
+impl CropColumns {
+    /// Create a filter keeping `width` columns, starting at column `x`.
+    pub fn new(x: u32, width: u32) -> Self {
+        CropColumns { x, width }
+    }
+}
+
+impl ScanlineFilter for CropColumns {
+    /// Returns a `DimensionMismatch` parameter error if the columns are not within the rows.
+    fn output_format(&self, width: u32, color: ColorType) -> ImageResult<(u32, ColorType)> {
+        match self.x.checked_add(self.width) {
+            Some(end) if end <= width => Ok((self.width, color)),
+            _ => Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))),
+        }
+    }
+
+    fn filter_row(&mut self, input: &[u8], color: ColorType, output: &mut [u8]) {
+        let bpp = usize::from(color.bytes_per_pixel());
+        output.copy_from_slice(&input[self.x as usize * bpp..][..output.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    #[test]
+    fn chains_filters() {
+        let data = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let decoder = MemoryDecoder::new(4, 1, ColorType::Rgb8, data);
+        let decoder = FilteredDecoder::new(decoder)
+            .with_filter(CropColumns::new(1, 2))
+            .unwrap()
+            .with_filter(FlipHorizontal)
+            .unwrap()
+            .with_filter(ConvertColor::new(ColorType::Rgba8))
+            .unwrap();
+
+        assert_eq!(decoder.dimensions(), (2, 1));
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        assert_eq!(decoder.scanline_bytes(), 8);
+        assert_eq!(
+            decoder.decode_fidelity(),
+            DecodeFidelity::LosslessConversion
+        );
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&decoder);
+        assert_eq!(decode(decoder), [7, 8, 9, 255, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn rejects_unfit_filters() {
+        let decoder = MemoryDecoder::new(2, 2, ColorType::La8, vec![0; 8]);
+        let result = FilteredDecoder::new(decoder).with_filter(CropColumns::new(1, 2));
+        assert!(matches!(result, Err(ImageError::Parameter(_))));
+
+        let data: Vec<u8> = (0..8).collect();
+        let decoder = MemoryDecoder::new(2, 2, ColorType::La8, data.clone());
+        let decoder = FilteredDecoder::new(decoder)
+            .with_filter(ConvertColor::new(ColorType::L8))
+            .unwrap();
+        assert_eq!(decoder.decode_fidelity(), DecodeFidelity::LossyConversion);
+        assert_eq!(decode(decoder), [0, 2, 4, 6]);
+
+        let decoder = FilteredDecoder::new(MemoryDecoder::new(2, 2, ColorType::La8, data.clone()));
+        assert_eq!(decode(decoder), data);
+    }
+
+    #[test]
+    fn drops_color_metadata_of_other_color_models() {
+        let mut decoder = MemoryDecoder::new(2, 1, ColorType::Rgb8, vec![0; 6]);
+        decoder.cicp = Some(Cicp::SRGB);
+        let decoder = FilteredDecoder::new(decoder)
+            .with_filter(ConvertColor::new(ColorType::Rgba8))
+            .unwrap();
+        assert_eq!(decoder.cicp(), Some(Cicp::SRGB));
+
+        let decoder = decoder
+            .with_filter(ConvertColor::new(ColorType::L8))
+            .unwrap()
+            .with_filter(ConvertColor::new(ColorType::Rgb8))
+            .unwrap();
+        assert_eq!(decoder.cicp(), None);
+    }
+
+    #[test]
+    fn checks_rows_against_limits() {
+        let mut decoder = MemoryDecoder::new(4, 1, ColorType::L8, vec![0; 4]);
+        decoder.limits.max_alloc = Some(20);
+        let decoder = FilteredDecoder::new(decoder)
+            .with_filter(ConvertColor::new(ColorType::Rgb8))
+            .unwrap();
+        assert!(matches!(decoder.into_reader(), Err(ImageError::Limits(_))));
+    }
+}
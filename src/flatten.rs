@@ -0,0 +1,238 @@
+use std::io::{self, Read};
+
+use crate::chunked::{chunk_len, ChunkConversion, ChunkedReader};
+use crate::convert::{convert_color, read_sample, write_sample};
+use crate::{AlphaMode, Channel, Cicp, DecodeFidelity, HdrMetadata, ImageResult, Limits};
+use crate::{ColorType, ExtendedColorType, ImageDecoder, RowOrder};
+
+/// A decoder adapter producing opaque pixels.
+///
+/// Color types with alpha are replaced by the same color type without it, see
+/// `ColorType::without_alpha`. By default the alpha channel is dropped and the color samples are
+/// kept as they are. With a background, each pixel is composited over it instead. Opaque images
+/// are passed through. The pixels are flattened while reading.
+pub struct FlattenAlpha<D> {
+    inner: D,
+    background: Option<[u8; 3]>,
+}
+
+/// The reader of a `FlattenAlpha` decoder.
+pub struct FlattenAlphaReader<R> {
+    inner: ChunkedReader<R, Flattener>,
+}
+
+struct Flattener {
+    from: ColorType,
+    to: ColorType,
+    alpha_mode: AlphaMode,
+    /// The samples of the background in the output color type, if alpha is composited.
+    background: Option<Vec<f32>>,
+}
+
+impl<'a, D: ImageDecoder<'a>> FlattenAlpha<D> {
+    /// Wrap a decoder to drop the alpha channel.
+    pub fn new(decoder: D) -> Self {
+        FlattenAlpha {
+            inner: decoder,
+            background: None,
+        }
+    }
+
+    /// Composite the pixels over a background color, given as 8-bit RGB.
+    ///
+    /// The color is scaled to the depth of the samples, and reduced to luma for luma images.
+    /// Compositing operates on the stored samples, without linearizing them.
+    pub fn with_background(mut self, rgb: [u8; 3]) -> Self {
+        self.background = Some(rgb);
+        self
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns whether the inner decoder produces alpha which has to be removed.
+    fn flattens(&self) -> bool {
+        self.inner.color_type().has_alpha()
+    }
+
+    /// The number of bytes of the inner decoder which are flattened at once.
+    fn source_chunk(&self) -> u64 {
+        let bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        chunk_len(self.inner.scanline_bytes(), bpp)
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for FlattenAlpha<D> {
+    type Reader = FlattenAlphaReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type().without_alpha()
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        if self.flattens() {
+            DecodeFidelity::LossyConversion
+        } else {
+            self.inner.decode_fidelity()
+        }
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        if !self.flattens() {
+            return self.inner.scanline_bytes();
+        }
+
+        let from_bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        self.source_chunk() / from_bpp * u64::from(self.color_type().bytes_per_pixel())
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let from = self.inner.color_type();
+        let to = from.without_alpha();
+        let flattener = if self.flattens() {
+            let background = self.background.map(|rgb| {
+                let mut pixel = vec![0; usize::from(to.bytes_per_pixel())];
+                convert_color(&rgb, ColorType::Rgb8, &mut pixel, to);
+                let format = to.layout().sample_format();
+                pixel
+                    .chunks_exact(format.bytes())
+                    .map(|sample| read_sample(sample, format))
+                    .collect()
+            });
+            Some(Flattener {
+                from,
+                to,
+                alpha_mode: self.inner.alpha_mode(),
+                background,
+            })
+        } else {
+            None
+        };
+
+        let (total_bytes, chunk) = (self.inner.total_bytes(), self.source_chunk());
+        let inner = self.inner.into_reader()?;
+        Ok(FlattenAlphaReader {
+            inner: ChunkedReader::new(inner, flattener, total_bytes, chunk),
+        })
+    }
+}
+
+impl<R: Read> Read for FlattenAlphaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl ChunkConversion for Flattener {
+    fn output_len(&self, len: usize) -> usize {
+        len / usize::from(self.from.bytes_per_pixel()) * usize::from(self.to.bytes_per_pixel())
+    }
+
+    fn convert(&mut self, source: &[u8], output: &mut [u8]) {
+        let layout = self.from.layout();
+        let format = layout.sample_format();
+        let sample_len = format.bytes();
+        let alpha = layout.byte_offset(Channel::Alpha).unwrap_or(0);
+        let from_bpp = usize::from(self.from.bytes_per_pixel());
+        let to_bpp = usize::from(self.to.bytes_per_pixel());
+
+        let pixels = source
+            .chunks_exact(from_bpp)
+            .zip(output.chunks_exact_mut(to_bpp));
+        for (pixel, target) in pixels {
+            // The alpha channel is always stored last.
+            let color = &pixel[..alpha];
+            let background = match &self.background {
+                Some(background) => background,
+                None => {
+                    target.copy_from_slice(color);
+                    continue;
+                }
+            };
+
+            let coverage = read_sample(&pixel[alpha..], format).clamp(0.0, 1.0);
+            let source_weight = match self.alpha_mode {
+                AlphaMode::Premultiplied => 1.0,
+                _ => coverage,
+            };
+            let samples = color.chunks_exact(sample_len);
+            let targets = target.chunks_exact_mut(sample_len);
+            for ((sample, target), &background) in samples.zip(targets).zip(background) {
+                let value = read_sample(sample, format) * source_weight;
+                write_sample(value + background * (1.0 - coverage), target, format);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    #[test]
+    fn strips_alpha() {
+        let data = vec![10, 20, 30, 0, 40, 50, 60, 255];
+        let decoder = FlattenAlpha::new(MemoryDecoder::new(2, 1, ColorType::Rgba8, data));
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        assert_eq!(decoder.decode_fidelity(), DecodeFidelity::LossyConversion);
+        assert_eq!(decode(decoder), [10, 20, 30, 40, 50, 60]);
+
+        let data = vec![1, 2, 3];
+        let decoder = FlattenAlpha::new(MemoryDecoder::new(3, 1, ColorType::L8, data.clone()));
+        assert_eq!(decode(decoder.with_background([255; 3])), data);
+    }
+
+    #[test]
+    fn composites_over_background() {
+        let data = vec![200, 0, 100, 255, 0, 128];
+        let decoder = MemoryDecoder::new(3, 1, ColorType::La8, data);
+        let decoder = FlattenAlpha::new(decoder).with_background([100, 100, 100]);
+        assert_eq!(decoder.color_type(), ColorType::L8);
+        assert_eq!(decode(decoder), [100, 100, 50]);
+
+        let samples = [0xffffu16, 0x8000];
+        let data = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+        let decoder = MemoryDecoder::new(1, 1, ColorType::La16, data);
+        let flattened = decode(FlattenAlpha::new(decoder).with_background([0; 3]));
+        assert_eq!(flattened, 0x8000u16.to_ne_bytes());
+    }
+}
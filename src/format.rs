@@ -1,8 +1,9 @@
-use crate::NonExhaustiveMarker;
+use std::ffi::OsStr;
 
 /// An enumeration of supported image formats.
 /// Not all formats support both encoding and decoding.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[non_exhaustive]
 pub enum ImageFormat {
     /// An Image in PNG Format
     Png,
@@ -37,6 +38,171 @@ pub enum ImageFormat {
     /// An Image in Radiance HDR Format
     Hdr,
 
-    #[doc(hidden)]
-    __NonExhaustive(NonExhaustiveMarker),
+    /// A camera raw image in Adobe DNG Format
+    ///
+    /// DNG files are TIFF files carrying a DNG version tag, see `guess_format`.
+    Dng,
+}
+
+/// How an image format compresses its pixel data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[non_exhaustive]
+pub enum CompressionKind {
+    /// The pixel data can always be restored exactly.
+    Lossless,
+
+    /// The pixel data is compressed in a way that discards information.
+    Lossy,
+
+    /// The format supports both lossless and lossy compression, depending on the file.
+    Either,
+}
+
+impl ImageFormat {
+    /// Returns how the format compresses pixel data.
+    ///
+    /// This describes the format specification, not any particular encoder. Note that even
+    /// lossless formats may not be able to represent every color type exactly.
+    pub const fn compression_kind(self) -> CompressionKind {
+        match self {
+            ImageFormat::Png
+            | ImageFormat::Gif
+            | ImageFormat::Pnm
+            | ImageFormat::Tga
+            | ImageFormat::Bmp
+            | ImageFormat::Ico
+            | ImageFormat::Hdr => CompressionKind::Lossless,
+            ImageFormat::Jpeg => CompressionKind::Lossy,
+            ImageFormat::WebP | ImageFormat::Tiff | ImageFormat::Dds | ImageFormat::Dng => {
+                CompressionKind::Either
+            }
+        }
+    }
+
+    /// Returns a numeric identifier for the format.
+    ///
+    /// The identifier of a format is guaranteed to never change across versions of this crate,
+    /// and identifiers are never reused. This makes them suitable for FFI and for persistence.
+    /// Newly added formats receive new identifiers. The value `0` is never assigned.
+    pub const fn to_stable_id(self) -> u32 {
+        match self {
+            ImageFormat::Png => 1,
+            ImageFormat::Jpeg => 2,
+            ImageFormat::Gif => 3,
+            ImageFormat::WebP => 4,
+            ImageFormat::Pnm => 5,
+            ImageFormat::Tiff => 6,
+            ImageFormat::Tga => 7,
+            ImageFormat::Dds => 8,
+            ImageFormat::Bmp => 9,
+            ImageFormat::Ico => 10,
+            ImageFormat::Hdr => 11,
+            ImageFormat::Dng => 12,
+        }
+    }
+
+    /// Returns the format with the given identifier, see `to_stable_id`.
+    ///
+    /// Returns `None` for identifiers which are unknown to this version of the crate.
+    pub const fn from_stable_id(id: u32) -> Option<ImageFormat> {
+        Some(match id {
+            1 => ImageFormat::Png,
+            2 => ImageFormat::Jpeg,
+            3 => ImageFormat::Gif,
+            4 => ImageFormat::WebP,
+            5 => ImageFormat::Pnm,
+            6 => ImageFormat::Tiff,
+            7 => ImageFormat::Tga,
+            8 => ImageFormat::Dds,
+            9 => ImageFormat::Bmp,
+            10 => ImageFormat::Ico,
+            11 => ImageFormat::Hdr,
+            12 => ImageFormat::Dng,
+            _ => return None,
+        })
+    }
+
+    /// Returns the format which uses the given file extension, ignoring case.
+    ///
+    /// The extension is given without the leading dot. Returns `None` for unknown extensions.
+    pub fn from_extension<S: AsRef<OsStr>>(extension: S) -> Option<ImageFormat> {
+        let extension = extension.as_ref().to_str()?.to_ascii_lowercase();
+        [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::WebP,
+            ImageFormat::Pnm,
+            ImageFormat::Tiff,
+            ImageFormat::Tga,
+            ImageFormat::Dds,
+            ImageFormat::Bmp,
+            ImageFormat::Ico,
+            ImageFormat::Hdr,
+            ImageFormat::Dng,
+        ]
+        .iter()
+        .copied()
+        .find(|format| format.extensions_str().contains(&extension.as_str()))
+    }
+
+    /// Returns the file extensions in use for the format, the most common first.
+    pub const fn extensions_str(self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png => &["png"],
+            ImageFormat::Jpeg => &["jpg", "jpeg", "jfif"],
+            ImageFormat::Gif => &["gif"],
+            ImageFormat::WebP => &["webp"],
+            ImageFormat::Pnm => &["pbm", "pam", "ppm", "pgm", "pnm"],
+            ImageFormat::Tiff => &["tiff", "tif"],
+            ImageFormat::Tga => &["tga"],
+            ImageFormat::Dds => &["dds"],
+            ImageFormat::Bmp => &["bmp"],
+            ImageFormat::Ico => &["ico"],
+            ImageFormat::Hdr => &["hdr"],
+            ImageFormat::Dng => &["dng"],
+        }
+    }
+
+    /// Returns whether the format always uses lossy compression.
+    ///
+    /// Formats which only may be lossy, such as WebP, return `false`. Check `compression_kind`
+    /// to treat them differently.
+    pub const fn is_lossy(self) -> bool {
+        matches!(self.compression_kind(), CompressionKind::Lossy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_ids_round_trip() {
+        for id in 0..64 {
+            if let Some(format) = ImageFormat::from_stable_id(id) {
+                assert_eq!(format.to_stable_id(), id);
+            }
+        }
+
+        // These must never change.
+        assert_eq!(ImageFormat::Png.to_stable_id(), 1);
+        assert_eq!(ImageFormat::Hdr.to_stable_id(), 11);
+        assert_eq!(ImageFormat::Dng.to_stable_id(), 12);
+        assert_eq!(ImageFormat::from_stable_id(0), None);
+    }
+
+    #[test]
+    fn maps_extensions() {
+        assert_eq!(ImageFormat::from_extension("JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_extension("tif"), Some(ImageFormat::Tiff));
+        assert_eq!(ImageFormat::from_extension("txt"), None);
+        for id in 1..=12 {
+            let format = ImageFormat::from_stable_id(id).unwrap();
+            assert_eq!(
+                ImageFormat::from_extension(format.extensions_str()[0]),
+                Some(format)
+            );
+        }
+    }
 }
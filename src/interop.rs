@@ -0,0 +1,220 @@
+//! Mappings of color types to the texture formats of graphics APIs.
+//!
+//! `TextureFormat` names the formats which can hold the pixels of a color type without
+//! conversion, and translates them to the identifiers of wgpu, Vulkan and Metal. It has no
+//! dependency on any of those APIs, match on it to obtain their own types.
+//!
+//! Samples wider than a byte are in native endian, as the graphics APIs expect them on the
+//! little-endian platforms they run on. Graphics APIs have no formats with three channels which
+//! can be sampled reliably, pixels of such color types have to be padded with alpha first, see
+//! `padded_color_type`. Luma is stored in the red channel, shaders have to replicate it to green
+//! and blue, for example with a texture swizzle.
+//!
+//! # sRGB and linear formats
+//!
+//! Sampling an sRGB texture decodes the transfer function in hardware, so that filtering and
+//! blending happen in linear light. This is what most images encoded with the sRGB transfer
+//! function want. A linear format returns the stored values, which is right for data which is
+//! already linear, such as normal maps or float images, and for shaders which decode the
+//! transfer function themselves. This crate only maps the 8-bit RGBA and BGRA color types to sRGB
+//! formats, the ones all three APIs share. Vulkan and Metal also have sRGB formats with fewer
+//! channels, such as `R8_SRGB` and `R8Unorm_sRGB`, which wgpu lacks.
+
+use crate::ColorType;
+
+/// How a texture is sampled.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextureEncoding {
+    /// The stored values are returned as they are.
+    #[default]
+    Linear,
+    /// The values are decoded from the sRGB transfer function when sampled.
+    Srgb,
+}
+
+/// A texture format holding the pixels of a color type in the same layout.
+///
+/// The variants are named after the formats of wgpu.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextureFormat {
+    /// One channel of 8-bit unsigned normalized samples.
+    R8Unorm,
+    /// Two channels of 8-bit unsigned normalized samples.
+    Rg8Unorm,
+    /// Four channels of 8-bit unsigned normalized samples.
+    Rgba8Unorm,
+    /// Same as `Rgba8Unorm`, with the color channels decoded from sRGB when sampled.
+    Rgba8UnormSrgb,
+    /// Four channels of 8-bit unsigned normalized samples, in blue, green, red and alpha order.
+    Bgra8Unorm,
+    /// Same as `Bgra8Unorm`, with the color channels decoded from sRGB when sampled.
+    Bgra8UnormSrgb,
+    /// One channel of 16-bit unsigned normalized samples.
+    R16Unorm,
+    /// Two channels of 16-bit unsigned normalized samples.
+    Rg16Unorm,
+    /// Four channels of 16-bit unsigned normalized samples.
+    Rgba16Unorm,
+    /// Four channels of 32-bit float samples.
+    Rgba32Float,
+}
+
+impl TextureFormat {
+    /// Returns the texture format storing pixels of the color type as they are.
+    ///
+    /// Returns `None` for color types with three channels, and for those not mapped to an sRGB
+    /// format if `encoding` is `TextureEncoding::Srgb`.
+    pub const fn from_color_type(color: ColorType, encoding: TextureEncoding) -> Option<Self> {
+        let srgb = matches!(encoding, TextureEncoding::Srgb);
+        Some(match color {
+            ColorType::Rgba8 if srgb => TextureFormat::Rgba8UnormSrgb,
+            ColorType::Bgra8 if srgb => TextureFormat::Bgra8UnormSrgb,
+            _ if srgb => return None,
+            ColorType::L8 => TextureFormat::R8Unorm,
+            ColorType::La8 => TextureFormat::Rg8Unorm,
+            ColorType::Rgba8 => TextureFormat::Rgba8Unorm,
+            ColorType::Bgra8 => TextureFormat::Bgra8Unorm,
+            ColorType::L16 => TextureFormat::R16Unorm,
+            ColorType::La16 => TextureFormat::Rg16Unorm,
+            ColorType::Rgba16 => TextureFormat::Rgba16Unorm,
+            ColorType::Rgba32F => TextureFormat::Rgba32Float,
+            _ => return None,
+        })
+    }
+
+    /// Returns the color type of the pixels stored in a texture of this format.
+    pub const fn color_type(self) -> ColorType {
+        match self {
+            TextureFormat::R8Unorm => ColorType::L8,
+            TextureFormat::Rg8Unorm => ColorType::La8,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => ColorType::Rgba8,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => ColorType::Bgra8,
+            TextureFormat::R16Unorm => ColorType::L16,
+            TextureFormat::Rg16Unorm => ColorType::La16,
+            TextureFormat::Rgba16Unorm => ColorType::Rgba16,
+            TextureFormat::Rgba32Float => ColorType::Rgba32F,
+        }
+    }
+
+    /// Returns how the texture is sampled.
+    pub const fn encoding(self) -> TextureEncoding {
+        match self {
+            TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => TextureEncoding::Srgb,
+            _ => TextureEncoding::Linear,
+        }
+    }
+
+    /// Returns the name of the `wgpu::TextureFormat` variant.
+    ///
+    /// The 16-bit normalized formats require the `TEXTURE_FORMAT_16BIT_NORM` feature of wgpu.
+    pub const fn wgpu_name(self) -> &'static str {
+        match self {
+            TextureFormat::R8Unorm => "R8Unorm",
+            TextureFormat::Rg8Unorm => "Rg8Unorm",
+            TextureFormat::Rgba8Unorm => "Rgba8Unorm",
+            TextureFormat::Rgba8UnormSrgb => "Rgba8UnormSrgb",
+            TextureFormat::Bgra8Unorm => "Bgra8Unorm",
+            TextureFormat::Bgra8UnormSrgb => "Bgra8UnormSrgb",
+            TextureFormat::R16Unorm => "R16Unorm",
+            TextureFormat::Rg16Unorm => "Rg16Unorm",
+            TextureFormat::Rgba16Unorm => "Rgba16Unorm",
+            TextureFormat::Rgba32Float => "Rgba32Float",
+        }
+    }
+
+    /// Returns the name of the `VkFormat` constant.
+    pub const fn vulkan_name(self) -> &'static str {
+        match self {
+            TextureFormat::R8Unorm => "VK_FORMAT_R8_UNORM",
+            TextureFormat::Rg8Unorm => "VK_FORMAT_R8G8_UNORM",
+            TextureFormat::Rgba8Unorm => "VK_FORMAT_R8G8B8A8_UNORM",
+            TextureFormat::Rgba8UnormSrgb => "VK_FORMAT_R8G8B8A8_SRGB",
+            TextureFormat::Bgra8Unorm => "VK_FORMAT_B8G8R8A8_UNORM",
+            TextureFormat::Bgra8UnormSrgb => "VK_FORMAT_B8G8R8A8_SRGB",
+            TextureFormat::R16Unorm => "VK_FORMAT_R16_UNORM",
+            TextureFormat::Rg16Unorm => "VK_FORMAT_R16G16_UNORM",
+            TextureFormat::Rgba16Unorm => "VK_FORMAT_R16G16B16A16_UNORM",
+            TextureFormat::Rgba32Float => "VK_FORMAT_R32G32B32A32_SFLOAT",
+        }
+    }
+
+    /// Returns the name of the `MTLPixelFormat` case.
+    pub const fn metal_name(self) -> &'static str {
+        match self {
+            TextureFormat::R8Unorm => "MTLPixelFormatR8Unorm",
+            TextureFormat::Rg8Unorm => "MTLPixelFormatRG8Unorm",
+            TextureFormat::Rgba8Unorm => "MTLPixelFormatRGBA8Unorm",
+            TextureFormat::Rgba8UnormSrgb => "MTLPixelFormatRGBA8Unorm_sRGB",
+            TextureFormat::Bgra8Unorm => "MTLPixelFormatBGRA8Unorm",
+            TextureFormat::Bgra8UnormSrgb => "MTLPixelFormatBGRA8Unorm_sRGB",
+            TextureFormat::R16Unorm => "MTLPixelFormatR16Unorm",
+            TextureFormat::Rg16Unorm => "MTLPixelFormatRG16Unorm",
+            TextureFormat::Rgba16Unorm => "MTLPixelFormatRGBA16Unorm",
+            TextureFormat::Rgba32Float => "MTLPixelFormatRGBA32Float",
+        }
+    }
+}
+
+/// Returns the color type to convert pixels to before they are uploaded into a texture.
+///
+/// Color types with three channels gain an opaque alpha channel, all others are returned as they
+/// are. The result has a texture format for `TextureEncoding::Linear`. Convert the pixels with
+/// `convert::convert_color` or a `ColorConvertingDecoder`.
+pub const fn padded_color_type(color: ColorType) -> ColorType {
+    match color {
+        ColorType::Rgb8 => ColorType::Rgba8,
+        ColorType::Bgr8 => ColorType::Bgra8,
+        ColorType::Rgb16 => ColorType::Rgba16,
+        ColorType::Rgb32F => ColorType::Rgba32F,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_color_types() {
+        let colors = [
+            ColorType::L8,
+            ColorType::La8,
+            ColorType::Rgb8,
+            ColorType::Rgba8,
+            ColorType::L16,
+            ColorType::La16,
+            ColorType::Rgb16,
+            ColorType::Rgba16,
+            ColorType::Bgr8,
+            ColorType::Bgra8,
+            ColorType::Rgb32F,
+            ColorType::Rgba32F,
+        ];
+        for &color in &colors {
+            let padded = padded_color_type(color);
+            let format = TextureFormat::from_color_type(padded, TextureEncoding::Linear).unwrap();
+            assert_eq!(format.color_type(), padded);
+            assert_eq!(format.encoding(), TextureEncoding::Linear);
+            assert!(padded.has_alpha() || padded == color);
+
+            if let Some(srgb) = TextureFormat::from_color_type(color, TextureEncoding::Srgb) {
+                assert_eq!(srgb.color_type(), color);
+                assert_eq!(srgb.encoding(), TextureEncoding::Srgb);
+            }
+        }
+
+        assert_eq!(
+            TextureFormat::from_color_type(ColorType::Rgb8, TextureEncoding::Linear),
+            None
+        );
+        assert_eq!(
+            TextureFormat::from_color_type(ColorType::L8, TextureEncoding::Srgb),
+            None
+        );
+        let format = TextureFormat::from_color_type(ColorType::Rgba8, TextureEncoding::Srgb);
+        assert_eq!(format, Some(TextureFormat::Rgba8UnormSrgb));
+        assert_eq!(format.unwrap().vulkan_name(), "VK_FORMAT_R8G8B8A8_SRGB");
+    }
+}
@@ -2,33 +2,87 @@
 
 #![deny(missing_docs)]
 #![deny(unused_extern_crates)]
-#![forbid(unsafe_code)]
+// Not `forbid`, which would reject the `allow` of the single cast in `read_exact_uninit`.
+#![deny(unsafe_code)]
 
+mod animation;
+mod animation_encoder;
+mod boxed;
+mod buffer;
+mod chunked;
+mod cms;
 mod colortype;
+mod compositor;
 mod decoder;
+mod detect;
+mod dimensions;
+mod encoder;
 mod error;
+mod filter;
+mod flatten;
 mod format;
+mod limits;
+mod luma;
+mod metadata;
+mod pad;
+mod probe;
+mod progress;
+mod reader;
+mod rect;
+mod registry;
+mod resumable;
+mod row_order;
+mod sequence;
+mod stats;
+mod thumbnail;
+mod view;
 
+#[cfg(test)]
+mod testing;
+
+pub mod convert;
+pub mod interop;
+pub mod quantize;
+pub mod streaming;
+pub mod transform;
+
+#[cfg(feature = "bench-util")]
+pub mod bench_util;
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+pub use animation::LoopCount;
+pub use animation::{AnimationDecoder, BlendMode, Delay, DisposalMethod, Estimate, Frame, Frames};
+pub use animation_encoder::{transcode_animation, AnimationEncoder, TranscodeOptions};
+pub use boxed::BoxedDecoder;
+pub use buffer::PixelBuffer;
+pub use cms::{Cms, ColorManagedDecoder, ColorManagedReader, ColorProfile, ColorTransform};
 pub use colortype::*;
+pub use compositor::FrameCompositor;
+pub use convert::{AlphaMode, ColorConvertingDecoder, ColorConvertingReader};
 pub use decoder::*;
+pub use detect::{guess_format, guess_format_candidates, Confidence};
+pub use dimensions::Dimensions;
+pub use encoder::*;
 pub use error::*;
-pub use format::ImageFormat;
-
-/// A marker struct for __NonExhaustive enums.
-///
-/// This is an empty type that can not be constructed. When an enum contains a tuple variant that
-/// includes this type the optimizer can statically determined tha the branch is never taken while
-/// at the same time the matching of the branch is required.
-///
-/// The effect is thus very similar to the actual `#[non_exhaustive]` attribute with no runtime
-/// costs. Also note that we use a dirty trick to not only hide this type from the doc but make it
-/// inaccessible. The visibility in this module is pub but the module itself is not and the
-/// top-level crate never exports the type.
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub struct NonExhaustiveMarker {
-    /// Allows this crate, and this crate only, to match on the impossibility of this variant.
-    pub(crate) _private: Empty,
-}
-
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) enum Empty {}
+pub use filter::{ConvertColor, CropColumns, FilteredDecoder, FilteredReader};
+pub use filter::{FlipHorizontal, ScanlineFilter};
+pub use flatten::{FlattenAlpha, FlattenAlphaReader};
+pub use format::{CompressionKind, ImageFormat};
+pub use limits::{LimitedReader, Limits};
+pub use luma::{LumaWeights, ToLuma, ToLumaReader};
+pub use metadata::{Chromaticity, Cicp, ContentLightLevel, HdrMetadata, ImageMetadata};
+pub use metadata::{MasteringDisplay, MetadataKind};
+pub use pad::{Alignment, PadToAlignment, PadToAlignmentReader};
+pub use probe::{ImageInfo, ImageProbe};
+pub use progress::ProgressTracker;
+pub use reader::ImageReader;
+pub use rect::Rect;
+pub use registry::{BufReadSeek, CodecRegistry, DecoderFactory};
+pub use resumable::{DecodeStatus, ResumableDecode};
+pub use row_order::{RowOrder, TopDownDecoder, TopDownReader};
+pub use sequence::{ImageSequence, SequenceFiles};
+pub use stats::{ChannelStats, PixelStats};
+pub use thumbnail::{DownscaleFilter, ThumbnailDecoder, ThumbnailReader};
+pub use view::ImageView;
@@ -0,0 +1,311 @@
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use crate::{ImageError, ImageResult, LimitError, LimitErrorKind};
+
+/// Resource limits for decoding.
+///
+/// Limits protect against malicious or corrupt files which claim huge dimensions. Every limit is
+/// optional, `None` means that there is no limit.
+///
+/// Large inputs are limited by reading them through a `LimitedReader`, which `ImageReader` does
+/// for the decoders it creates.
+///
+/// Decoders receive their limits through `ImageDecoder::set_limits`. The provided read methods of
+/// `ImageDecoder` additionally validate the dimensions and the decoded size against
+/// `ImageDecoder::limits` before reading, so that a decoder which does not check its limits
+/// itself is still covered by a safety net.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Limits {
+    /// The maximum width of an image in pixels.
+    pub max_image_width: Option<u32>,
+    /// The maximum height of an image in pixels.
+    pub max_image_height: Option<u32>,
+    /// The maximum number of bytes a decoder may allocate, including the decoded image.
+    pub max_alloc: Option<u64>,
+    /// The maximum number of bytes read from the input, see `LimitedReader`.
+    pub max_input_bytes: Option<u64>,
+}
+
+impl Limits {
+    /// The default maximum allocation of 512 MiB.
+    pub const DEFAULT_MAX_ALLOC: u64 = 512 * 1024 * 1024;
+
+    /// Limits which allow everything.
+    pub fn no_limits() -> Self {
+        Limits {
+            max_image_width: None,
+            max_image_height: None,
+            max_alloc: None,
+            max_input_bytes: None,
+        }
+    }
+
+    /// Check that an image of the given dimensions is allowed.
+    pub fn check_dimensions(&self, width: u32, height: u32) -> ImageResult<()> {
+        let too_wide = self.max_image_width.is_some_and(|max| width > max);
+        let too_high = self.max_image_height.is_some_and(|max| height > max);
+        if too_wide || too_high {
+            Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that an allocation of the given number of bytes is allowed.
+    ///
+    /// This does not account for the allocation, see `reserve` for that.
+    pub fn check_alloc(&self, amount: u64) -> ImageResult<()> {
+        match self.max_alloc {
+            Some(max) if amount > max => Err(insufficient_memory()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Account for an allocation, reducing the remaining allowance.
+    ///
+    /// Returns an error, and leaves the limits unchanged, if the allocation is not allowed.
+    pub fn reserve(&mut self, amount: u64) -> ImageResult<()> {
+        self.check_alloc(amount)?;
+        if let Some(max) = &mut self.max_alloc {
+            *max -= amount;
+        }
+        Ok(())
+    }
+
+    /// Return an allowance that was previously reserved.
+    pub fn free(&mut self, amount: u64) {
+        if let Some(max) = &mut self.max_alloc {
+            *max = max.saturating_add(amount);
+        }
+    }
+}
+
+impl Default for Limits {
+    /// No dimension or input limits and an allocation limit of `DEFAULT_MAX_ALLOC`.
+    fn default() -> Self {
+        Limits {
+            max_image_width: None,
+            max_image_height: None,
+            max_alloc: Some(Limits::DEFAULT_MAX_ALLOC),
+            max_input_bytes: None,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Limits {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Limits {
+            max_image_width: u.arbitrary()?,
+            max_image_height: u.arbitrary()?,
+            max_alloc: u.arbitrary()?,
+            max_input_bytes: u.arbitrary()?,
+        })
+    }
+}
+
+/// A reader failing with a limit error past a maximum number of bytes.
+///
+/// Compressed data can be crafted to keep a decoder reading indefinitely, or files can simply be
+/// larger than an application is willing to process. Decoders and applications wrap untrusted
+/// readers in this, configured from `Limits::max_input_bytes`. Reading beyond the limit fails
+/// with an I/O error which converts to an `ImageError::Limits` of kind
+/// `LimitErrorKind::InputTooLarge`. Inputs of exactly the maximum length are read to their end.
+///
+/// The limit applies to the offset from the position of the reader when it was wrapped, so
+/// seeking back and reading data again does not count twice.
+#[derive(Debug)]
+pub struct LimitedReader<R> {
+    inner: R,
+    max_bytes: Option<u64>,
+    /// The offset from the start.
+    position: u64,
+    /// The position of the inner reader at the start, determined when seeking first.
+    origin: Option<u64>,
+}
+
+impl<R> LimitedReader<R> {
+    /// Wrap a reader to read at most `max_bytes` bytes.
+    pub fn new(reader: R, max_bytes: u64) -> Self {
+        LimitedReader {
+            inner: reader,
+            max_bytes: Some(max_bytes),
+            position: 0,
+            origin: None,
+        }
+    }
+
+    /// Wrap a reader to read at most `Limits::max_input_bytes`, if that limit is set.
+    pub fn from_limits(reader: R, limits: &Limits) -> Self {
+        LimitedReader {
+            max_bytes: limits.max_input_bytes,
+            ..LimitedReader::new(reader, 0)
+        }
+    }
+
+    /// Returns the number of bytes which may still be read, or `None` if there is no limit.
+    pub fn remaining(&self) -> Option<u64> {
+        self.max_bytes.map(|max| max.saturating_sub(self.position))
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.remaining() {
+            Some(0) if !buf.is_empty() => {
+                // Only inputs which actually continue exceed the limit.
+                let read = self.inner.read(&mut buf[..1])?;
+                self.position += read as u64;
+                return match read {
+                    0 => Ok(0),
+                    _ => Err(input_too_large()),
+                };
+            }
+            Some(remaining) => buf
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX)),
+            None => buf.len(),
+        };
+
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for LimitedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let remaining = self.remaining();
+        let available = self.inner.fill_buf()?;
+        match remaining {
+            Some(0) if !available.is_empty() => Err(input_too_large()),
+            Some(remaining) => {
+                let len = available
+                    .len()
+                    .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                Ok(&available[..len])
+            }
+            None => Ok(available),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount as u64;
+        self.inner.consume(amount);
+    }
+}
+
+impl<R: Seek> Seek for LimitedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let origin = match self.origin {
+            Some(origin) => origin,
+            None => {
+                let origin = self.inner.stream_position()? - self.position;
+                *self.origin.insert(origin)
+            }
+        };
+
+        // Positions relative to the start of the wrapped reader are given to it as they are.
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(origin + offset),
+            relative => relative,
+        };
+        let absolute = self.inner.seek(pos)?;
+        self.position = absolute.saturating_sub(origin);
+        Ok(self.position)
+    }
+}
+
+fn input_too_large() -> io::Error {
+    io::Error::other(LimitError::from_kind(LimitErrorKind::InputTooLarge))
+}
+
+pub(crate) fn insufficient_memory() -> ImageError {
+    ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_dimensions() {
+        let mut limits = Limits::no_limits();
+        assert!(limits.check_dimensions(u32::MAX, u32::MAX).is_ok());
+
+        limits.max_image_width = Some(100);
+        assert!(limits.check_dimensions(100, 5000).is_ok());
+        assert!(limits.check_dimensions(101, 1).is_err());
+    }
+
+    #[test]
+    fn reserves_allocations() {
+        let mut limits = Limits::no_limits();
+        limits.max_alloc = Some(10);
+        limits.reserve(6).unwrap();
+        assert!(limits.reserve(6).is_err());
+        assert_eq!(limits.max_alloc, Some(4));
+
+        limits.free(6);
+        assert!(limits.reserve(10).is_ok());
+    }
+
+    #[test]
+    fn limits_input_bytes() {
+        let data: Vec<u8> = (0..10).collect();
+        let mut exact = Vec::new();
+        LimitedReader::new(&data[..], 10)
+            .read_to_end(&mut exact)
+            .unwrap();
+        assert_eq!(exact, data);
+
+        let mut reader = LimitedReader::new(io::Cursor::new(&data), 6);
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reader.remaining(), Some(2));
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        match ImageError::from(err) {
+            ImageError::Limits(err) => assert_eq!(err.kind(), LimitErrorKind::InputTooLarge),
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        // Data read again after seeking back does not count.
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        assert_eq!(reader.fill_buf().unwrap(), [2, 3, 4, 5]);
+        reader.consume(4);
+        assert!(reader.fill_buf().is_err());
+    }
+
+    #[test]
+    fn limits_relative_to_the_start() {
+        let mut cursor = io::Cursor::new((0..10).collect::<Vec<u8>>());
+        cursor.set_position(4);
+        let limits = Limits {
+            max_input_bytes: Some(3),
+            ..Limits::default()
+        };
+        let mut reader = LimitedReader::from_limits(cursor, &limits);
+        assert_eq!(reader.seek(SeekFrom::Current(1)).unwrap(), 1);
+        assert_eq!(reader.seek(SeekFrom::Start(0)).unwrap(), 0);
+        let mut buf = Vec::new();
+        assert!(reader.read_to_end(&mut buf).is_err());
+        assert_eq!(buf, [4, 5, 6]);
+
+        let unlimited = LimitedReader::from_limits(&[0u8; 3][..], &Limits::no_limits());
+        assert_eq!(unlimited.remaining(), None);
+    }
+}
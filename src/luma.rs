@@ -0,0 +1,337 @@
+use std::io::{self, Read};
+
+use crate::chunked::{chunk_len, ChunkConversion, ChunkedReader};
+use crate::convert::{linear_to_srgb, read_sample, srgb_to_linear, write_sample, SampleFormat};
+use crate::{Channel, Cicp, DecodeFidelity, HdrMetadata, ImageResult, Limits};
+use crate::{ColorType, ExtendedColorType, ImageDecoder, RowOrder};
+
+/// The weights of red, green and blue in the luma of a pixel.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LumaWeights {
+    /// The coefficients of Rec. 601, used by JPEG and standard definition video.
+    Rec601,
+    /// The coefficients of Rec. 709, matching the primaries of sRGB.
+    #[default]
+    Rec709,
+}
+
+impl LumaWeights {
+    /// Returns the weights of red, green and blue, which sum to one.
+    pub const fn coefficients(self) -> [f32; 3] {
+        match self {
+            LumaWeights::Rec601 => [0.299, 0.587, 0.114],
+            LumaWeights::Rec709 => [0.2126, 0.7152, 0.0722],
+        }
+    }
+}
+
+/// A decoder adapter producing grayscale pixels.
+///
+/// Images with 8-bit samples are converted to `ColorType::L8`, all others to `ColorType::L16`.
+/// Float samples are clamped to their nominal range. The alpha channel is dropped, wrap the inner
+/// decoder in a `FlattenAlpha` first to composite it over a background instead. Luma images of the
+/// output color type are passed through.
+///
+/// By default the weights are applied to the stored samples, as most formats define luma. In
+/// linear light they are applied to the intensities instead, which preserves the brightness of
+/// saturated colors. The transfer function is taken from `ImageDecoder::cicp`.
+pub struct ToLuma<D> {
+    inner: D,
+    weights: LumaWeights,
+    linear_light: bool,
+}
+
+/// The reader of a `ToLuma` decoder.
+pub struct ToLumaReader<R> {
+    inner: ChunkedReader<R, LumaConverter>,
+}
+
+struct LumaConverter {
+    from: ColorType,
+    to: ColorType,
+    weights: [f32; 3],
+    transfer: Transfer,
+}
+
+/// How samples are mapped to linear light before they are weighted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transfer {
+    /// The samples are weighted as they are stored.
+    Stored,
+    /// The samples are encoded with the sRGB transfer function.
+    Srgb,
+}
+
+impl<'a, D: ImageDecoder<'a>> ToLuma<D> {
+    /// Wrap a decoder to produce luma with the coefficients of Rec. 709.
+    pub fn new(decoder: D) -> Self {
+        ToLuma {
+            inner: decoder,
+            weights: LumaWeights::default(),
+            linear_light: false,
+        }
+    }
+
+    /// Set the weights of the color channels.
+    pub fn with_weights(mut self, weights: LumaWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// Apply the weights in linear light.
+    ///
+    /// Samples with the sRGB transfer function, and those without CICP information, are
+    /// linearized before weighting and the luma is encoded with the same function again. Samples
+    /// which are already linear, or use any other transfer function, are weighted as stored.
+    pub fn with_linear_light(mut self, linear_light: bool) -> Self {
+        self.linear_light = linear_light;
+        self
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns whether the inner decoder produces pixels which have to be converted.
+    fn converts(&self) -> bool {
+        self.inner.color_type() != self.color_type()
+    }
+
+    fn transfer(&self) -> Transfer {
+        if !self.linear_light {
+            return Transfer::Stored;
+        }
+
+        let srgb = Cicp::SRGB.transfer_characteristics;
+        match self.inner.cicp() {
+            Some(cicp) if cicp.transfer_characteristics != srgb => Transfer::Stored,
+            _ => Transfer::Srgb,
+        }
+    }
+
+    /// The number of bytes of the inner decoder which are converted at once.
+    fn source_chunk(&self) -> u64 {
+        let bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        chunk_len(self.inner.scanline_bytes(), bpp)
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for ToLuma<D> {
+    type Reader = ToLumaReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        match self.inner.color_type().layout().sample_format() {
+            SampleFormat::U8 => ColorType::L8,
+            _ => ColorType::L16,
+        }
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        if self.converts() {
+            DecodeFidelity::LossyConversion
+        } else {
+            self.inner.decode_fidelity()
+        }
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    /// Returns `None` for color images, whose profile does not describe gray pixels.
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        let layout = self.inner.color_type().layout();
+        match layout.position(Channel::Luma) {
+            Some(_) => self.inner.icc_profile(),
+            None => None,
+        }
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        if !self.converts() {
+            return self.inner.scanline_bytes();
+        }
+
+        let from_bpp = u64::from(self.inner.color_type().bytes_per_pixel());
+        self.source_chunk() / from_bpp * u64::from(self.color_type().bytes_per_pixel())
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let converter = self.converts().then(|| LumaConverter {
+            from: self.inner.color_type(),
+            to: self.color_type(),
+            weights: self.weights.coefficients(),
+            transfer: self.transfer(),
+        });
+        let (total_bytes, chunk) = (self.inner.total_bytes(), self.source_chunk());
+        let inner = self.inner.into_reader()?;
+        Ok(ToLumaReader {
+            inner: ChunkedReader::new(inner, converter, total_bytes, chunk),
+        })
+    }
+}
+
+impl<R: Read> Read for ToLumaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl ChunkConversion for LumaConverter {
+    fn output_len(&self, len: usize) -> usize {
+        len / usize::from(self.from.bytes_per_pixel()) * usize::from(self.to.bytes_per_pixel())
+    }
+
+    fn convert(&mut self, source: &[u8], output: &mut [u8]) {
+        let layout = self.from.layout();
+        let format = layout.sample_format();
+        let to_format = self.to.layout().sample_format();
+        let from_bpp = usize::from(self.from.bytes_per_pixel());
+        let to_bpp = usize::from(self.to.bytes_per_pixel());
+        let luma = layout.byte_offset(Channel::Luma);
+        let rgb = [Channel::Red, Channel::Green, Channel::Blue].map(|c| layout.byte_offset(c));
+
+        let pixels = source
+            .chunks_exact(from_bpp)
+            .zip(output.chunks_exact_mut(to_bpp));
+        for (pixel, target) in pixels {
+            let sample = |offset: usize| read_sample(&pixel[offset..], format).clamp(0.0, 1.0);
+            let value = match luma {
+                Some(offset) => sample(offset),
+                None => {
+                    let mut sum = 0.0;
+                    for (offset, weight) in rgb.iter().zip(self.weights) {
+                        let value = offset.map_or(0.0, sample);
+                        sum += weight * self.linearize(value);
+                    }
+                    self.encode(sum)
+                }
+            };
+            write_sample(value, target, to_format);
+        }
+    }
+}
+
+impl LumaConverter {
+    fn linearize(&self, value: f32) -> f32 {
+        match self.transfer {
+            Transfer::Stored => value,
+            Transfer::Srgb => srgb_to_linear(value),
+        }
+    }
+
+    fn encode(&self, value: f32) -> f32 {
+        match self.transfer {
+            Transfer::Stored => value,
+            Transfer::Srgb => linear_to_srgb(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    #[test]
+    fn weights_color_channels() {
+        let data = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 10, 10];
+        let decoder = ToLuma::new(MemoryDecoder::new(4, 1, ColorType::Rgb8, data.clone()));
+        assert_eq!(decoder.color_type(), ColorType::L8);
+        assert_eq!(decoder.decode_fidelity(), DecodeFidelity::LossyConversion);
+        assert_eq!(decode(decoder), [54, 182, 18, 10]);
+
+        let decoder = MemoryDecoder::new(4, 1, ColorType::Rgb8, data);
+        let decoder = ToLuma::new(decoder).with_weights(LumaWeights::Rec601);
+        assert_eq!(decode(decoder), [76, 150, 29, 10]);
+
+        let data = vec![1, 2, 3, 4];
+        let decoder = ToLuma::new(MemoryDecoder::new(2, 1, ColorType::La8, data));
+        assert_eq!(decode(decoder), [1, 3]);
+
+        let samples = [0u16, 0xffff, 0];
+        let data = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+        let decoder = ToLuma::new(MemoryDecoder::new(1, 1, ColorType::Rgb16, data));
+        assert_eq!(decoder.color_type(), ColorType::L16);
+        let expected = (0.7152f32 * 65535.0).round() as u16;
+        assert_eq!(decode(decoder), expected.to_ne_bytes());
+    }
+
+    #[test]
+    fn drops_color_profiles() {
+        /// Tags its image with a profile.
+        struct Profiled(MemoryDecoder);
+
+        impl<'a> ImageDecoder<'a> for Profiled {
+            type Reader = <MemoryDecoder as ImageDecoder<'a>>::Reader;
+
+            fn dimensions(&self) -> (u32, u32) {
+                self.0.dimensions()
+            }
+
+            fn color_type(&self) -> ColorType {
+                self.0.color_type()
+            }
+
+            fn icc_profile(&self) -> Option<Vec<u8>> {
+                Some(vec![1, 2, 3])
+            }
+
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                self.0.into_reader()
+            }
+        }
+
+        let decoder = Profiled(MemoryDecoder::new(1, 1, ColorType::Rgb8, vec![0; 3]));
+        assert_eq!(ToLuma::new(decoder).icc_profile(), None);
+
+        let decoder = Profiled(MemoryDecoder::new(1, 1, ColorType::La8, vec![0; 2]));
+        assert_eq!(ToLuma::new(decoder).icc_profile(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn weights_in_linear_light() {
+        let data = vec![255, 0, 0, 128, 128, 128];
+        let decoder = MemoryDecoder::new(2, 1, ColorType::Rgb8, data.clone());
+        let decoder = ToLuma::new(decoder).with_linear_light(true);
+        assert_eq!(decode(decoder), [127, 128]);
+
+        let mut decoder = MemoryDecoder::new(2, 1, ColorType::Rgb8, data);
+        decoder.cicp = Some(Cicp {
+            transfer_characteristics: 8,
+            ..Cicp::SRGB
+        });
+        let decoder = ToLuma::new(decoder).with_linear_light(true);
+        assert_eq!(decode(decoder), [54, 128]);
+    }
+}
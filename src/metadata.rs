@@ -0,0 +1,274 @@
+use crate::ImageDecoder;
+
+/// A chromaticity coordinate in the CIE 1931 color space.
+///
+/// Both coordinates are in increments of 0.00002, as stored by AVIF, HEIF and the HEVC and AV1
+/// bitstreams.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Chromaticity {
+    /// The x coordinate in increments of 0.00002.
+    pub x: u16,
+    /// The y coordinate in increments of 0.00002.
+    pub y: u16,
+}
+
+/// The brightness of the content of an HDR image, as defined by CTA-861.3.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// The maximum content light level (MaxCLL) in candela per square meter.
+    pub max_content_light_level: u16,
+    /// The maximum frame-average light level (MaxFALL) in candela per square meter.
+    pub max_frame_average_light_level: u16,
+}
+
+/// The color volume of the display an HDR image was mastered on, as defined by SMPTE ST 2086.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct MasteringDisplay {
+    /// The primaries of the display in the order red, green, blue.
+    pub primaries: [Chromaticity; 3],
+    /// The white point of the display.
+    pub white_point: Chromaticity,
+    /// The maximum luminance of the display in increments of 0.0001 candela per square meter.
+    pub max_luminance: u32,
+    /// The minimum luminance of the display in increments of 0.0001 candela per square meter.
+    pub min_luminance: u32,
+}
+
+/// Metadata required to tone map an HDR image correctly.
+///
+/// The values are kept in the integer units of the file formats so that they can be passed from a
+/// decoder to an encoder without rounding.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct HdrMetadata {
+    /// The light level of the content, if known.
+    pub content_light_level: Option<ContentLightLevel>,
+    /// The display the content was mastered on, if known.
+    pub mastering_display: Option<MasteringDisplay>,
+}
+
+/// A description of the color space of an image by coding-independent code points.
+///
+/// The code points are defined by ITU-T H.273 and used by AVIF, HEIF, JPEG XL and PNG instead of,
+/// or in addition to, an ICC profile. The samples handled by this crate are RGB, so the matrix
+/// coefficients are usually `0`, the identity.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Cicp {
+    /// The chromaticities of the primaries and the white point.
+    pub color_primaries: u8,
+    /// The transfer function between the encoded values and linear light.
+    pub transfer_characteristics: u8,
+    /// The matrix deriving luma and chroma from RGB.
+    pub matrix_coefficients: u8,
+    /// Whether the samples use the full range of values, instead of the narrow video range.
+    pub full_range: bool,
+}
+
+impl Cicp {
+    /// The sRGB color space.
+    pub const SRGB: Cicp = Cicp {
+        color_primaries: 1,
+        transfer_characteristics: 13,
+        matrix_coefficients: 0,
+        full_range: true,
+    };
+
+    /// The Display P3 color space, with the sRGB transfer function.
+    pub const DISPLAY_P3: Cicp = Cicp {
+        color_primaries: 12,
+        transfer_characteristics: 13,
+        matrix_coefficients: 0,
+        full_range: true,
+    };
+
+    /// The BT.2020 primaries with the perceptual quantizer (PQ) of BT.2100.
+    pub const BT2020_PQ: Cicp = Cicp {
+        color_primaries: 9,
+        transfer_characteristics: 16,
+        matrix_coefficients: 0,
+        full_range: true,
+    };
+
+    /// The BT.2020 primaries with the hybrid log-gamma (HLG) function of BT.2100.
+    pub const BT2020_HLG: Cicp = Cicp {
+        color_primaries: 9,
+        transfer_characteristics: 18,
+        matrix_coefficients: 0,
+        full_range: true,
+    };
+
+    /// Returns whether this is the sRGB color space.
+    pub fn is_srgb(&self) -> bool {
+        *self == Cicp::SRGB
+    }
+}
+
+/// Identifies a kind of metadata an image can carry.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetadataKind {
+    /// An embedded ICC profile.
+    IccProfile,
+    /// An EXIF block.
+    Exif,
+    /// An XMP packet.
+    Xmp,
+    /// A color space description by code points.
+    Cicp,
+    /// HDR mastering metadata.
+    Hdr,
+}
+
+/// The metadata of an image which is independent of its pixels.
+///
+/// This bundles the metadata a decoder produced so that it can be passed to an encoder at once,
+/// see `ImageEncoder::set_metadata`. EXIF and XMP are kept as the raw bytes of the file.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ImageMetadata {
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    cicp: Option<Cicp>,
+    hdr: Option<HdrMetadata>,
+}
+
+impl ImageMetadata {
+    /// Create metadata where nothing is present.
+    pub fn new() -> Self {
+        ImageMetadata::default()
+    }
+
+    /// Collect the metadata which a decoder reports about its image.
+    pub fn of_decoder<'a, D: ImageDecoder<'a>>(decoder: &D) -> Self {
+        ImageMetadata {
+            icc_profile: decoder.icc_profile(),
+            exif: None,
+            xmp: None,
+            cicp: decoder.cicp(),
+            hdr: decoder.hdr_metadata(),
+        }
+    }
+
+    /// Set the ICC profile.
+    pub fn with_icc_profile(mut self, profile: Vec<u8>) -> Self {
+        self.icc_profile = Some(profile);
+        self
+    }
+
+    /// Set the EXIF block.
+    pub fn with_exif(mut self, exif: Vec<u8>) -> Self {
+        self.exif = Some(exif);
+        self
+    }
+
+    /// Set the XMP packet.
+    pub fn with_xmp(mut self, xmp: Vec<u8>) -> Self {
+        self.xmp = Some(xmp);
+        self
+    }
+
+    /// Set the color space description by code points.
+    pub fn with_cicp(mut self, cicp: Cicp) -> Self {
+        self.cicp = Some(cicp);
+        self
+    }
+
+    /// Set the HDR mastering metadata.
+    pub fn with_hdr_metadata(mut self, hdr: HdrMetadata) -> Self {
+        self.hdr = Some(hdr);
+        self
+    }
+
+    /// The ICC profile, if any.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// The EXIF block, if any.
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.exif.as_deref()
+    }
+
+    /// The XMP packet, if any.
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.xmp.as_deref()
+    }
+
+    /// The color space description by code points, if any.
+    pub fn cicp(&self) -> Option<Cicp> {
+        self.cicp
+    }
+
+    /// The HDR mastering metadata, if any.
+    pub fn hdr_metadata(&self) -> Option<&HdrMetadata> {
+        self.hdr.as_ref()
+    }
+
+    /// Check if a kind of metadata is present.
+    pub fn contains(&self, kind: MetadataKind) -> bool {
+        match kind {
+            MetadataKind::IccProfile => self.icc_profile.is_some(),
+            MetadataKind::Exif => self.exif.is_some(),
+            MetadataKind::Xmp => self.xmp.is_some(),
+            MetadataKind::Cicp => self.cicp.is_some(),
+            MetadataKind::Hdr => self.hdr.is_some(),
+        }
+    }
+}
+
+impl Chromaticity {
+    /// The coordinates as floating point values.
+    pub fn to_f32(self) -> (f32, f32) {
+        (f32::from(self.x) * 0.00002, f32::from(self.y) * 0.00002)
+    }
+}
+
+impl MasteringDisplay {
+    /// The maximum luminance in candela per square meter.
+    pub fn max_luminance_nits(&self) -> f32 {
+        self.max_luminance as f32 * 0.0001
+    }
+
+    /// The minimum luminance in candela per square meter.
+    pub fn min_luminance_nits(&self) -> f32 {
+        self.min_luminance as f32 * 0.0001
+    }
+}
+
+impl HdrMetadata {
+    /// Returns whether no metadata is present.
+    pub fn is_empty(&self) -> bool {
+        self.content_light_level.is_none() && self.mastering_display.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_units() {
+        let display = MasteringDisplay {
+            white_point: Chromaticity { x: 15635, y: 16450 },
+            max_luminance: 10_000_000,
+            min_luminance: 50,
+            ..MasteringDisplay::default()
+        };
+
+        let (x, y) = display.white_point.to_f32();
+        assert!((x - 0.3127).abs() < 1e-6 && (y - 0.329).abs() < 1e-6);
+        assert_eq!(display.max_luminance_nits(), 1000.0);
+        assert!((display.min_luminance_nits() - 0.005).abs() < 1e-6);
+
+        assert!(HdrMetadata::default().is_empty());
+    }
+
+    #[test]
+    fn cicp_constants() {
+        assert!(Cicp::SRGB.is_srgb());
+        assert!(!Cicp::BT2020_PQ.is_srgb());
+        assert_eq!(
+            Cicp::BT2020_PQ.color_primaries,
+            Cicp::BT2020_HLG.color_primaries
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+use crate::limits::insufficient_memory;
+use crate::{AlphaMode, Cicp, DecodeFidelity, HdrMetadata, ImageError, ImageResult, Limits};
+use crate::{ColorType, ExtendedColorType, ImageDecoder, LimitError, LimitErrorKind, RowOrder};
+
+/// The sizes an image is padded to by `PadToAlignment`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Alignment {
+    /// A multiple of the given number of pixels, such as the 4 by 4 blocks of BCn compression.
+    ///
+    /// A multiple of zero is treated as one.
+    Multiple(u32),
+    /// A power of two, as required for mipmapped textures by some graphics APIs.
+    PowerOfTwo,
+}
+
+impl Alignment {
+    /// Returns the least aligned size which is at least `size`.
+    ///
+    /// Zero stays zero. Returns `None` if the aligned size does not fit into a `u32`.
+    pub fn pad(self, size: u32) -> Option<u32> {
+        if size == 0 {
+            return Some(0);
+        }
+
+        match self {
+            Alignment::Multiple(multiple) => size.checked_next_multiple_of(multiple.max(1)),
+            Alignment::PowerOfTwo => size.checked_next_power_of_two(),
+        }
+    }
+}
+
+/// A decoder adapter padding the image to aligned dimensions.
+///
+/// Width and height are both padded according to the alignment, by repeating the pixels at the
+/// right and bottom edges. Rows are padded while reading, holding a single row in memory. Images
+/// which are aligned already are passed through.
+pub struct PadToAlignment<D> {
+    inner: D,
+    alignment: Alignment,
+}
+
+/// The reader of a `PadToAlignment` decoder.
+pub struct PadToAlignmentReader<R> {
+    inner: R,
+    padder: Option<Padder>,
+    /// The rows of the inner decoder which have not been read yet.
+    remaining_rows: u32,
+    /// The padded row, or nothing if the inner reader is passed through.
+    output: Vec<u8>,
+    /// The times `output` is served again after it was read.
+    repeats: u32,
+    position: usize,
+}
+
+struct Padder {
+    bpp: usize,
+    source_row: usize,
+    padded_row: usize,
+    /// The number of rows added at the bottom of the image.
+    extra_rows: u32,
+    row_order: RowOrder,
+    height: u32,
+}
+
+impl<'a, D: ImageDecoder<'a>> PadToAlignment<D> {
+    /// Wrap a decoder to pad its image to the alignment.
+    pub fn new(decoder: D, alignment: Alignment) -> Self {
+        PadToAlignment {
+            inner: decoder,
+            alignment,
+        }
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// The padded dimensions, if they fit into a `u32`.
+    fn padded_dimensions(&self) -> Option<(u32, u32)> {
+        let (width, height) = self.inner.dimensions();
+        Some((self.alignment.pad(width)?, self.alignment.pad(height)?))
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for PadToAlignment<D> {
+    type Reader = PadToAlignmentReader<D::Reader>;
+
+    /// Returns the padded dimensions.
+    ///
+    /// Dimensions which can not be padded within a `u32` are reported as `u32::MAX`, reading
+    /// such images results in a limit error.
+    fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.inner.dimensions();
+        let pad = |size| self.alignment.pad(size).unwrap_or(u32::MAX);
+        (pad(width), pad(height))
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        self.inner.set_output_color(color)
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        self.inner.decode_fidelity()
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        if self.padded_dimensions() == Some(self.inner.dimensions()) {
+            return self.inner.scanline_bytes();
+        }
+
+        u64::from(self.dimensions().0) * u64::from(self.color_type().bytes_per_pixel())
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let (width, height) = self.inner.dimensions();
+        let (padded_width, padded_height) = self.padded_dimensions().ok_or_else(|| {
+            ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError))
+        })?;
+
+        let padder = if (padded_width, padded_height) == (width, height) {
+            None
+        } else {
+            let bpp = usize::from(self.color_type().bytes_per_pixel());
+            let row_len = |width: u32| {
+                usize::try_from(u64::from(width) * bpp as u64).map_err(|_| insufficient_memory())
+            };
+            Some(Padder {
+                bpp,
+                source_row: row_len(width)?,
+                padded_row: row_len(padded_width)?,
+                extra_rows: padded_height - height,
+                row_order: self.inner.row_order(),
+                height,
+            })
+        };
+
+        let row_len = padder.as_ref().map_or(0, |padder| padder.padded_row);
+        self.inner.limits().check_alloc(row_len as u64)?;
+        Ok(PadToAlignmentReader {
+            inner: self.inner.into_reader()?,
+            padder,
+            remaining_rows: height,
+            output: vec![0; row_len],
+            repeats: 0,
+            position: row_len,
+        })
+    }
+}
+
+impl<R: Read> Read for PadToAlignmentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let padder = match &self.padder {
+            Some(padder) => padder,
+            None => return self.inner.read(buf),
+        };
+
+        if self.position == self.output.len() && self.repeats > 0 {
+            self.repeats -= 1;
+            self.position = 0;
+        } else if self.position == self.output.len() {
+            if self.remaining_rows == 0 || padder.source_row == 0 {
+                return Ok(0);
+            }
+
+            let row = &mut self.output;
+            self.inner.read_exact(&mut row[..padder.source_row])?;
+            let edge = padder.source_row - padder.bpp;
+            for start in (padder.source_row..padder.padded_row).step_by(padder.bpp) {
+                row.copy_within(edge..padder.source_row, start);
+            }
+
+            // The row at the bottom edge is repeated for the rows added below it, which come
+            // first when the rows are produced from the bottom up.
+            let bottom = match padder.row_order {
+                RowOrder::BottomUp => self.remaining_rows == padder.height,
+                _ => self.remaining_rows == 1,
+            };
+            self.repeats = if bottom { padder.extra_rows } else { 0 };
+            self.remaining_rows -= 1;
+            self.position = 0;
+        }
+
+        let available = &self.output[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    #[test]
+    fn aligns_sizes() {
+        assert_eq!(Alignment::Multiple(4).pad(5), Some(8));
+        assert_eq!(Alignment::Multiple(4).pad(8), Some(8));
+        assert_eq!(Alignment::Multiple(0).pad(5), Some(5));
+        assert_eq!(Alignment::PowerOfTwo.pad(5), Some(8));
+        assert_eq!(Alignment::PowerOfTwo.pad(0), Some(0));
+        assert_eq!(Alignment::PowerOfTwo.pad(u32::MAX), None);
+    }
+
+    #[test]
+    fn repeats_edges() {
+        let data = vec![1, 2, 3, 4, 5, 6];
+        let decoder = MemoryDecoder::new(3, 2, ColorType::L8, data);
+        let decoder = PadToAlignment::new(decoder, Alignment::Multiple(4));
+        assert_eq!(decoder.dimensions(), (4, 4));
+        assert_eq!(
+            decode(decoder),
+            [1, 2, 3, 3, 4, 5, 6, 6, 4, 5, 6, 6, 4, 5, 6, 6]
+        );
+
+        let data = vec![1, 2, 3, 4];
+        let decoder = MemoryDecoder::new(1, 2, ColorType::La8, data.clone());
+        let decoder = PadToAlignment::new(decoder, Alignment::PowerOfTwo);
+        assert_eq!(decode(decoder), data);
+    }
+
+    #[test]
+    fn repeats_bottom_row_in_place() {
+        let decoder = MemoryDecoder::new(2, 5, ColorType::L8, (0..10).collect());
+        let mut reader = PadToAlignment::new(decoder, Alignment::PowerOfTwo)
+            .into_reader()
+            .unwrap();
+        assert_eq!(reader.output.len(), 2);
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes[..10], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(bytes[10..], [8, 9, 8, 9, 8, 9]);
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::Read;
+
+use crate::{Dimensions, ExtendedColorType, ImageFormat, ImageResult};
+
+/// Basic properties of an image, as read from the header of its file.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ImageInfo {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    color_type: ExtendedColorType,
+}
+
+impl ImageInfo {
+    /// Describe an image of the given format, dimensions and color type of the file.
+    pub fn new(
+        format: ImageFormat,
+        (width, height): (u32, u32),
+        color_type: ExtendedColorType,
+    ) -> Self {
+        ImageInfo {
+            format,
+            width,
+            height,
+            color_type,
+        }
+    }
+
+    /// The format of the file.
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// Returns a tuple containing the width and height of the image.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the width and height of the image.
+    pub fn size(&self) -> Dimensions {
+        Dimensions::new(self.width, self.height)
+    }
+
+    /// The color type of the samples stored in the file.
+    ///
+    /// This corresponds to `ImageDecoder::original_color_type`, a decoder may produce a different
+    /// color type.
+    pub fn color_type(&self) -> ExtendedColorType {
+        self.color_type
+    }
+}
+
+/// Reads the basic properties of an image without constructing a decoder.
+///
+/// A probe parses only as much of the header as is needed, which makes it much cheaper than a
+/// decoder when scanning many files. The trait is object safe so that probes of different formats
+/// can be kept side by side.
+pub trait ImageProbe {
+    /// The format this probe understands.
+    fn format(&self) -> ImageFormat;
+
+    /// Read the header of an image from the start of the reader.
+    ///
+    /// The reader is left at an unspecified position. Files that are not of the format of this
+    /// probe result in a decoding or unsupported error.
+    fn probe(&self, reader: &mut dyn Read) -> ImageResult<ImageInfo>;
+
+    /// Read the header of an image held in memory.
+    fn probe_bytes(&self, bytes: &[u8]) -> ImageResult<ImageInfo> {
+        let mut reader = bytes;
+        self.probe(&mut reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodingError, ImageError};
+
+    /// Reads the IHDR chunk of a PNG file.
+    struct PngProbe;
+
+    impl ImageProbe for PngProbe {
+        fn format(&self) -> ImageFormat {
+            ImageFormat::Png
+        }
+
+        fn probe(&self, reader: &mut dyn Read) -> ImageResult<ImageInfo> {
+            let mut header = [0; 26];
+            reader.read_exact(&mut header)?;
+            if &header[..8] != b"\x89PNG\r\n\x1a\n" || &header[12..16] != b"IHDR" {
+                return Err(ImageError::Decoding(DecodingError::from_format_hint(
+                    ImageFormat::Png.into(),
+                )));
+            }
+
+            let read_u32 = |at: usize| {
+                u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+            };
+            let color = match (header[25], header[24]) {
+                (0, 8) => ExtendedColorType::L8,
+                (2, 16) => ExtendedColorType::Rgb16,
+                (3, bits) => ExtendedColorType::Unknown {
+                    bits_per_sample: bits,
+                    channels: 1,
+                },
+                (_, bits) => ExtendedColorType::Unknown {
+                    bits_per_sample: bits,
+                    channels: 0,
+                },
+            };
+            Ok(ImageInfo::new(
+                ImageFormat::Png,
+                (read_u32(16), read_u32(20)),
+                color,
+            ))
+        }
+    }
+
+    #[test]
+    fn probes_header_only() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        png.extend_from_slice(&[4, 3, 0, 0, 0]);
+
+        let probe: &dyn ImageProbe = &PngProbe;
+        let info = probe.probe_bytes(&png).unwrap();
+        assert_eq!(info.format(), ImageFormat::Png);
+        assert_eq!(info.dimensions(), (640, 480));
+        assert_eq!(info.color_type().bits_per_pixel(), 4);
+
+        assert!(probe.probe_bytes(&png[..20]).is_err());
+    }
+}
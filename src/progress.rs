@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use crate::Progress;
+
+/// Estimates throughput and remaining time from a sequence of `Progress` updates.
+///
+/// The rate is an exponential moving average of the rates observed between consecutive updates,
+/// which smooths out bursts of a decoder or encoder. Its unit is that of `Progress`, usually bytes,
+/// per second.
+///
+/// # Examples
+///
+/// ```
+/// use std::cell::RefCell;
+/// use image_core::{Progress, ProgressTracker};
+///
+/// let tracker = RefCell::new(ProgressTracker::new());
+/// let callback = |progress: Progress| {
+///     let mut tracker = tracker.borrow_mut();
+///     tracker.update(progress);
+///     if let Some(eta) = tracker.estimated_remaining() {
+///         println!("{:.0} bytes/s, done in {:?}", tracker.rate().unwrap(), eta);
+///     }
+/// };
+/// # callback(Progress { current: 0, total: 1 });
+/// ```
+#[derive(Clone, Debug)]
+pub struct ProgressTracker {
+    smoothing: f64,
+    last: Option<(Instant, Progress)>,
+    rate: Option<f64>,
+}
+
+impl ProgressTracker {
+    /// The default weight of the newest observation in the moving average.
+    pub const DEFAULT_SMOOTHING: f64 = 0.3;
+
+    /// Create a tracker without any observations.
+    pub fn new() -> Self {
+        ProgressTracker {
+            smoothing: Self::DEFAULT_SMOOTHING,
+            last: None,
+            rate: None,
+        }
+    }
+
+    /// Set the weight of the newest observation in the moving average.
+    ///
+    /// A value of `1.0` disables smoothing, smaller values react slower to changes of the rate.
+    /// The value is clamped to the range from `0.01` to `1.0`.
+    pub fn with_smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = if smoothing.is_nan() {
+            Self::DEFAULT_SMOOTHING
+        } else {
+            smoothing.clamp(0.01, 1.0)
+        };
+        self
+    }
+
+    /// Record a progress update at the current time.
+    pub fn update(&mut self, progress: Progress) {
+        self.update_at(progress, Instant::now())
+    }
+
+    /// Record a progress update that was observed at the given time.
+    ///
+    /// Progress that goes backwards, or updates older than the previous one, are taken as the start
+    /// of a new operation and reset the estimate.
+    pub fn update_at(&mut self, progress: Progress, now: Instant) {
+        let (then, previous) = match self.last {
+            Some((then, previous)) if now >= then && progress.current >= previous.current => {
+                (then, previous)
+            }
+            _ => {
+                self.last = Some((now, progress));
+                self.rate = None;
+                return;
+            }
+        };
+
+        let elapsed = now.duration_since(then).as_secs_f64();
+        if elapsed == 0.0 {
+            // Attribute the work to the next observation instead.
+            return;
+        }
+
+        let observed = (progress.current - previous.current) as f64 / elapsed;
+        self.rate = Some(match self.rate {
+            Some(rate) => rate + self.smoothing * (observed - rate),
+            None => observed,
+        });
+        self.last = Some((now, progress));
+    }
+
+    /// The most recent progress that was recorded.
+    pub fn progress(&self) -> Option<Progress> {
+        self.last.map(|(_, progress)| progress)
+    }
+
+    /// The smoothed rate of progress per second.
+    ///
+    /// This is `None` until two updates at different times have been recorded.
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// The estimated time until the operation completes, based on the smoothed rate.
+    ///
+    /// This is `None` while there is no rate, or when progress has stalled.
+    pub fn estimated_remaining(&self) -> Option<Duration> {
+        let remaining = self.progress()?.remaining();
+        if remaining == 0 {
+            return Some(Duration::from_secs(0));
+        }
+
+        let rate = self.rate?;
+        if rate <= 0.0 {
+            return None;
+        }
+
+        let seconds = remaining as f64 / rate;
+        if seconds.is_finite() && seconds < u64::MAX as f64 {
+            Some(Duration::from_secs_f64(seconds))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        ProgressTracker::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(current: u64) -> Progress {
+        Progress {
+            current,
+            total: 1000,
+        }
+    }
+
+    #[test]
+    fn estimates_rate_and_remaining_time() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new().with_smoothing(1.0);
+        tracker.update_at(at(0), start);
+        assert_eq!(tracker.rate(), None);
+
+        tracker.update_at(at(100), start + Duration::from_secs(1));
+        assert_eq!(tracker.rate(), Some(100.0));
+        assert_eq!(tracker.estimated_remaining(), Some(Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn smooths_rate() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new().with_smoothing(0.5);
+        tracker.update_at(at(0), start);
+        tracker.update_at(at(100), start + Duration::from_secs(1));
+        tracker.update_at(at(400), start + Duration::from_secs(2));
+        assert_eq!(tracker.rate(), Some(200.0));
+    }
+
+    #[test]
+    fn resets_on_new_operation() {
+        let start = Instant::now();
+        let mut tracker = ProgressTracker::new();
+        tracker.update_at(at(500), start);
+        tracker.update_at(at(600), start + Duration::from_secs(1));
+        tracker.update_at(at(10), start + Duration::from_secs(2));
+        assert_eq!(tracker.rate(), None);
+        assert_eq!(tracker.progress(), Some(at(10)));
+    }
+}
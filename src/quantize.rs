@@ -0,0 +1,340 @@
+//! Reducing images to a palette of colors.
+//!
+//! Formats such as GIF and indexed PNG store an index into a palette for each pixel. `quantize`
+//! chooses a palette for an image with the median cut algorithm, and `map_to_palette` replaces
+//! every pixel by the index of the nearest color of a palette. The indices are passed to encoders
+//! as `INDEX_COLOR_TYPE` after the palette was set with `ImageEncoder::set_palette`.
+//!
+//! Colors are compared as 8-bit RGBA, pixels of other color types are converted with
+//! `convert::convert_color` first. All fully transparent pixels are treated as the same color.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::convert::convert_color;
+use crate::error::{ImageError, ParameterError, ParameterErrorKind};
+use crate::{ColorType, ExtendedColorType, ImageResult};
+
+/// The color type of indices into a palette, one byte per pixel.
+pub const INDEX_COLOR_TYPE: ExtendedColorType = ExtendedColorType::Unknown {
+    bits_per_sample: 8,
+    channels: 1,
+};
+
+/// The largest number of colors of a palette, as addressed by 8-bit indices.
+pub const MAX_PALETTE_LEN: usize = 256;
+
+/// The number of pixels converted to RGBA at once.
+const CHUNK_PIXELS: usize = 1024;
+
+/// A list of at most 256 colors in 8-bit RGBA.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 4]>,
+}
+
+impl Palette {
+    /// Create a palette of the given colors.
+    ///
+    /// Returns a parameter error if there are more than `MAX_PALETTE_LEN` colors.
+    pub fn new(colors: Vec<[u8; 4]>) -> ImageResult<Self> {
+        if colors.len() > MAX_PALETTE_LEN {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("a palette of {} colors", colors.len())),
+            )));
+        }
+        Ok(Palette { colors })
+    }
+
+    /// The colors of the palette, in the order of their indices.
+    pub fn colors(&self) -> &[[u8; 4]] {
+        &self.colors
+    }
+
+    /// The number of colors.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Returns whether the palette has no colors.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Returns whether every color of the palette is fully opaque.
+    ///
+    /// Formats such as PNG then need no separate transparency information.
+    pub fn is_opaque(&self) -> bool {
+        self.colors.iter().all(|color| color[3] == u8::MAX)
+    }
+
+    /// The colors as consecutive RGB triples, dropping alpha.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|color| &color[..3])
+            .copied()
+            .collect()
+    }
+
+    /// Returns the index of the color closest to `color`, or `None` if the palette is empty.
+    ///
+    /// Colors are compared by the squared distance of their samples.
+    pub fn nearest_index(&self, color: [u8; 4]) -> Option<u8> {
+        let color = canonical(color);
+        let distance = |entry: &[u8; 4]| -> u32 {
+            let entry = canonical(*entry);
+            entry
+                .iter()
+                .zip(&color)
+                .map(|(&a, &b)| u32::from(a.abs_diff(b)).pow(2))
+                .sum()
+        };
+
+        let (index, _) = self
+            .colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| distance(entry))?;
+        // Palettes hold at most `MAX_PALETTE_LEN` colors.
+        Some(index as u8)
+    }
+}
+
+/// Choose a palette of at most `max_colors` colors for the pixels.
+///
+/// Images with few enough colors are represented exactly. Otherwise the colors are split
+/// repeatedly at the weighted median of the channel with the largest range, and each part is
+/// represented by the average of its pixels. The result only depends on the pixels, not on their
+/// order. `max_colors` is limited to `MAX_PALETTE_LEN`, at least one color is chosen for images
+/// which are not empty.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels.
+pub fn quantize(pixels: &[u8], color: ColorType, max_colors: usize) -> Palette {
+    let mut histogram = HashMap::new();
+    for_each_rgba(pixels, color, |pixel| {
+        *histogram.entry(canonical(pixel)).or_insert(0u64) += 1;
+    });
+
+    let mut colors: Vec<([u8; 4], u64)> = histogram.into_iter().collect();
+    colors.sort_unstable();
+    let max_colors = max_colors.clamp(1, MAX_PALETTE_LEN);
+
+    let mut boxes = Vec::new();
+    if !colors.is_empty() {
+        boxes.push(ColorBox::new(0, colors.len(), &colors));
+    }
+    while boxes.len() < max_colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.len() > 1)
+            .max_by_key(|(_, color_box)| (color_box.range, color_box.population));
+        let index = match widest {
+            Some((index, _)) => index,
+            None => break,
+        };
+
+        let color_box = boxes.swap_remove(index);
+        let (first, second) = color_box.split(&mut colors);
+        boxes.push(first);
+        boxes.push(second);
+    }
+
+    boxes.sort_unstable_by_key(|color_box| color_box.start);
+    Palette {
+        colors: boxes.iter().map(|b| b.average(&colors)).collect(),
+    }
+}
+
+/// Replace every pixel by the index of the nearest color of the palette.
+///
+/// The indices have the color type `INDEX_COLOR_TYPE`.
+///
+/// # Panics
+///
+/// Panics if the buffer does not hold whole pixels, or if it holds pixels and the palette is
+/// empty.
+pub fn map_to_palette(pixels: &[u8], color: ColorType, palette: &Palette) -> Vec<u8> {
+    let mut cache = HashMap::new();
+    let mut indices = Vec::with_capacity(pixels.len() / usize::from(color.bytes_per_pixel()));
+    for_each_rgba(pixels, color, |pixel| {
+        let index = *cache.entry(canonical(pixel)).or_insert_with(|| {
+            palette
+                .nearest_index(pixel)
+                .expect("pixels can not be mapped to an empty palette")
+        });
+        indices.push(index);
+    });
+    indices
+}
+
+/// A range of the sorted colors of an image, represented by one color of the palette.
+struct ColorBox {
+    start: usize,
+    end: usize,
+    /// The number of pixels of all colors of the box.
+    population: u64,
+    /// The channel with the largest range of values.
+    channel: usize,
+    range: u8,
+}
+
+impl ColorBox {
+    fn new(start: usize, end: usize, colors: &[([u8; 4], u64)]) -> Self {
+        let (mut min, mut max) = ([u8::MAX; 4], [0; 4]);
+        let mut population = 0;
+        for &(color, count) in &colors[start..end] {
+            for channel in 0..4 {
+                min[channel] = min[channel].min(color[channel]);
+                max[channel] = max[channel].max(color[channel]);
+            }
+            population += count;
+        }
+
+        let ranges = (0..4).map(|channel| max[channel].saturating_sub(min[channel]));
+        let (channel, range) = ranges
+            .enumerate()
+            .max_by_key(|&(channel, range)| (range, Reverse(channel)))
+            .unwrap_or((0, 0));
+        ColorBox {
+            start,
+            end,
+            population,
+            channel,
+            range,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Split the box at the weighted median of the channel with the largest range.
+    ///
+    /// The box must hold at least two colors, each part receives at least one of them.
+    fn split(self, colors: &mut [([u8; 4], u64)]) -> (ColorBox, ColorBox) {
+        let channel = self.channel;
+        let part = &mut colors[self.start..self.end];
+        part.sort_unstable_by_key(|&(color, _)| (color[channel], color));
+
+        let mut count = 0;
+        let median = part
+            .iter()
+            .position(|&(_, pixels)| {
+                count += pixels;
+                count * 2 >= self.population
+            })
+            .map_or(1, |index| index + 1)
+            .clamp(1, part.len() - 1);
+
+        let middle = self.start + median;
+        (
+            ColorBox::new(self.start, middle, colors),
+            ColorBox::new(middle, self.end, colors),
+        )
+    }
+
+    /// The average of the colors of the box, weighted by their number of pixels.
+    fn average(&self, colors: &[([u8; 4], u64)]) -> [u8; 4] {
+        let mut sums = [0u64; 4];
+        for &(color, count) in &colors[self.start..self.end] {
+            for (sum, &sample) in sums.iter_mut().zip(&color) {
+                *sum += u64::from(sample) * count;
+            }
+        }
+        let population = self.population.max(1);
+        sums.map(|sum| ((sum + population / 2) / population) as u8)
+    }
+}
+
+/// All fully transparent colors are the same.
+fn canonical(color: [u8; 4]) -> [u8; 4] {
+    if color[3] == 0 {
+        [0; 4]
+    } else {
+        color
+    }
+}
+
+/// Call `f` with every pixel converted to 8-bit RGBA.
+fn for_each_rgba<F: FnMut([u8; 4])>(pixels: &[u8], color: ColorType, mut f: F) {
+    let bpp = usize::from(color.bytes_per_pixel());
+    assert!(
+        pixels.len().is_multiple_of(bpp),
+        "{} bytes are not whole pixels of {:?}",
+        pixels.len(),
+        color,
+    );
+
+    let mut rgba = vec![0; CHUNK_PIXELS * 4];
+    for chunk in pixels.chunks(CHUNK_PIXELS * bpp) {
+        let rgba = &mut rgba[..chunk.len() / bpp * 4];
+        convert_color(chunk, color, rgba, ColorType::Rgba8);
+        for pixel in rgba.chunks_exact(4) {
+            f([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_few_colors_exactly() {
+        let pixels = [255, 0, 0, 0, 0, 255, 255, 0, 0, 10, 20, 30];
+        let palette = quantize(&pixels, ColorType::Rgb8, 16);
+        assert_eq!(
+            palette.colors(),
+            [[10, 20, 30, 255], [0, 0, 255, 255], [255, 0, 0, 255]]
+        );
+        assert!(palette.is_opaque());
+        assert_eq!(palette.to_rgb8()[..3], [10, 20, 30]);
+        assert_eq!(
+            map_to_palette(&pixels, ColorType::Rgb8, &palette),
+            [2, 1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn reduces_colors() {
+        let pixels: Vec<u8> = (0..=255).collect();
+        let palette = quantize(&pixels, ColorType::L8, 4);
+        let levels: Vec<u8> = palette.colors().iter().map(|color| color[0]).collect();
+        assert_eq!(levels, [32, 96, 160, 224]);
+
+        let indices = map_to_palette(&pixels, ColorType::L8, &palette);
+        assert_eq!(indices[..3], [0, 0, 0]);
+        assert_eq!(indices[255], 3);
+
+        let transparent = [1, 2, 3, 0, 4, 5, 6, 0, 9, 9, 9, 255];
+        let palette = quantize(&transparent, ColorType::Rgba8, 256);
+        assert_eq!(palette.colors(), [[0, 0, 0, 0], [9, 9, 9, 255]]);
+        assert!(!palette.is_opaque());
+        assert_eq!(palette.nearest_index([7, 7, 7, 0]), Some(0));
+        assert_eq!(Palette::default().nearest_index([0; 4]), None);
+        assert!(Palette::new(vec![[0; 4]; 257]).is_err());
+    }
+
+    #[test]
+    fn maps_full_palettes() {
+        let pixels: Vec<u8> = (0..=255)
+            .flat_map(|value| [value, 0, 255 - value])
+            .collect();
+        let palette = quantize(&pixels, ColorType::Rgb8, MAX_PALETTE_LEN);
+        assert_eq!(palette.len(), MAX_PALETTE_LEN);
+
+        let indices = map_to_palette(&pixels, ColorType::Rgb8, &palette);
+        assert_eq!(indices.len(), 256);
+        assert!(indices.contains(&255));
+        let last = pixels.len() - 3;
+        assert_eq!(
+            palette.colors()[usize::from(indices[255])],
+            [pixels[last], pixels[last + 1], pixels[last + 2], 255]
+        );
+        assert_eq!(palette.nearest_index([255, 0, 0, 255]), Some(indices[255]));
+    }
+}
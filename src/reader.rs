@@ -0,0 +1,332 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ContextError, ImageError, ImageFormatHint, Operation, UnsupportedError};
+use crate::{guess_format_candidates, BoxedDecoder, CodecRegistry, Confidence, ImageDecoder};
+use crate::{ImageFormat, ImageResult, LimitedReader, Limits, PixelBuffer};
+
+/// The number of bytes inspected to detect the format, enough to tell DNG files from TIFF.
+const DETECT_BYTES: u64 = 4096;
+
+/// Opens images of any format with a registered decoder.
+///
+/// The format is detected from the content of the file with `guess_format_candidates`. Formats
+/// without a signature, or files with an unrecognized one, fall back to the format of the file
+/// extension. Decoders receive the configured limits before they are returned, and read the
+/// image through a `LimitedReader` if `Limits::max_input_bytes` is set.
+///
+/// Errors of `open_with_context`, `into_decoder_with_context` and `decode_with_context` carry the
+/// format and the path of the file, if known, see `ContextError`.
+pub struct ImageReader<'r, R> {
+    reader: R,
+    registry: &'r CodecRegistry,
+    format: Option<ImageFormat>,
+    extension_format: Option<ImageFormat>,
+    limits: Limits,
+    path: Option<PathBuf>,
+}
+
+impl<'r, R: BufRead + Seek> ImageReader<'r, R> {
+    /// Read an image from the current position of a reader, with the default limits.
+    pub fn new(reader: R, registry: &'r CodecRegistry) -> Self {
+        ImageReader {
+            reader,
+            registry,
+            format: None,
+            extension_format: None,
+            limits: Limits::default(),
+            path: None,
+        }
+    }
+
+    /// Decode the image as the given format, without detecting it.
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Use the format of a file extension if the format can not be detected from the content.
+    ///
+    /// Unknown extensions are ignored.
+    pub fn with_extension_hint<S: AsRef<OsStr>>(mut self, extension: S) -> Self {
+        self.extension_format = ImageFormat::from_extension(extension);
+        self
+    }
+
+    /// Set the limits the decoder has to respect.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the format the image is decoded as.
+    ///
+    /// The most likely format of the content which has a registered decoder is chosen, ignoring
+    /// detections of `Low` confidence. Then the format of the extension hint is used, if it has a
+    /// registered decoder. The reader is returned to its position afterwards. Results in an
+    /// unsupported error if no registered decoder applies.
+    pub fn guess_format(&mut self) -> ImageResult<ImageFormat> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+
+        let start = self.reader.stream_position()?;
+        let mut prefix = Vec::new();
+        (&mut self.reader)
+            .take(DETECT_BYTES)
+            .read_to_end(&mut prefix)?;
+        self.reader.seek(SeekFrom::Start(start))?;
+
+        let candidates = guess_format_candidates(&prefix);
+        let detected = candidates
+            .iter()
+            .filter(|&&(_, confidence)| confidence > Confidence::Low)
+            .map(|&(format, _)| format)
+            .find(|&format| self.registry.can_decode(format));
+
+        let format = detected
+            .or_else(|| {
+                self.extension_format
+                    .filter(|&format| self.registry.can_decode(format))
+            })
+            .ok_or_else(|| {
+                let hint = match (self.extension_format, candidates.first()) {
+                    (Some(format), _) | (None, Some(&(format, _))) => format.into(),
+                    (None, None) => ImageFormatHint::Unknown,
+                };
+                ImageError::Unsupported(UnsupportedError::from(hint))
+            })?;
+
+        self.format = Some(format);
+        Ok(format)
+    }
+
+    /// Create the decoder of the image.
+    pub fn into_decoder<'a>(mut self) -> ImageResult<BoxedDecoder<'a>>
+    where
+        R: 'a,
+    {
+        let format = self.guess_format()?;
+        #[cfg(feature = "tracing")]
+        let _span = format_span(format);
+        self.create_decoder(format)
+    }
+
+    fn create_decoder<'a>(self, format: ImageFormat) -> ImageResult<BoxedDecoder<'a>>
+    where
+        R: 'a,
+    {
+        let factory = self.registry.decoder_factory(format).ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from(ImageFormatHint::Exact(format)))
+        })?;
+
+        let mut decoder = match self.limits.max_input_bytes {
+            Some(max) => factory.create_decoder(Box::new(LimitedReader::new(self.reader, max)))?,
+            None => factory.create_decoder(Box::new(self.reader))?,
+        };
+        decoder.set_limits(self.limits)?;
+        Ok(decoder)
+    }
+
+    /// Decode the image into a buffer.
+    pub fn decode(mut self) -> ImageResult<PixelBuffer> {
+        let format = self.guess_format()?;
+        #[cfg(feature = "tracing")]
+        let _span = format_span(format);
+        let decoder = self.create_decoder(format)?;
+        let (width, height) = decoder.dimensions();
+        let color = decoder.color_type();
+        PixelBuffer::from_vec(width, height, color, decoder.read_image_to_vec()?)
+    }
+
+    /// Same as `into_decoder`, but attaches the operation that failed to errors.
+    ///
+    /// The errors also carry the detected format and the path of files opened with `open`.
+    pub fn into_decoder_with_context<'a>(mut self) -> Result<BoxedDecoder<'a>, ContextError>
+    where
+        R: 'a,
+    {
+        let format = self.detect_with_context()?;
+        let path = self.path.take();
+        self.into_decoder()
+            .map_err(|err| with_input(err, format.into(), Operation::Decode, path))
+    }
+
+    /// Same as `decode`, but attaches the operation that failed to errors.
+    ///
+    /// The errors also carry the detected format and the path of files opened with `open`.
+    pub fn decode_with_context(mut self) -> Result<PixelBuffer, ContextError> {
+        let format = self.detect_with_context()?;
+        let path = self.path.take();
+        self.decode()
+            .map_err(|err| with_input(err, format.into(), Operation::Decode, path))
+    }
+
+    fn detect_with_context(&mut self) -> Result<ImageFormat, ContextError> {
+        self.guess_format().map_err(|err| {
+            let path = self.path.clone();
+            with_input(err, ImageFormatHint::Unknown, Operation::DetectFormat, path)
+        })
+    }
+}
+
+impl<'r> ImageReader<'r, BufReader<File>> {
+    /// Open an image file, using its extension as a hint for the format.
+    pub fn open<P: AsRef<Path>>(path: P, registry: &'r CodecRegistry) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut reader = ImageReader::new(BufReader::new(File::open(path)?), registry);
+        reader.path = Some(path.to_path_buf());
+        Ok(match path.extension() {
+            Some(extension) => reader.with_extension_hint(extension),
+            None => reader,
+        })
+    }
+
+    /// Same as `open`, but attaches the path and `Operation::Open` to errors.
+    pub fn open_with_context<P: AsRef<Path>>(
+        path: P,
+        registry: &'r CodecRegistry,
+    ) -> Result<Self, ContextError> {
+        let path = path.as_ref();
+        ImageReader::open(path, registry).map_err(|err| {
+            let path = Some(path.to_path_buf());
+            with_input(err.into(), ImageFormatHint::Unknown, Operation::Open, path)
+        })
+    }
+}
+
+/// Enter a span with the format of an image, which encloses the spans of its decoder.
+#[cfg(feature = "tracing")]
+fn format_span(format: ImageFormat) -> tracing::span::EnteredSpan {
+    tracing::debug_span!("image", format = ?format).entered()
+}
+
+/// Attach the context of the input to an error.
+fn with_input(
+    err: ImageError,
+    format: ImageFormatHint,
+    operation: Operation,
+    path: Option<PathBuf>,
+) -> ContextError {
+    let err = err.with_context(format, operation);
+    match path {
+        Some(path) => err.with_path(path),
+        None => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeFactory;
+    use crate::{ColorType, LimitErrorKind};
+    use std::io::Cursor;
+
+    fn registry() -> CodecRegistry {
+        CodecRegistry::new()
+            .with_decoder(FakeFactory(ImageFormat::Pnm, b"P5"))
+            .with_decoder(FakeFactory(ImageFormat::Tga, b""))
+    }
+
+    #[test]
+    fn detects_the_format() {
+        let registry = registry();
+        let file = Cursor::new(b"P5\x02\x01\x07\x08".to_vec());
+
+        let mut reader = ImageReader::new(file, &registry).with_extension_hint("tga");
+        assert_eq!(reader.guess_format().unwrap(), ImageFormat::Pnm);
+        let image = reader.decode().unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.color_type(), ColorType::L8);
+        assert_eq!(image.as_bytes(), [7, 8]);
+    }
+
+    #[test]
+    fn falls_back_to_the_extension() {
+        let registry = registry();
+        let file = || Cursor::new(b"\x01\x01\x2a".to_vec());
+
+        let image = ImageReader::new(file(), &registry)
+            .with_extension_hint("TGA")
+            .decode()
+            .unwrap();
+        assert_eq!(image.as_bytes(), [42]);
+
+        match ImageReader::new(file(), &registry).into_decoder() {
+            Err(ImageError::Unsupported(_)) => {}
+            _ => panic!("decoded an unknown format"),
+        }
+    }
+
+    #[test]
+    fn applies_limits() {
+        let registry = registry();
+        let file = Cursor::new(b"P5\x02\x01\x07\x08".to_vec());
+        let limits = Limits {
+            max_image_width: Some(1),
+            ..Limits::default()
+        };
+
+        match ImageReader::new(file, &registry)
+            .with_limits(limits)
+            .into_decoder()
+        {
+            Err(ImageError::Limits(err)) => {
+                assert_eq!(err.kind(), LimitErrorKind::DimensionError)
+            }
+            _ => panic!("limits were not applied"),
+        }
+
+        let file = Cursor::new(b"P5\x02\x02\x07\x08\x09\x0a".to_vec());
+        let limits = Limits {
+            max_input_bytes: Some(5),
+            ..Limits::default()
+        };
+        match ImageReader::new(file, &registry)
+            .with_limits(limits)
+            .decode()
+        {
+            Err(ImageError::Limits(err)) => {
+                assert_eq!(err.kind(), LimitErrorKind::InputTooLarge)
+            }
+            _ => panic!("input limit was not applied"),
+        }
+    }
+
+    #[test]
+    fn attaches_context_to_errors() {
+        let registry = registry();
+        let file = Cursor::new(b"P5\x02\x02\x07".to_vec());
+        let mut reader = ImageReader::new(file, &registry);
+        reader.path = Some(PathBuf::from("frames/0001.pgm"));
+
+        let err = reader.decode_with_context().unwrap_err();
+        assert_eq!(err.operation(), Operation::Decode);
+        assert_eq!(err.format_hint(), &ImageFormatHint::Exact(ImageFormat::Pnm));
+        assert_eq!(err.path(), Some(Path::new("frames/0001.pgm")));
+        assert_eq!(
+            err.io_error().map(io::Error::kind),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+        assert!(err
+            .to_string()
+            .starts_with("Failed to decode frames/0001.pgm as Pnm: "));
+
+        let file = Cursor::new(b"unknown".to_vec());
+        let err = ImageReader::new(file, &registry)
+            .into_decoder_with_context()
+            .err()
+            .unwrap();
+        assert_eq!(err.operation(), Operation::DetectFormat);
+        assert!(matches!(err.into_error(), ImageError::Unsupported(_)));
+
+        let err = ImageReader::open_with_context("missing/0001.pgm", &registry)
+            .err()
+            .unwrap();
+        assert_eq!(err.operation(), Operation::Open);
+        assert_eq!(err.path(), Some(Path::new("missing/0001.pgm")));
+        assert_eq!(err.kind(), crate::ErrorKind::Io);
+    }
+}
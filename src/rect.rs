@@ -0,0 +1,132 @@
+use crate::Dimensions;
+
+/// A rectangular region of an image, in pixels.
+///
+/// The region starts at the column `x` and row `y`, and spans `width` columns and `height` rows.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Rect {
+    /// The leftmost column of the region.
+    pub x: u32,
+    /// The topmost row of the region.
+    pub y: u32,
+    /// The number of columns of the region.
+    pub width: u32,
+    /// The number of rows of the region.
+    pub height: u32,
+}
+
+impl Rect {
+    /// Create a rectangle from its position and size.
+    pub const fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Create a rectangle covering a whole image of the given dimensions.
+    pub const fn from_dimensions((width, height): (u32, u32)) -> Self {
+        Rect::new(0, 0, width, height)
+    }
+
+    /// Returns a tuple containing the width and height of the rectangle.
+    pub const fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the width and height of the rectangle.
+    pub const fn size(&self) -> Dimensions {
+        Dimensions::new(self.width, self.height)
+    }
+
+    /// Returns the number of pixels in the rectangle.
+    pub const fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Returns whether the rectangle contains no pixels.
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The column after the rightmost one, or `None` if that overflows.
+    pub fn right(&self) -> Option<u32> {
+        self.x.checked_add(self.width)
+    }
+
+    /// The row after the bottommost one, or `None` if that overflows.
+    pub fn bottom(&self) -> Option<u32> {
+        self.y.checked_add(self.height)
+    }
+
+    /// Returns whether the pixel at the given position lies within the rectangle.
+    pub fn contains_point(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (u64::from(x), u64::from(y));
+        let (left, top) = (u64::from(self.x), u64::from(self.y));
+        left <= x
+            && x < left + u64::from(self.width)
+            && top <= y
+            && y < top + u64::from(self.height)
+    }
+
+    /// Returns whether another rectangle lies completely within this one.
+    ///
+    /// An empty rectangle is contained if its position lies within or on the edge of this one.
+    pub fn contains(&self, other: &Rect) -> bool {
+        let end = |start: u32, len: u32| u64::from(start) + u64::from(len);
+        self.x <= other.x
+            && self.y <= other.y
+            && end(other.x, other.width) <= end(self.x, self.width)
+            && end(other.y, other.height) <= end(self.y, self.height)
+    }
+
+    /// Returns the overlap of two rectangles, or `None` if they do not share a pixel.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let end = |start: u32, len: u32| u64::from(start) + u64::from(len);
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = end(self.x, self.width).min(end(other.x, other.width));
+        let bottom = end(self.y, self.height).min(end(other.y, other.height));
+
+        if u64::from(x) < right && u64::from(y) < bottom {
+            Some(Rect::new(
+                x,
+                y,
+                (right - u64::from(x)) as u32,
+                (bottom - u64::from(y)) as u32,
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containment() {
+        let image = Rect::from_dimensions((10, 5));
+        assert!(image.contains(&Rect::new(2, 1, 8, 4)));
+        assert!(image.contains(&Rect::new(10, 5, 0, 0)));
+        assert!(!image.contains(&Rect::new(9, 0, 2, 1)));
+        assert!(!image.contains(&Rect::new(0, u32::MAX, 1, 1)));
+
+        assert!(image.contains_point(9, 4));
+        assert!(!image.contains_point(10, 0));
+        assert_eq!(Rect::new(u32::MAX, 0, 1, 1).right(), None);
+    }
+
+    #[test]
+    fn intersections() {
+        let a = Rect::new(0, 0, 4, 4);
+        let b = Rect::new(2, 3, 10, 10);
+        assert_eq!(a.intersection(&b), Some(Rect::new(2, 3, 2, 1)));
+        assert_eq!(b.intersection(&a), a.intersection(&b));
+        assert_eq!(a.intersection(&Rect::new(4, 0, 1, 1)), None);
+        assert_eq!(a.intersection(&Rect::new(1, 1, 0, 0)), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::{BufRead, Seek};
+
+use crate::{BoxedDecoder, ImageFormat, ImageResult};
+
+/// A reader which is buffered and can be positioned, as needed by the decoders of most formats.
+///
+/// This is implemented for all types implementing both traits.
+pub trait BufReadSeek: BufRead + Seek {}
+
+impl<T: BufRead + Seek + ?Sized> BufReadSeek for T {}
+
+/// Constructs the decoders of one format.
+///
+/// Codec crates implement this to make their decoder available through a `CodecRegistry`. The
+/// trait is object safe so that factories of different formats can be kept side by side.
+pub trait DecoderFactory {
+    /// The format this factory decodes.
+    fn format(&self) -> ImageFormat;
+
+    /// Create a decoder reading an image from the current position of the reader.
+    ///
+    /// Files that are not of the format of this factory result in a decoding or unsupported
+    /// error.
+    fn create_decoder<'a>(
+        &self,
+        reader: Box<dyn BufReadSeek + 'a>,
+    ) -> ImageResult<BoxedDecoder<'a>>;
+}
+
+/// The decoders available to an application, by format.
+///
+/// A registry is filled with the factories of the codec crates an application depends on, and
+/// then selects the decoder for a file at runtime. See `ImageReader` for opening files with it.
+#[derive(Default)]
+pub struct CodecRegistry {
+    decoders: Vec<Box<dyn DecoderFactory>>,
+}
+
+impl CodecRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        CodecRegistry::default()
+    }
+
+    /// Add the factory of a decoder.
+    ///
+    /// A factory replaces any factory of the same format that was registered before.
+    pub fn with_decoder<F: DecoderFactory + 'static>(mut self, factory: F) -> Self {
+        self.register_decoder(Box::new(factory));
+        self
+    }
+
+    /// Add the factory of a decoder, replacing any factory of the same format.
+    pub fn register_decoder(&mut self, factory: Box<dyn DecoderFactory>) {
+        let format = factory.format();
+        self.decoders.retain(|existing| existing.format() != format);
+        self.decoders.push(factory);
+    }
+
+    /// Returns the factory of decoders of a format, if one is registered.
+    pub fn decoder_factory(&self, format: ImageFormat) -> Option<&dyn DecoderFactory> {
+        self.decoders
+            .iter()
+            .find(|factory| factory.format() == format)
+            .map(|factory| &**factory)
+    }
+
+    /// Returns whether a decoder of the format is registered.
+    pub fn can_decode(&self, format: ImageFormat) -> bool {
+        self.decoder_factory(format).is_some()
+    }
+
+    /// Returns the formats with a registered decoder, in the order of registration.
+    pub fn decodable_formats(&self) -> impl Iterator<Item = ImageFormat> + '_ {
+        self.decoders.iter().map(|factory| factory.format())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeFactory;
+
+    #[test]
+    fn replaces_factories_of_a_format() {
+        let registry = CodecRegistry::new()
+            .with_decoder(FakeFactory(ImageFormat::Pnm, b"P5"))
+            .with_decoder(FakeFactory(ImageFormat::Bmp, b"BM"))
+            .with_decoder(FakeFactory(ImageFormat::Pnm, b"P6"));
+
+        let formats: Vec<_> = registry.decodable_formats().collect();
+        assert_eq!(formats, [ImageFormat::Bmp, ImageFormat::Pnm]);
+        assert!(!registry.can_decode(ImageFormat::Png));
+
+        let factory = registry.decoder_factory(ImageFormat::Pnm).unwrap();
+        let reader = std::io::Cursor::new(b"P6\x01\x01\x09".to_vec());
+        assert!(factory.create_decoder(Box::new(reader)).is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use crate::{ImageDecoder, ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::{PixelBuffer, Progress, ProgressOptions};
+
+/// The state of a `ResumableDecode` after a call to `ResumableDecode::resume`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeStatus {
+    /// The whole image has been decoded.
+    Complete,
+    /// The time budget was used up before the image was decoded.
+    ///
+    /// Decoding continues where it stopped with the next call to `resume`.
+    BudgetExceeded(Progress),
+}
+
+/// A decode which is spread over several calls, each bounded by a time budget.
+///
+/// The image is read from `into_reader` in groups of scanlines as configured by `ProgressOptions`,
+/// and the time budget is checked between groups. A call to `resume` may thus exceed the budget
+/// by the time it takes to decode one group. Each call reads at least one group, so that decoding
+/// advances even with a tiny budget. Interactive applications
+/// can resume the decode once per frame and show a placeholder or the rows decoded so far, or
+/// move it to a worker thread and `finish` it there.
+pub struct ResumableDecode<R> {
+    reader: R,
+    buffer: PixelBuffer,
+    bytes_read: usize,
+    group_bytes: usize,
+    time_budget: Option<Duration>,
+    failed: bool,
+}
+
+impl<R: Read> ResumableDecode<R> {
+    /// Start decoding the image of a decoder, without reading any rows yet.
+    ///
+    /// Each call to `resume` returns once `time_budget` is exceeded. The image is validated
+    /// against the limits of the decoder before its buffer is allocated.
+    pub fn new<'a, D>(
+        decoder: D,
+        options: ProgressOptions,
+        time_budget: Duration,
+    ) -> ImageResult<Self>
+    where
+        D: ImageDecoder<'a, Reader = R>,
+    {
+        let (width, height) = decoder.dimensions();
+        let limits = decoder.limits();
+        limits.check_dimensions(width, height)?;
+        limits.check_alloc(decoder.total_bytes())?;
+
+        let buffer = PixelBuffer::new(width, height, decoder.color_type())?;
+        let group_bytes = options.group_bytes(decoder.scanline_bytes());
+        Ok(ResumableDecode {
+            reader: decoder.into_reader()?,
+            buffer,
+            bytes_read: 0,
+            group_bytes,
+            time_budget: Some(time_budget),
+            failed: false,
+        })
+    }
+
+    /// Continue decoding until the image is complete or the time budget is exceeded.
+    ///
+    /// The decode can not be resumed after an error, as the rows which were partially read are
+    /// lost. Later calls result in a `FailedAlready` parameter error.
+    pub fn resume(&mut self) -> ImageResult<DecodeStatus> {
+        if self.failed {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::FailedAlready,
+            )));
+        }
+
+        let start = Instant::now();
+        let total = self.buffer.as_bytes().len();
+        while self.bytes_read < total {
+            let len = self.group_bytes.min(total - self.bytes_read);
+            let target = &mut self.buffer.as_bytes_mut()[self.bytes_read..][..len];
+            if let Err(err) = self.reader.read_exact(target) {
+                self.failed = true;
+                return Err(err.into());
+            }
+            self.bytes_read += len;
+
+            match self.time_budget {
+                Some(budget) if self.bytes_read < total && start.elapsed() >= budget => {
+                    return Ok(DecodeStatus::BudgetExceeded(self.progress()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(DecodeStatus::Complete)
+    }
+
+    /// The number of bytes decoded so far, out of the whole image.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            current: self.bytes_read as u64,
+            total: self.buffer.as_bytes().len() as u64,
+        }
+    }
+
+    /// Returns whether the whole image has been decoded.
+    pub fn is_complete(&self) -> bool {
+        self.bytes_read == self.buffer.as_bytes().len()
+    }
+
+    /// The image as decoded so far.
+    ///
+    /// Rows are stored in the order reported by `ImageDecoder::row_order`, the bytes which have
+    /// not been decoded yet are zero.
+    pub fn buffer(&self) -> &PixelBuffer {
+        &self.buffer
+    }
+
+    /// Decode the rest of the image regardless of the time budget and return it.
+    pub fn finish(mut self) -> ImageResult<PixelBuffer> {
+        self.time_budget = None;
+        self.resume()?;
+        Ok(self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryDecoder;
+    use crate::ColorType;
+    use std::io::Cursor;
+
+    fn options() -> ProgressOptions {
+        ProgressOptions::new().with_min_interval_bytes(25)
+    }
+
+    #[test]
+    fn resumes_after_budget() {
+        let data: Vec<u8> = (0..100).collect();
+        let decoder = MemoryDecoder::new(10, 10, ColorType::L8, data.clone());
+        let mut decode = ResumableDecode::new(decoder, options(), Duration::ZERO).unwrap();
+        assert_eq!(decode.progress().current(), 0);
+
+        // Groups of three rows, one per call.
+        for current in [30, 60, 90] {
+            let progress = Progress {
+                current,
+                total: 100,
+            };
+            assert_eq!(
+                decode.resume().unwrap(),
+                DecodeStatus::BudgetExceeded(progress)
+            );
+        }
+        assert_eq!(decode.buffer().as_bytes()[..90], data[..90]);
+        assert_eq!(decode.buffer().as_bytes()[90..], [0; 10]);
+        assert!(!decode.is_complete());
+
+        assert_eq!(decode.resume().unwrap(), DecodeStatus::Complete);
+        assert!(decode.is_complete());
+        assert_eq!(decode.finish().unwrap().as_bytes(), data);
+
+        let decoder = MemoryDecoder::new(10, 10, ColorType::L8, data.clone());
+        let decode = ResumableDecode::new(decoder, options(), Duration::ZERO).unwrap();
+        assert_eq!(decode.finish().unwrap().as_bytes(), data);
+
+        let mut decoder = MemoryDecoder::new(2, 2, ColorType::L8, vec![1, 2, 3, 4]);
+        decoder.limits.max_alloc = Some(3);
+        assert!(ResumableDecode::new(decoder, options(), Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn stops_after_errors() {
+        /// Ends in the middle of the second group of rows.
+        struct Truncated(MemoryDecoder);
+
+        impl<'a> ImageDecoder<'a> for Truncated {
+            type Reader = Cursor<Vec<u8>>;
+
+            fn dimensions(&self) -> (u32, u32) {
+                self.0.dimensions()
+            }
+
+            fn color_type(&self) -> ColorType {
+                self.0.color_type()
+            }
+
+            fn scanline_bytes(&self) -> u64 {
+                self.0.scanline_bytes()
+            }
+
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                let mut data = self.0.buffer.into_vec();
+                data.truncate(45);
+                Ok(Cursor::new(data))
+            }
+        }
+
+        let decoder = Truncated(MemoryDecoder::new(10, 10, ColorType::L8, vec![0; 100]));
+        let mut decode = ResumableDecode::new(decoder, options(), Duration::ZERO).unwrap();
+        assert!(decode.resume().is_ok());
+        assert!(matches!(decode.resume(), Err(ImageError::IoError(_))));
+        assert_eq!(decode.progress().current(), 30);
+        assert!(matches!(decode.resume(), Err(ImageError::Parameter(_))));
+        assert!(decode.finish().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{self, Cursor, Read};
+
+use crate::transform::flip_vertical_in_place;
+use crate::{AlphaMode, Cicp, DecodeFidelity, HdrMetadata, ProgressOptions};
+use crate::{ColorType, ExtendedColorType, ImageDecoder, ImageResult, Limits, Progress};
+
+/// The order in which a decoder produces the rows of an image.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RowOrder {
+    /// The first row is the top of the image.
+    TopDown,
+    /// The first row is the bottom of the image, as commonly stored by BMP and TGA.
+    BottomUp,
+}
+
+/// A decoder adapter that always produces rows from top to bottom.
+///
+/// Decoders which produce their rows in the order of the file, see `ImageDecoder::row_order`,
+/// avoid buffering for callers that can handle either order. This adapter restores the top-down
+/// order for all others. Images which are already top-down are passed through. Otherwise
+/// `read_image` flips the rows in the buffer of the caller while the reader has to hold the
+/// whole image in memory.
+pub struct TopDownDecoder<D> {
+    inner: D,
+}
+
+/// The reader of a `TopDownDecoder`.
+pub struct TopDownReader<R> {
+    inner: Rows<R>,
+}
+
+enum Rows<R> {
+    TopDown(R),
+    Flipped(Cursor<Vec<u8>>),
+}
+
+impl<'a, D: ImageDecoder<'a>> TopDownDecoder<D> {
+    /// Wrap a decoder to produce top-down rows.
+    pub fn new(decoder: D) -> Self {
+        TopDownDecoder { inner: decoder }
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for TopDownDecoder<D> {
+    type Reader = TopDownReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.inner.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        self.inner.set_output_color(color)
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        self.inner.decode_fidelity()
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        RowOrder::TopDown
+    }
+
+    fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+        self.inner.set_scale_hint(numerator, denominator)
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        match self.inner.row_order() {
+            RowOrder::BottomUp => self.total_bytes(),
+            _ => self.inner.scanline_bytes(),
+        }
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let inner = match self.inner.row_order() {
+            RowOrder::BottomUp => {
+                let (width, height) = self.inner.dimensions();
+                let color = self.inner.color_type();
+                let mut buf = self.inner.read_image_to_vec()?;
+                flip_vertical_in_place(&mut buf, width, height, color);
+                Rows::Flipped(Cursor::new(buf))
+            }
+            _ => Rows::TopDown(self.inner.into_reader()?),
+        };
+        Ok(TopDownReader { inner })
+    }
+
+    fn read_image_with_progress_options<F: Fn(Progress)>(
+        self,
+        buf: &mut [u8],
+        options: ProgressOptions,
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let (width, height) = self.inner.dimensions();
+        let color = self.inner.color_type();
+        let order = self.inner.row_order();
+
+        self.inner
+            .read_image_with_progress_options(buf, options, progress_callback)?;
+        if order == RowOrder::BottomUp {
+            flip_vertical_in_place(buf, width, height, color);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TopDownReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Rows::TopDown(reader) => reader.read(buf),
+            Rows::Flipped(reader) => reader.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    /// Produces the rows of a memory decoder bottom-up.
+    struct BottomUp(MemoryDecoder);
+
+    impl<'a> ImageDecoder<'a> for BottomUp {
+        type Reader = Cursor<Vec<u8>>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            self.0.dimensions()
+        }
+
+        fn color_type(&self) -> ColorType {
+            self.0.color_type()
+        }
+
+        fn row_order(&self) -> RowOrder {
+            RowOrder::BottomUp
+        }
+
+        fn into_reader(self) -> ImageResult<Self::Reader> {
+            let (width, height) = self.dimensions();
+            let mut buf = self.0.buffer.into_vec();
+            flip_vertical_in_place(&mut buf, width, height, ColorType::L8);
+            Ok(Cursor::new(buf))
+        }
+    }
+
+    #[test]
+    fn flips_bottom_up_rows() {
+        let data: Vec<u8> = (0..6).collect();
+        let bottom_up = || BottomUp(MemoryDecoder::new(2, 3, ColorType::L8, data.clone()));
+        assert_eq!(decode(bottom_up()), [4, 5, 2, 3, 0, 1]);
+
+        let decoder = TopDownDecoder::new(bottom_up());
+        assert_eq!(decoder.row_order(), RowOrder::TopDown);
+        assert_eq!(decode(decoder), data);
+
+        let mut read = Vec::new();
+        TopDownDecoder::new(bottom_up())
+            .into_reader()
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn passes_top_down_rows() {
+        let data: Vec<u8> = (0..6).collect();
+        let decoder = TopDownDecoder::new(MemoryDecoder::new(2, 3, ColorType::L8, data.clone()));
+        assert_eq!(decoder.scanline_bytes(), 2);
+        assert_eq!(decode(decoder), data);
+    }
+}
@@ -0,0 +1,196 @@
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::{AnimationDecoder, BoxedDecoder, CodecRegistry, ContextError, Delay, Estimate, Frame};
+use crate::{ImageDecoder, ImageError, ImageReader, ImageResult, LoopCount, PixelBuffer};
+
+/// An animation made of a sequence of still images, such as numbered frames in a folder.
+///
+/// Each frame is decoded from the next decoder of an iterator and displayed for the same delay,
+/// at the top left of the canvas. The iterator may open the decoders lazily, for example from the
+/// entries of an archive, so that only one frame is held in memory at a time. Use `open` to read
+/// the frames from files with the decoders of a registry.
+pub struct ImageSequence<I> {
+    decoders: I,
+    delay: Delay,
+    loop_count: Option<LoopCount>,
+}
+
+/// The decoders of the files of an `ImageSequence`.
+///
+/// The errors of opening a file carry its path, see `ImageReader::open_with_context`. They are
+/// dropped by `next_frame`, iterate the files from `ImageSequence::into_inner` to inspect them.
+pub struct SequenceFiles<'r, I> {
+    paths: I,
+    registry: &'r CodecRegistry,
+}
+
+impl<I> ImageSequence<I> {
+    /// Create a sequence displaying the image of each decoder for `delay`.
+    pub fn new<T: IntoIterator<IntoIter = I>>(decoders: T, delay: Delay) -> Self {
+        ImageSequence {
+            decoders: decoders.into_iter(),
+            delay,
+            loop_count: None,
+        }
+    }
+
+    /// Report how often the sequence is played, which it does not specify by itself.
+    pub fn with_loop_count(mut self, count: LoopCount) -> Self {
+        self.loop_count = Some(count);
+        self
+    }
+
+    /// Returns the iterator of the remaining decoders.
+    pub fn into_inner(self) -> I {
+        self.decoders
+    }
+}
+
+impl<'r, I, P> ImageSequence<SequenceFiles<'r, I>>
+where
+    I: Iterator<Item = P>,
+    P: AsRef<Path>,
+{
+    /// Create a sequence of the images in the files, in the order of the paths.
+    ///
+    /// The files are opened with `ImageReader::open_with_context` when their frame is reached.
+    /// Paths are not sorted, numbered files without leading zeros have to be sorted by their
+    /// number first.
+    pub fn open<T>(paths: T, registry: &'r CodecRegistry, delay: Delay) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        let files = SequenceFiles {
+            paths: paths.into_iter(),
+            registry,
+        };
+        ImageSequence::new(files, delay)
+    }
+}
+
+impl<'r, I, P> Iterator for SequenceFiles<'r, I>
+where
+    I: Iterator<Item = P>,
+    P: AsRef<Path>,
+{
+    type Item = Result<BoxedDecoder<'r>, ContextError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.paths.next()?;
+        let decoder = ImageReader::open_with_context(path, self.registry)
+            .and_then(ImageReader::into_decoder_with_context);
+        Some(decoder)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.paths.size_hint()
+    }
+}
+
+impl<'a, I, D, E> AnimationDecoder for ImageSequence<I>
+where
+    I: Iterator<Item = Result<D, E>>,
+    D: ImageDecoder<'a>,
+    E: Into<ImageError>,
+{
+    fn next_frame(&mut self) -> Option<ImageResult<Frame>> {
+        let decoder = match self.decoders.next()? {
+            Ok(decoder) => decoder,
+            Err(err) => return Some(Err(err.into())),
+        };
+        Some(decode_frame(decoder).map(|frame| frame.with_delay(self.delay)))
+    }
+
+    /// Skips the decoder of the next frame without decoding its image.
+    fn skip_frame(&mut self) -> Option<ImageResult<Delay>> {
+        Some(
+            self.decoders
+                .next()?
+                .map(|_| self.delay)
+                .map_err(Into::into),
+        )
+    }
+
+    /// Returns the exact number of frames if the iterator of decoders reports it.
+    fn frame_count_hint(&self) -> Option<Estimate<u32>> {
+        match self.decoders.size_hint() {
+            (lower, Some(upper)) if lower == upper => {
+                u32::try_from(lower).ok().map(Estimate::Exact)
+            }
+            _ => None,
+        }
+    }
+
+    fn total_duration_hint(&self) -> Option<Estimate<Delay>> {
+        let count = self.frame_count_hint()?.value();
+        let total = self.delay.to_duration().saturating_mul(count);
+        Some(Estimate::Exact(Delay::from_saturating_duration(total)))
+    }
+
+    fn loop_count(&self) -> Option<LoopCount> {
+        self.loop_count
+    }
+}
+
+fn decode_frame<'a, D: ImageDecoder<'a>>(decoder: D) -> ImageResult<Frame> {
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let profile = decoder.icc_profile();
+    let buffer = PixelBuffer::from_vec(width, height, color, decoder.read_image_to_vec()?)?;
+
+    let frame = Frame::new(buffer);
+    Ok(match profile {
+        Some(profile) => frame.with_icc_profile(profile),
+        None => frame,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryDecoder;
+    use crate::ColorType;
+
+    fn frames() -> Vec<ImageResult<MemoryDecoder>> {
+        (0..3)
+            .map(|value| Ok(MemoryDecoder::new(2, 1, ColorType::L8, vec![value; 2])))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_frames_in_order() {
+        let mut sequence = ImageSequence::new(frames(), Delay::from_millis(40));
+        assert_eq!(sequence.frame_count_hint(), Some(Estimate::Exact(3)));
+        assert_eq!(
+            sequence.total_duration_hint(),
+            Some(Estimate::Exact(Delay::from_millis(120)))
+        );
+        assert_eq!(sequence.loop_count(), None);
+
+        assert_eq!(
+            sequence.skip_frame().unwrap().unwrap(),
+            Delay::from_millis(40)
+        );
+        let frames: Vec<_> = sequence.into_frames().collect::<ImageResult<_>>().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer().as_bytes(), [1, 1]);
+        assert_eq!(frames[1].buffer().as_bytes(), [2, 2]);
+        assert_eq!(frames[1].delay(), Delay::from_millis(40));
+    }
+
+    #[test]
+    fn opens_files_with_registry() {
+        let registry = CodecRegistry::new();
+        let mut sequence = ImageSequence::open(["missing.png"], &registry, Delay::from_millis(10))
+            .with_loop_count(LoopCount::Infinite);
+        assert_eq!(sequence.loop_count(), Some(LoopCount::Infinite));
+        assert!(sequence.next_frame().unwrap().is_err());
+        assert!(sequence.next_frame().is_none());
+
+        let mut files =
+            ImageSequence::open(["missing.png"], &registry, Delay::from_millis(10)).into_inner();
+        let err = files.next().unwrap().err().unwrap();
+        assert_eq!(err.path(), Some(Path::new("missing.png")));
+    }
+}
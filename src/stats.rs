@@ -0,0 +1,205 @@
+use crate::convert::read_sample;
+use crate::{Channel, ColorType, LumaWeights};
+
+/// Statistics of the pixels of an image, accumulated row by row.
+///
+/// Pass the rows of an image to `update` as they are decoded, for example from
+/// `ImageDecoder::read_image_with_row_hook` or `streaming::rows`, to collect the statistics in
+/// the same pass. Samples are normalized to the nominal range of `0.0` to `1.0`. The luma of
+/// color images is computed with the coefficients of Rec. 709.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelStats {
+    color: ColorType,
+    pixels: u64,
+    channels: Vec<ChannelStats>,
+    luma_range: Option<(f32, f32)>,
+}
+
+/// Statistics of one channel of the pixels of an image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    channel: Channel,
+    histogram: Box<[u64; 256]>,
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: u64,
+}
+
+impl PixelStats {
+    /// Create empty statistics for pixels of a color type.
+    pub fn new(color: ColorType) -> Self {
+        let channels = color
+            .layout()
+            .channels()
+            .iter()
+            .map(|&channel| ChannelStats {
+                channel,
+                histogram: Box::new([0; 256]),
+                min: f32::INFINITY,
+                max: f32::NEG_INFINITY,
+                sum: 0.0,
+                count: 0,
+            })
+            .collect();
+        PixelStats {
+            color,
+            pixels: 0,
+            channels,
+            luma_range: None,
+        }
+    }
+
+    /// Account for pixels in the color type of the statistics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer does not hold whole pixels.
+    pub fn update(&mut self, pixels: &[u8]) {
+        let layout = self.color.layout();
+        let format = layout.sample_format();
+        let bpp = usize::from(self.color.bytes_per_pixel());
+        assert!(
+            pixels.len().is_multiple_of(bpp),
+            "{} bytes are not whole pixels of {:?}",
+            pixels.len(),
+            self.color,
+        );
+
+        let weights = LumaWeights::Rec709.coefficients();
+        let rgb = [Channel::Red, Channel::Green, Channel::Blue].map(|c| layout.position(c));
+        let luma = layout.position(Channel::Luma);
+        let mut samples = vec![0.0; self.channels.len()];
+        for pixel in pixels.chunks_exact(bpp) {
+            let values = pixel
+                .chunks_exact(format.bytes())
+                .map(|s| read_sample(s, format));
+            for ((value, stats), sample) in values.zip(&mut self.channels).zip(&mut samples) {
+                stats.add(value);
+                *sample = value;
+            }
+
+            let value = match luma {
+                Some(index) => samples[index],
+                None => rgb
+                    .iter()
+                    .zip(weights)
+                    .map(|(index, weight)| index.map_or(0.0, |index| samples[index]) * weight)
+                    .sum(),
+            };
+            let (min, max) = self.luma_range.unwrap_or((value, value));
+            self.luma_range = Some((min.min(value), max.max(value)));
+        }
+        self.pixels += (pixels.len() / bpp) as u64;
+    }
+
+    /// The color type of the pixels.
+    pub fn color_type(&self) -> ColorType {
+        self.color
+    }
+
+    /// The number of pixels accounted for.
+    pub fn pixel_count(&self) -> u64 {
+        self.pixels
+    }
+
+    /// The statistics of every channel, in the order of the channels in memory.
+    pub fn channels(&self) -> &[ChannelStats] {
+        &self.channels
+    }
+
+    /// The statistics of a channel, if the pixels have it.
+    pub fn channel(&self, channel: Channel) -> Option<&ChannelStats> {
+        self.channels.iter().find(|stats| stats.channel == channel)
+    }
+
+    /// The least and greatest luma of all pixels, or `None` if there were no pixels.
+    pub fn luma_range(&self) -> Option<(f32, f32)> {
+        self.luma_range
+    }
+}
+
+impl ChannelStats {
+    /// The channel described.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// The number of samples in each of 256 ranges of equal width.
+    ///
+    /// 8-bit samples are counted by their value. Float samples outside of the nominal range are
+    /// counted in the first or last bin.
+    pub fn histogram(&self) -> &[u64; 256] {
+        &self.histogram
+    }
+
+    /// The least sample, or `None` if there were no pixels.
+    pub fn min(&self) -> Option<f32> {
+        self.mean().map(|_| self.min)
+    }
+
+    /// The greatest sample, or `None` if there were no pixels.
+    pub fn max(&self) -> Option<f32> {
+        self.mean().map(|_| self.max)
+    }
+
+    /// The average sample, or `None` if there were no pixels.
+    pub fn mean(&self) -> Option<f32> {
+        match self.count {
+            0 => None,
+            count => Some((self.sum / count as f64) as f32),
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        let bin = (value.clamp(0.0, 1.0) * 256.0) as usize;
+        self.histogram[bin.min(255)] += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += f64::from(value);
+        self.count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryDecoder;
+    use crate::ImageDecoder;
+
+    #[test]
+    fn accumulates_rows() {
+        let data = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 51, 51, 51];
+        let decoder = MemoryDecoder::new(2, 2, ColorType::Rgb8, data);
+        let mut stats = PixelStats::new(ColorType::Rgb8);
+        decoder
+            .read_image_with_row_hook(&mut [0; 12], |row| stats.update(row))
+            .unwrap();
+
+        assert_eq!(stats.pixel_count(), 4);
+        let red = stats.channel(Channel::Red).unwrap();
+        assert_eq!((red.min(), red.max()), (Some(0.0), Some(1.0)));
+        assert_eq!(red.mean(), Some(0.3));
+        assert_eq!(red.histogram()[255], 1);
+        assert_eq!(red.histogram()[51], 1);
+        assert_eq!(red.histogram()[0], 2);
+
+        let (min, max) = stats.luma_range().unwrap();
+        assert!((min - 0.0722).abs() < 1e-6 && (max - 0.7152).abs() < 1e-6);
+        assert!(stats.channel(Channel::Alpha).is_none());
+    }
+
+    #[test]
+    fn starts_empty() {
+        let mut stats = PixelStats::new(ColorType::La16);
+        assert_eq!(stats.channels().len(), 2);
+        assert_eq!(stats.channels()[0].mean(), None);
+        assert_eq!(stats.luma_range(), None);
+
+        let samples = [0x8000u16, 0xffff];
+        let pixel: Vec<u8> = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+        stats.update(&pixel);
+        assert_eq!(stats.channel(Channel::Luma).unwrap().histogram()[128], 1);
+        assert_eq!(stats.channel(Channel::Alpha).unwrap().max(), Some(1.0));
+    }
+}
@@ -0,0 +1,471 @@
+//! Processing images which do not fit into memory.
+//!
+//! `ImageDecoder::total_bytes` may exceed what can be allocated, or even `usize::MAX` on 32-bit
+//! targets. Such images can still be processed end to end, as long as no step needs the whole
+//! image at once. This module reads them row by row from `into_reader`, or in horizontal bands of
+//! a fixed height with `ImageDecoderExt::read_rect`, and passes the rows to
+//! `ImageEncoder::write_rows`.
+//!
+//! Memory use is then bounded by the decoder and the encoder. Encoders must override `write_rows`
+//! to encode incrementally, the provided implementation collects the whole image.
+//!
+//! Decoded images can also be verified against stored checksums row by row, with `digest`, and
+//! a reduced size preview can be produced in the same pass as the full image with
+//! `decode_with_preview`.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::error::{ImageError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::limits::insufficient_memory;
+use crate::thumbnail::{fit_dimensions, Downscaler};
+use crate::{DownscaleFilter, RowOrder};
+use crate::{ImageDecoder, ImageDecoderExt, ImageEncoder, ImageResult, PixelBuffer, Rect};
+
+/// An iterator over the rows of an image, see `rows`.
+pub struct Rows<R> {
+    reader: R,
+    row_len: usize,
+    remaining: u32,
+}
+
+/// Returns an iterator over the rows of the image of a decoder.
+///
+/// Only a single row is held in memory at a time. The rows are produced in the order reported by
+/// `ImageDecoder::row_order`. The dimensions are validated against the limits of the decoder, but
+/// only one row has to fit into the allocation limit.
+pub fn rows<'a, D: ImageDecoder<'a>>(decoder: D) -> ImageResult<Rows<D::Reader>> {
+    let (width, height) = decoder.dimensions();
+    let row_len = u64::from(width) * u64::from(decoder.color_type().bytes_per_pixel());
+    let limits = decoder.limits();
+    limits.check_dimensions(width, height)?;
+    limits.check_alloc(row_len)?;
+
+    Ok(Rows {
+        row_len: usize::try_from(row_len).map_err(|_| insufficient_memory())?,
+        remaining: height,
+        reader: decoder.into_reader()?,
+    })
+}
+
+impl<R: Read> Iterator for Rows<R> {
+    type Item = ImageResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let mut row = vec![0; self.row_len];
+        if let Err(err) = self.reader.read_exact(&mut row) {
+            self.remaining = 0;
+            return Some(Err(err.into()));
+        }
+        self.remaining -= 1;
+        Some(Ok(row))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<R: Read> ExactSizeIterator for Rows<R> {}
+
+/// Compute a digest of the decoded image, without holding more than a row in memory.
+///
+/// Every row is passed to `update` together with the state of the digest, in the order reported
+/// by `ImageDecoder::row_order`, and the state is returned to be finalized by the caller. This
+/// works with any checksum, for example `digest(decoder, hasher, |h, row| h.write(row))` for a
+/// `Hasher`, or with the update method of a SHA-256 implementation.
+pub fn digest<'a, D, S, F>(decoder: D, mut state: S, mut update: F) -> ImageResult<S>
+where
+    D: ImageDecoder<'a>,
+    F: FnMut(&mut S, &[u8]),
+{
+    for row in rows(decoder)? {
+        update(&mut state, &row?);
+    }
+    Ok(state)
+}
+
+/// Decode the whole image, returning it together with the state of its digest.
+///
+/// The state is updated with the same rows as by `digest`, while they are decoded with
+/// `ImageDecoder::read_image_with_row_hook`.
+pub fn decode_with_digest<'a, D, S, F>(
+    decoder: D,
+    mut state: S,
+    mut update: F,
+) -> ImageResult<(PixelBuffer, S)>
+where
+    D: ImageDecoder<'a>,
+    F: FnMut(&mut S, &[u8]),
+{
+    let (width, height) = decoder.dimensions();
+    let limits = decoder.limits();
+    limits.check_dimensions(width, height)?;
+    limits.check_alloc(decoder.total_bytes())?;
+
+    let mut buffer = PixelBuffer::new(width, height, decoder.color_type())?;
+    decoder.read_image_with_row_hook(buffer.as_bytes_mut(), |row| update(&mut state, row))?;
+    Ok((buffer, state))
+}
+
+/// Decode the whole image together with a preview fitting into `max_width` by `max_height`.
+///
+/// The preview is box filtered from the rows while they are decoded, so the image is decoded only
+/// once. Its dimensions are chosen like those of a `ThumbnailDecoder`: the aspect ratio is
+/// preserved and images which already fit are not enlarged. Both buffers have the color type of
+/// the decoder and hold their rows in the order reported by `ImageDecoder::row_order`. Their
+/// combined size is checked against the allocation limit.
+pub fn decode_with_preview<'a, D: ImageDecoder<'a>>(
+    decoder: D,
+    max_width: u32,
+    max_height: u32,
+) -> ImageResult<(PixelBuffer, PixelBuffer)> {
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let (preview_width, preview_height) = fit_dimensions((width, height), (max_width, max_height));
+    let preview_row = u64::from(preview_width) * u64::from(color.bytes_per_pixel());
+    let limits = decoder.limits();
+    limits.check_dimensions(width, height)?;
+    limits.check_alloc(
+        decoder
+            .total_bytes()
+            .saturating_add(preview_row * u64::from(preview_height)),
+    )?;
+
+    let mut buffer = PixelBuffer::new(width, height, color)?;
+    let mut preview = PixelBuffer::new(preview_width, preview_height, color)?;
+    if width == 0 || height == 0 {
+        // Empty images have an empty preview, there are no rows to downscale.
+        decoder.read_image(buffer.as_bytes_mut())?;
+        return Ok((buffer, preview));
+    }
+
+    let mut downscaler = Downscaler::new(
+        color,
+        DownscaleFilter::Box,
+        (width, height),
+        (preview_width, preview_height),
+    );
+
+    let preview_row = usize::try_from(preview_row).map_err(|_| insufficient_memory())?;
+    let mut preview_rows = preview.as_bytes_mut().chunks_exact_mut(preview_row.max(1));
+    let mut target = preview_rows.next();
+    decoder.read_image_with_row_hook(buffer.as_bytes_mut(), |row| {
+        if let Some(output) = &mut target {
+            if downscaler.push_row(row, output) {
+                target = preview_rows.next();
+            }
+        }
+    })?;
+    Ok((buffer, preview))
+}
+
+/// Encode the image of a decoder, streaming it row by row.
+///
+/// The image is written in the color type of the decoder. Decoders producing bottom-up rows
+/// result in an unsupported error, since the encoder expects them top-down. Wrap them in a
+/// `TopDownDecoder` if the image fits into memory, or use `transcode_in_bands` otherwise.
+pub fn transcode_rows<'a, D, E, W>(decoder: D, encoder: E, writer: W) -> ImageResult<()>
+where
+    D: ImageDecoder<'a>,
+    E: ImageEncoder,
+    W: Write,
+{
+    if decoder.row_order() != RowOrder::TopDown {
+        return Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature("streaming bottom-up rows".into()),
+            ),
+        ));
+    }
+
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let mut rows = UntilError::new(rows(decoder)?);
+    let result = encoder.write_rows(writer, width, height, color.into(), &mut rows);
+    rows.finish(result)
+}
+
+/// Encode the image of a decoder, reading it in horizontal bands.
+///
+/// Each band spans the whole width of the image and `band_height` rows, only the last one may be
+/// lower. One band is held in memory at a time, its size is checked against the allocation limit
+/// of the decoder. The image is written in the color type of the decoder. A `band_height` of zero
+/// is treated as one.
+pub fn transcode_in_bands<'a, D, E, W>(
+    decoder: &mut D,
+    encoder: E,
+    writer: W,
+    band_height: u32,
+) -> ImageResult<()>
+where
+    D: ImageDecoderExt<'a>,
+    E: ImageEncoder,
+    W: Write,
+{
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let row_len = u64::from(width) * u64::from(color.bytes_per_pixel());
+    let band_height = band_height.clamp(1, height.max(1));
+    let band_len = row_len * u64::from(band_height);
+    let limits = decoder.limits();
+    limits.check_dimensions(width, height)?;
+    limits.check_alloc(band_len)?;
+
+    let band_len = usize::try_from(band_len).map_err(|_| insufficient_memory())?;
+    let mut bands = UntilError::new(Bands {
+        decoder,
+        band: vec![0; band_len],
+        row_len: row_len as usize,
+        band_height,
+        band_top: 0,
+        band_rows: 0,
+        next_row: 0,
+        width,
+        height,
+    });
+    let result = encoder.write_rows(writer, width, height, color.into(), &mut bands);
+    bands.finish(result)
+}
+
+/// Reads the rows of an image through `read_rect`, one band at a time.
+struct Bands<'d, D> {
+    decoder: &'d mut D,
+    band: Vec<u8>,
+    row_len: usize,
+    band_height: u32,
+    /// The first row of the band which was read last.
+    band_top: u32,
+    /// The number of rows of the band which was read last.
+    band_rows: u32,
+    next_row: u32,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, D: ImageDecoderExt<'a>> Iterator for Bands<'_, D> {
+    type Item = ImageResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_row == self.height {
+            return None;
+        }
+
+        if self.next_row == self.band_top + self.band_rows {
+            self.band_top = self.next_row;
+            self.band_rows = self.band_height.min(self.height - self.next_row);
+            let rect = Rect::new(0, self.band_top, self.width, self.band_rows);
+            let len = self.row_len * self.band_rows as usize;
+            if let Err(err) = self.decoder.read_rect(rect, &mut self.band[..len]) {
+                self.next_row = self.height;
+                return Some(Err(err));
+            }
+        }
+
+        let offset = (self.next_row - self.band_top) as usize * self.row_len;
+        self.next_row += 1;
+        Some(Ok(self.band[offset..][..self.row_len].to_vec()))
+    }
+}
+
+/// Ends an iterator of results at the first error, keeping it for later.
+///
+/// This lets fallible rows be passed to `ImageEncoder::write_rows`, which expects plain rows.
+struct UntilError<I> {
+    inner: I,
+    error: Option<ImageError>,
+}
+
+impl<I> UntilError<I> {
+    fn new(inner: I) -> Self {
+        UntilError { inner, error: None }
+    }
+
+    /// Prefer the error of the rows over that of the encoder, which only noticed missing rows.
+    fn finish(self, result: ImageResult<()>) -> ImageResult<()> {
+        match self.error {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+}
+
+impl<I: Iterator<Item = ImageResult<Vec<u8>>>> Iterator for UntilError<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.inner.next()? {
+            Ok(row) => Some(row),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+    use crate::{ColorType, ExtendedColorType, ParameterError, ParameterErrorKind};
+    use std::io::Cursor;
+
+    /// Writes the rows as they arrive, counting them.
+    struct RowEncoder<'r>(&'r mut u32);
+
+    impl ImageEncoder for RowEncoder<'_> {
+        fn write_image<W: Write>(
+            self,
+            _: W,
+            _: &[u8],
+            _: u32,
+            _: u32,
+            _: ExtendedColorType,
+        ) -> ImageResult<()> {
+            unreachable!("rows are written incrementally")
+        }
+
+        fn write_rows<W, I>(
+            self,
+            mut writer: W,
+            _: u32,
+            height: u32,
+            _: ExtendedColorType,
+            rows: I,
+        ) -> ImageResult<()>
+        where
+            W: Write,
+            I: IntoIterator,
+            I::Item: AsRef<[u8]>,
+        {
+            for row in rows {
+                writer.write_all(row.as_ref())?;
+                *self.0 += 1;
+            }
+            if *self.0 == height {
+                Ok(())
+            } else {
+                Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::DimensionMismatch,
+                )))
+            }
+        }
+    }
+
+    #[test]
+    fn transcodes_rows() {
+        let data: Vec<u8> = (0..12).collect();
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        assert_eq!(rows(decoder).unwrap().len(), 3);
+
+        let (mut count, mut encoded) = (0, Vec::new());
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        transcode_rows(decoder, RowEncoder(&mut count), &mut encoded).unwrap();
+        assert_eq!((count, encoded), (3, data));
+    }
+
+    #[test]
+    fn transcodes_in_bands() {
+        let data: Vec<u8> = (0..20).collect();
+        let mut decoder = MemoryDecoder::new(2, 5, ColorType::La8, data.clone());
+
+        let (mut count, mut encoded) = (0, Vec::new());
+        transcode_in_bands(&mut decoder, RowEncoder(&mut count), &mut encoded, 2).unwrap();
+        assert_eq!((count, encoded), (5, data));
+
+        decoder.limits.max_alloc = Some(7);
+        let result = transcode_in_bands(&mut decoder, RowEncoder(&mut 0), Vec::new(), 2);
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn digests_rows() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let data: Vec<u8> = (0..12).collect();
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        let streamed = digest(decoder, DefaultHasher::new(), |h, row| h.write(row)).unwrap();
+
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        let (buffer, decoded) =
+            decode_with_digest(decoder, DefaultHasher::new(), |h, row| h.write(row)).unwrap();
+        assert_eq!(buffer.as_bytes(), &data[..]);
+        assert_eq!(decoded.finish(), streamed.finish());
+
+        let mut hasher = DefaultHasher::new();
+        data.chunks(4).for_each(|row| hasher.write(row));
+        assert_eq!(streamed.finish(), hasher.finish());
+
+        // Digests which are not a `Hasher` receive the same rows.
+        let decoder = MemoryDecoder::new(2, 3, ColorType::La8, data.clone());
+        let rows = digest(decoder, Vec::new(), |rows, row| rows.push(row.to_vec())).unwrap();
+        assert_eq!(rows.concat(), data);
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn decodes_preview_in_one_pass() {
+        let data: Vec<u8> = (0..5 * 3 * 3).collect();
+        let decoder = MemoryDecoder::new(5, 3, ColorType::Rgb8, data.clone());
+        let (buffer, preview) = decode_with_preview(decoder, 2, 2).unwrap();
+        assert_eq!(buffer.as_bytes(), &data[..]);
+        assert_eq!((preview.width(), preview.height()), (2, 1));
+
+        let decoder = MemoryDecoder::new(5, 3, ColorType::Rgb8, data.clone());
+        let thumbnail = crate::ThumbnailDecoder::new(decoder, 2, 2);
+        assert_eq!(preview.as_bytes(), &decode(thumbnail)[..]);
+
+        let mut decoder = MemoryDecoder::new(5, 3, ColorType::Rgb8, data);
+        decoder.limits.max_alloc = Some(50);
+        let result = decode_with_preview(decoder, 2, 2);
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn previews_empty_images() {
+        let decoder = MemoryDecoder::new(0, 5, ColorType::Rgb8, Vec::new());
+        let (buffer, preview) = decode_with_preview(decoder, 2, 2).unwrap();
+        assert_eq!((buffer.width(), buffer.height()), (0, 5));
+        assert_eq!((preview.width(), preview.height()), (0, 2));
+        assert!(preview.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn reports_errors_of_rows() {
+        /// Claims more rows than its data holds.
+        struct Truncated;
+
+        impl<'a> ImageDecoder<'a> for Truncated {
+            type Reader = Cursor<Vec<u8>>;
+
+            fn dimensions(&self) -> (u32, u32) {
+                (2, 3)
+            }
+
+            fn color_type(&self) -> ColorType {
+                ColorType::L8
+            }
+
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                Ok(Cursor::new(vec![0; 5]))
+            }
+        }
+
+        let mut count = 0;
+        let result = transcode_rows(Truncated, RowEncoder(&mut count), Vec::new());
+        assert_eq!(count, 2);
+        assert!(matches!(result, Err(ImageError::IoError(_))));
+    }
+}
@@ -0,0 +1,458 @@
+//! Utilities for testing codec implementations.
+//!
+//! The functions in this module check that an implementation upholds the contract of the traits
+//! in this crate. They panic with a descriptive message on the first violation and are meant to be
+//! called from the test suite of a format crate.
+//!
+//! This module is only available with the `test-util` feature.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::io::Read;
+use std::mem::MaybeUninit;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{ColorType, ImageDecoder, ImageDecoderExt, Progress};
+use crate::{ImageError, ParameterErrorKind, Rect};
+
+/// Check that a decoder upholds the contract of `ImageDecoder`.
+///
+/// The factory is called repeatedly and must produce a fresh decoder for the same image each
+/// time. This checks that
+/// * `total_bytes` matches the dimensions and color type,
+/// * `scanline_bytes` is within bounds,
+/// * `read_image`, `read_image_with_progress`, `read_image_uninit` and `into_reader` produce the
+///   same data,
+/// * progress is reported monotonically and ends with the total,
+/// * a buffer of the wrong size is rejected, with a panic or with an error.
+///
+/// # Panics
+///
+/// This function panics if the decoder violates the contract or fails to decode.
+pub fn check_decoder_contract<'a, D, F>(decoder_factory: F)
+where
+    D: ImageDecoder<'a>,
+    F: Fn() -> D,
+{
+    let decoder = decoder_factory();
+    let (width, height) = decoder.dimensions();
+    let total_bytes = decoder.total_bytes();
+    let scanline_bytes = decoder.scanline_bytes();
+
+    assert_eq!(
+        total_bytes,
+        u64::from(width) * u64::from(height) * u64::from(decoder.color_type().bytes_per_pixel()),
+        "total_bytes does not match the dimensions and color type",
+    );
+    assert!(
+        scanline_bytes <= total_bytes,
+        "scanline_bytes {} exceeds total_bytes {}",
+        scanline_bytes,
+        total_bytes,
+    );
+    assert!(
+        total_bytes == 0 || scanline_bytes > 0,
+        "scanline_bytes is zero for a non-empty image",
+    );
+
+    let len = usize::try_from(total_bytes).expect("Test image exceeds the address space");
+    let mut image = vec![0; len];
+    decoder
+        .read_image(&mut image)
+        .expect("Failed to read image");
+
+    let mut from_reader = Vec::new();
+    decoder_factory()
+        .into_reader()
+        .expect("Failed to create reader")
+        .read_to_end(&mut from_reader)
+        .expect("Failed to read from reader");
+    assert!(
+        from_reader == image,
+        "into_reader and read_image produce different data",
+    );
+
+    let reported = RefCell::new(Vec::new());
+    let mut with_progress = vec![0; len];
+    decoder_factory()
+        .read_image_with_progress(&mut with_progress, |progress| {
+            reported.borrow_mut().push(progress)
+        })
+        .expect("Failed to read image with progress");
+    assert!(
+        with_progress == image,
+        "read_image_with_progress and read_image produce different data",
+    );
+    check_progress(&reported.into_inner());
+
+    let mut uninit = vec![MaybeUninit::uninit(); len];
+    let from_uninit = decoder_factory()
+        .read_image_uninit(&mut uninit)
+        .expect("Failed to read image into uninitialized buffer");
+    assert!(
+        from_uninit == &image[..],
+        "read_image_uninit and read_image produce different data",
+    );
+
+    for &wrong_len in &[len + 1, len.saturating_sub(1)] {
+        if wrong_len == len {
+            continue;
+        }
+
+        let mut buf = vec![0; wrong_len];
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| decoder_factory().read_image(&mut buf)));
+        assert!(
+            !matches!(result, Ok(Ok(()))),
+            "read_image accepted a buffer of {} bytes instead of {}",
+            wrong_len,
+            len,
+        );
+    }
+}
+
+/// Check that a decoder upholds the contract of `ImageDecoderExt`, in addition to that of
+/// `ImageDecoder`.
+///
+/// Rectangles covering the whole image, single rows, single columns and each quadrant are read
+/// and compared against the corresponding part of the complete image. Rectangles exceeding the
+/// image must be rejected with a `ParameterErrorKind::RectOutOfBounds` error.
+///
+/// # Panics
+///
+/// This function panics if the decoder violates the contract or fails to decode.
+pub fn check_decoder_ext_contract<'a, D, F>(decoder_factory: F)
+where
+    D: ImageDecoderExt<'a>,
+    F: Fn() -> D,
+{
+    check_decoder_contract(&decoder_factory);
+
+    let decoder = decoder_factory();
+    let (width, height) = decoder.dimensions();
+    let bpp = usize::from(decoder.color_type().bytes_per_pixel());
+    let len = usize::try_from(decoder.total_bytes()).expect("Test image exceeds the address space");
+    let mut image = vec![0; len];
+    decoder
+        .read_image(&mut image)
+        .expect("Failed to read image");
+
+    let (half_width, half_height) = (width / 2, height / 2);
+    let rects = [
+        Rect::from_dimensions((width, height)),
+        Rect::new(0, 0, width, height.min(1)),
+        Rect::new(0, height.saturating_sub(1), width, height.min(1)),
+        Rect::new(0, 0, width.min(1), height),
+        Rect::new(width.saturating_sub(1), 0, width.min(1), height),
+        Rect::new(0, 0, half_width, half_height),
+        Rect::new(half_width, 0, width - half_width, half_height),
+        Rect::new(0, half_height, half_width, height - half_height),
+        Rect::new(
+            half_width,
+            half_height,
+            width - half_width,
+            height - half_height,
+        ),
+    ];
+
+    let mut decoder = decoder_factory();
+    for &rect in &rects {
+        let row_len = rect.width as usize * bpp;
+        let mut expected = Vec::with_capacity(row_len * rect.height as usize);
+        for row in rect.y..rect.y + rect.height {
+            let start = (row as usize * width as usize + rect.x as usize) * bpp;
+            expected.extend_from_slice(&image[start..][..row_len]);
+        }
+
+        let reported = RefCell::new(Vec::new());
+        let mut buf = vec![0; expected.len()];
+        decoder
+            .read_rect_with_progress(rect, &mut buf, |progress| {
+                reported.borrow_mut().push(progress)
+            })
+            .expect("Failed to read rectangle");
+        assert!(
+            buf == expected,
+            "read_rect of {:?} differs from read_image",
+            rect
+        );
+        check_progress(&reported.into_inner());
+    }
+
+    let outside = [
+        Rect::new(width, 0, 1, height.min(1)),
+        Rect::new(0, height, width.min(1), 1),
+        Rect::new(half_width, half_height, width, height),
+        Rect::new(u32::MAX, 0, 2, 1),
+    ];
+    for &rect in &outside {
+        let len = usize::try_from(rect.area())
+            .unwrap_or(usize::MAX)
+            .saturating_mul(bpp)
+            .min(image.len());
+        let mut buf = vec![0; len];
+        let result = decoder.read_rect_with_progress(rect, &mut buf, |_| {});
+        assert!(
+            matches!(
+                result,
+                Err(ImageError::Parameter(ref err))
+                    if matches!(err.kind(), ParameterErrorKind::RectOutOfBounds { .. })
+            ),
+            "read_rect of {:?} outside of the image was not rejected",
+            rect
+        );
+    }
+}
+
+/// Returns the largest difference between corresponding samples of two images.
+///
+/// Both buffers hold the same color type, in the layout produced by `ImageDecoder::read_image`.
+/// The difference is measured in units of the sample type, i.e. it is at most 255 for 8-bit and
+/// 65535 for 16-bit color types. Floating point samples are scaled so that `1.0` corresponds to
+/// 65535, a difference that exceeds the range of `u16` saturates. So does a NaN sample in either
+/// image.
+///
+/// # Panics
+///
+/// This function panics if the buffers differ in length or are not a whole number of pixels.
+pub fn max_channel_delta(actual: &[u8], expected: &[u8], color: ColorType) -> u16 {
+    sample_pairs(actual, expected, color)
+        .map(|(a, b)| match (a - b).abs() {
+            diff if diff.is_nan() => f64::INFINITY,
+            diff => diff,
+        })
+        .fold(0.0, f64::max)
+        .ceil()
+        .min(f64::from(u16::MAX)) as u16
+}
+
+/// Computes the peak signal-to-noise ratio between two images, in decibels.
+///
+/// The peak is the largest value of the sample type, or `1.0` for floating point samples.
+/// Identical images have an infinite ratio.
+///
+/// # Panics
+///
+/// This function panics if the buffers differ in length or are not a whole number of pixels.
+pub fn psnr(actual: &[u8], expected: &[u8], color: ColorType) -> f64 {
+    let (sum, count) =
+        sample_pairs(actual, expected, color).fold((0.0, 0u64), |(sum, count), (a, b)| {
+            let diff = a - b;
+            (sum + diff * diff, count + 1)
+        });
+
+    if sum == 0.0 {
+        return f64::INFINITY;
+    }
+
+    let peak = if sample_bytes(color) == 1 {
+        f64::from(u8::MAX)
+    } else {
+        f64::from(u16::MAX)
+    };
+    let mse = sum / count as f64;
+    10.0 * (peak * peak / mse).log10()
+}
+
+/// Check that two images differ by at most `max_delta` in every sample.
+///
+/// The tolerance is in the units of `max_channel_delta`. NaN samples never match.
+///
+/// # Panics
+///
+/// This function panics, with the position of the first offending sample, if the images differ
+/// by more than the tolerance or if the buffers differ in length.
+pub fn assert_images_close(actual: &[u8], expected: &[u8], color: ColorType, max_delta: u16) {
+    let channels = usize::from(color.channel_count());
+    let offending = sample_pairs(actual, expected, color)
+        .enumerate()
+        .find(|&(_, (a, b))| {
+            let diff = (a - b).abs();
+            diff.is_nan() || diff > f64::from(max_delta)
+        });
+
+    if let Some((index, (a, b))) = offending {
+        panic!(
+            "Sample {} of pixel {} is {} but expected {}, exceeding the tolerance of {}",
+            index % channels,
+            index / channels,
+            a,
+            b,
+            max_delta,
+        );
+    }
+}
+
+/// The corresponding samples of two images of the same length.
+fn sample_pairs<'b>(
+    actual: &'b [u8],
+    expected: &'b [u8],
+    color: ColorType,
+) -> impl Iterator<Item = (f64, f64)> + 'b {
+    assert_eq!(
+        actual.len(),
+        expected.len(),
+        "Buffers of {} and {} bytes can not be compared",
+        actual.len(),
+        expected.len(),
+    );
+    samples(actual, color).zip(samples(expected, color))
+}
+
+fn sample_bytes(color: ColorType) -> usize {
+    usize::from(color.bytes_per_pixel() / color.channel_count())
+}
+
+/// The samples of an image, floating point samples scaled to the range of 16-bit samples.
+fn samples(buf: &[u8], color: ColorType) -> impl Iterator<Item = f64> + '_ {
+    let bpp = usize::from(color.bytes_per_pixel());
+    assert_eq!(buf.len() % bpp, 0, "Buffer is not a whole number of pixels");
+
+    let sample_bytes = sample_bytes(color);
+    buf.chunks_exact(sample_bytes)
+        .map(move |sample| match sample_bytes {
+            1 => f64::from(sample[0]),
+            2 => f64::from(u16::from_ne_bytes([sample[0], sample[1]])),
+            _ => {
+                let sample = f32::from_ne_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                f64::from(sample) * f64::from(u16::MAX)
+            }
+        })
+}
+
+fn check_progress(reported: &[Progress]) {
+    let mut previous = 0;
+    for progress in reported {
+        assert!(
+            progress.current >= previous,
+            "Progress decreased from {} to {}",
+            previous,
+            progress.current,
+        );
+        assert!(
+            progress.current <= progress.total,
+            "Progress {} exceeds the total {}",
+            progress.current,
+            progress.total,
+        );
+        previous = progress.current;
+    }
+
+    if let Some(last) = reported.last() {
+        assert_eq!(
+            last.current, last.total,
+            "The final progress does not reach the total"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_rect_bounds, ImageResult};
+    use std::io::Cursor;
+
+    /// Decodes a gradient from memory.
+    struct GradientDecoder {
+        width: u32,
+        height: u32,
+    }
+
+    impl GradientDecoder {
+        fn data(&self) -> Vec<u8> {
+            (0..self.width * self.height * 3).map(|i| i as u8).collect()
+        }
+    }
+
+    impl<'a> ImageDecoder<'a> for GradientDecoder {
+        type Reader = Cursor<Vec<u8>>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        fn color_type(&self) -> ColorType {
+            ColorType::Rgb8
+        }
+
+        fn into_reader(self) -> ImageResult<Self::Reader> {
+            Ok(Cursor::new(self.data()))
+        }
+    }
+
+    impl<'a> ImageDecoderExt<'a> for GradientDecoder {
+        fn read_rect_with_progress<F: Fn(Progress)>(
+            &mut self,
+            rect: Rect,
+            buf: &mut [u8],
+            progress_callback: F,
+        ) -> ImageResult<()> {
+            check_rect_bounds(self.dimensions(), rect)?;
+            let data = self.data();
+            let row_len = rect.width as usize * 3;
+            for row in 0..rect.height as usize {
+                let start = ((rect.y as usize + row) * self.width as usize + rect.x as usize) * 3;
+                buf[row * row_len..][..row_len].copy_from_slice(&data[start..][..row_len]);
+            }
+            progress_callback(Progress {
+                current: buf.len() as u64,
+                total: buf.len() as u64,
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn compares_16bit_samples() {
+        let expected: Vec<u8> = [1000u16, 2000, 3000]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        let actual: Vec<u8> = [1000u16, 2010, 2995]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+
+        assert_eq!(max_channel_delta(&actual, &expected, ColorType::Rgb16), 10);
+        assert_eq!(psnr(&expected, &expected, ColorType::Rgb16), f64::INFINITY);
+        assert!(psnr(&actual, &expected, ColorType::Rgb16) > 60.0);
+        assert_images_close(&actual, &expected, ColorType::Rgb16, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sample 1 of pixel 0")]
+    fn reports_offending_sample() {
+        assert_images_close(&[0, 9, 0], &[0, 0, 0], ColorType::Rgb8, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sample 2 of pixel 0 is NaN")]
+    fn nan_samples_do_not_match() {
+        let expected: Vec<u8> = [0.5f32, 0.25, 1.0]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        let actual: Vec<u8> = [0.5f32, 0.25, f32::NAN]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+
+        let delta = max_channel_delta(&actual, &expected, ColorType::Rgb32F);
+        assert_eq!(delta, u16::MAX);
+        assert_images_close(&actual, &expected, ColorType::Rgb32F, u16::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be compared")]
+    fn rejects_different_lengths() {
+        assert_images_close(&[1], &[1, 200], ColorType::L8, 0);
+    }
+
+    #[test]
+    fn gradient_upholds_contract() {
+        check_decoder_ext_contract(|| GradientDecoder {
+            width: 7,
+            height: 5,
+        });
+    }
+}
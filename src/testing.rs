@@ -0,0 +1,109 @@
+//! Shared fixtures for the unit tests of this crate.
+
+use std::io::{Cursor, Read};
+
+use crate::{check_rect_bounds, BoxedDecoder, BufReadSeek, DecoderFactory, ImageDecoderExt};
+use crate::{Cicp, ImageFormat, Progress, Rect};
+use crate::{ColorType, DecodingError, ImageDecoder, ImageError, ImageResult, Limits, PixelBuffer};
+
+/// Decodes an image held in memory.
+pub(crate) struct MemoryDecoder {
+    pub(crate) buffer: PixelBuffer,
+    pub(crate) limits: Limits,
+    pub(crate) cicp: Option<Cicp>,
+}
+
+impl MemoryDecoder {
+    pub(crate) fn new(width: u32, height: u32, color: ColorType, data: Vec<u8>) -> Self {
+        MemoryDecoder {
+            buffer: PixelBuffer::from_vec(width, height, color, data).unwrap(),
+            limits: Limits::no_limits(),
+            cicp: None,
+        }
+    }
+}
+
+impl<'a> ImageDecoder<'a> for MemoryDecoder {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.buffer.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.buffer.color_type()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        u64::from(self.buffer.width()) * u64::from(self.buffer.color_type().bytes_per_pixel())
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.cicp
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits.clone()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(Cursor::new(self.buffer.into_vec()))
+    }
+}
+
+impl<'a> ImageDecoderExt<'a> for MemoryDecoder {
+    fn read_rect_with_progress<F: Fn(Progress)>(
+        &mut self,
+        rect: Rect,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        check_rect_bounds(self.dimensions(), rect)?;
+        let bpp = usize::from(self.buffer.color_type().bytes_per_pixel());
+        let (width, row_len) = (self.buffer.width() as usize, rect.width as usize * bpp);
+        for (row, target) in buf.chunks_exact_mut(row_len.max(1)).enumerate() {
+            let start = ((rect.y as usize + row) * width + rect.x as usize) * bpp;
+            target.copy_from_slice(&self.buffer.as_bytes()[start..][..row_len]);
+        }
+        progress_callback(Progress {
+            current: buf.len() as u64,
+            total: buf.len() as u64,
+        });
+        Ok(())
+    }
+}
+
+/// Decodes files of a magic number, a byte each of width and height, and 8-bit luma samples.
+pub(crate) struct FakeFactory(pub(crate) ImageFormat, pub(crate) &'static [u8]);
+
+impl DecoderFactory for FakeFactory {
+    fn format(&self) -> ImageFormat {
+        self.0
+    }
+
+    fn create_decoder<'a>(
+        &self,
+        mut reader: Box<dyn BufReadSeek + 'a>,
+    ) -> ImageResult<BoxedDecoder<'a>> {
+        let mut header = vec![0; self.1.len() + 2];
+        reader.read_exact(&mut header)?;
+        if !header.starts_with(self.1) {
+            return Err(ImageError::Decoding(DecodingError::from_format_hint(
+                self.0.into(),
+            )));
+        }
+
+        let (width, height) = (header[self.1.len()], header[self.1.len() + 1]);
+        let mut data = vec![0; usize::from(width) * usize::from(height)];
+        reader.read_exact(&mut data)?;
+        let decoder = MemoryDecoder::new(width.into(), height.into(), ColorType::L8, data);
+        Ok(BoxedDecoder::new(decoder))
+    }
+}
+
+/// Decode the complete image into a vector.
+pub(crate) fn decode<'a>(decoder: impl ImageDecoder<'a>) -> Vec<u8> {
+    let mut buf = vec![0; decoder.total_bytes() as usize];
+    decoder.read_image(&mut buf).unwrap();
+    buf
+}
@@ -0,0 +1,491 @@
+use std::io::{self, Read};
+
+use crate::{
+    AlphaMode, Cicp, ColorType, DecodeFidelity, ExtendedColorType, HdrMetadata, ImageDecoder,
+    ImageResult, Limits, RowOrder,
+};
+
+/// The filter used to reduce the size of an image.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DownscaleFilter {
+    /// Pick the pixel at the center of each covered area.
+    Nearest,
+    /// Average all pixels of each covered area.
+    Box,
+}
+
+/// A decoder adapter producing a reduced size version of an image.
+///
+/// The dimensions are chosen to fit into a bounding box while preserving the aspect ratio. Images
+/// which already fit are not enlarged. The downscaling happens while reading, keeping only a
+/// single row of the inner decoder and of the output in memory.
+pub struct ThumbnailDecoder<D> {
+    inner: D,
+    width: u32,
+    height: u32,
+    filter: DownscaleFilter,
+}
+
+/// The reader of a `ThumbnailDecoder`.
+pub struct ThumbnailReader<R> {
+    inner: R,
+    downscaler: Downscaler,
+    row: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+}
+
+/// Reduces the size of an image that is provided row by row.
+pub(crate) struct Downscaler {
+    color: ColorType,
+    filter: DownscaleFilter,
+    /// The first source column of each output column, followed by the source width.
+    columns: Vec<u32>,
+    src_height: u32,
+    dst_height: u32,
+    /// The source row that will be pushed next.
+    src_row: u32,
+    /// The output row that is currently being accumulated.
+    dst_row: u32,
+    sums: Vec<f64>,
+}
+
+impl<'a, D: ImageDecoder<'a>> ThumbnailDecoder<D> {
+    /// Create an adapter that fits the image of the decoder into `max_width` by `max_height`.
+    ///
+    /// Each dimension of a non-empty image is at least one pixel, even for a zero bound. The
+    /// image is filtered with `DownscaleFilter::Box` unless configured otherwise. The decoder is
+    /// hinted to decode at the reduced scale with `ImageDecoder::set_scale_hint`, only the
+    /// remaining reduction is done by the adapter.
+    pub fn new(mut decoder: D, max_width: u32, max_height: u32) -> Self {
+        let (src_width, src_height) = decoder.dimensions();
+        let (width, height) = fit_dimensions((src_width, src_height), (max_width, max_height));
+        if (width, height) != (src_width, src_height) {
+            // The dimension which is reduced the least limits the scale.
+            let hint = if u64::from(width) * u64::from(src_height)
+                >= u64::from(height) * u64::from(src_width)
+            {
+                (width, src_width)
+            } else {
+                (height, src_height)
+            };
+            decoder.set_scale_hint(hint.0, hint.1);
+        }
+
+        let (scaled_width, scaled_height) = decoder.dimensions();
+        let (width, height) = (width.min(scaled_width), height.min(scaled_height));
+        ThumbnailDecoder {
+            inner: decoder,
+            width,
+            height,
+            filter: DownscaleFilter::Box,
+        }
+    }
+
+    /// Choose the filter used for the reduction.
+    pub fn with_filter(mut self, filter: DownscaleFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Returns the adapted decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<'a, D: ImageDecoder<'a>> ImageDecoder<'a> for ThumbnailDecoder<D> {
+    type Reader = ThumbnailReader<D::Reader>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.inner.color_type()
+    }
+
+    fn set_output_color(&mut self, color: ColorType) -> ImageResult<()> {
+        self.inner.set_output_color(color)
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.inner.original_color_type()
+    }
+
+    fn decode_fidelity(&self) -> DecodeFidelity {
+        self.inner.decode_fidelity()
+    }
+
+    fn hdr_metadata(&self) -> Option<HdrMetadata> {
+        self.inner.hdr_metadata()
+    }
+
+    fn cicp(&self) -> Option<Cicp> {
+        self.inner.cicp()
+    }
+
+    fn icc_profile(&self) -> Option<Vec<u8>> {
+        self.inner.icc_profile()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.inner.alpha_mode()
+    }
+
+    fn row_order(&self) -> RowOrder {
+        self.inner.row_order()
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        self.inner.set_limits(limits)
+    }
+
+    fn limits(&self) -> Limits {
+        self.inner.limits()
+    }
+
+    fn scanline_bytes(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.color_type().bytes_per_pixel())
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let color = self.inner.color_type();
+        let (src_width, src_height) = self.inner.dimensions();
+        let downscaler = Downscaler::new(
+            color,
+            self.filter,
+            (src_width, src_height),
+            (self.width, self.height),
+        );
+        let bpp = usize::from(color.bytes_per_pixel());
+
+        Ok(ThumbnailReader {
+            inner: self.inner.into_reader()?,
+            downscaler,
+            row: vec![0; src_width as usize * bpp],
+            output: vec![0; self.width as usize * bpp],
+            position: self.width as usize * bpp,
+        })
+    }
+}
+
+impl<R: Read> Read for ThumbnailReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.downscaler.is_done() {
+                return Ok(0);
+            }
+
+            self.inner.read_exact(&mut self.row)?;
+            if self.downscaler.push_row(&self.row, &mut self.output) {
+                self.position = 0;
+            }
+        }
+
+        let available = &self.output[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl Downscaler {
+    /// Create a downscaler, the destination must not be larger than the source.
+    pub(crate) fn new(
+        color: ColorType,
+        filter: DownscaleFilter,
+        (src_width, src_height): (u32, u32),
+        (dst_width, dst_height): (u32, u32),
+    ) -> Self {
+        debug_assert!(dst_width <= src_width && dst_height <= src_height);
+        let columns = (0..=dst_width)
+            .map(|x| span_start(x, src_width, dst_width))
+            .collect();
+
+        Downscaler {
+            color,
+            filter,
+            columns,
+            src_height,
+            dst_height,
+            src_row: 0,
+            dst_row: 0,
+            sums: vec![0.0; dst_width as usize * usize::from(color.channel_count())],
+        }
+    }
+
+    /// Returns whether all output rows have been produced.
+    pub(crate) fn is_done(&self) -> bool {
+        self.dst_row == self.dst_height
+    }
+
+    /// Add the next source row, returns `true` if `output` was filled with a finished row.
+    pub(crate) fn push_row(&mut self, row: &[u8], output: &mut [u8]) -> bool {
+        debug_assert!(!self.is_done());
+        let start = span_start(self.dst_row, self.src_height, self.dst_height);
+        let end = span_start(self.dst_row + 1, self.src_height, self.dst_height);
+        let y = self.src_row;
+        self.src_row += 1;
+
+        match self.filter {
+            DownscaleFilter::Nearest => {
+                if y == start + (end - start) / 2 {
+                    self.pick_nearest(row, output);
+                }
+            }
+            DownscaleFilter::Box => self.accumulate(row),
+        }
+
+        if self.src_row < end {
+            return false;
+        }
+
+        if let DownscaleFilter::Box = self.filter {
+            self.average(end - start, output);
+        }
+        self.dst_row += 1;
+        true
+    }
+
+    fn pick_nearest(&self, row: &[u8], output: &mut [u8]) {
+        let bpp = usize::from(self.color.bytes_per_pixel());
+        for (pixel, span) in output.chunks_exact_mut(bpp).zip(self.columns.windows(2)) {
+            let x = (span[0] + (span[1] - span[0]) / 2) as usize;
+            pixel.copy_from_slice(&row[x * bpp..][..bpp]);
+        }
+    }
+
+    fn accumulate(&mut self, row: &[u8]) {
+        let bpp = usize::from(self.color.bytes_per_pixel());
+        let channels = usize::from(self.color.channel_count());
+        let sample_bytes = bpp / channels;
+
+        for (sums, span) in self
+            .sums
+            .chunks_exact_mut(channels)
+            .zip(self.columns.windows(2))
+        {
+            let pixels = &row[span[0] as usize * bpp..span[1] as usize * bpp];
+            for pixel in pixels.chunks_exact(bpp) {
+                for (sum, sample) in sums.iter_mut().zip(pixel.chunks_exact(sample_bytes)) {
+                    *sum += match sample_bytes {
+                        1 => f64::from(sample[0]),
+                        2 => f64::from(u16::from_ne_bytes([sample[0], sample[1]])),
+                        _ => f64::from(f32::from_ne_bytes([
+                            sample[0], sample[1], sample[2], sample[3],
+                        ])),
+                    };
+                }
+            }
+        }
+    }
+
+    fn average(&mut self, rows: u32, output: &mut [u8]) {
+        let channels = usize::from(self.color.channel_count());
+        let sample_bytes = usize::from(self.color.bytes_per_pixel()) / channels;
+        let mut samples = output.chunks_exact_mut(sample_bytes);
+
+        for (sums, span) in self
+            .sums
+            .chunks_exact_mut(channels)
+            .zip(self.columns.windows(2))
+        {
+            let count = f64::from(rows) * f64::from(span[1] - span[0]);
+            for (sum, sample) in sums.iter_mut().zip(&mut samples) {
+                let mean = *sum / count;
+                match sample_bytes {
+                    1 => sample[0] = mean.round() as u8,
+                    2 => sample.copy_from_slice(&(mean.round() as u16).to_ne_bytes()),
+                    _ => sample.copy_from_slice(&(mean as f32).to_ne_bytes()),
+                }
+                *sum = 0.0;
+            }
+        }
+    }
+}
+
+/// The first source index covered by the destination index `i`.
+///
+/// An empty destination has no spans, its only boundary is at the start.
+fn span_start(i: u32, src_len: u32, dst_len: u32) -> u32 {
+    if dst_len == 0 {
+        return 0;
+    }
+    (u64::from(i) * u64::from(src_len) / u64::from(dst_len)) as u32
+}
+
+/// Fit dimensions into a bounding box while preserving the aspect ratio, without enlarging.
+pub(crate) fn fit_dimensions(
+    (width, height): (u32, u32),
+    (max_width, max_height): (u32, u32),
+) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width, height);
+    }
+    if width == 0 || height == 0 {
+        // Without an aspect ratio to preserve, both dimensions are clamped to the box.
+        return (width.min(max_width), height.min(max_height));
+    }
+
+    let (max_width, max_height) = (u64::from(max_width.max(1)), u64::from(max_height.max(1)));
+    let (w, h) = (u64::from(width), u64::from(height));
+    let (fit_width, fit_height) = if w * max_height <= h * max_width {
+        // The height is the limiting dimension.
+        ((w * max_height + h / 2) / h, max_height)
+    } else {
+        (max_width, (h * max_width + w / 2) / w)
+    };
+
+    (
+        fit_width.max(1).min(w) as u32,
+        fit_height.max(1).min(h) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decode, MemoryDecoder};
+
+    #[test]
+    fn fits_bounding_box() {
+        assert_eq!(fit_dimensions((400, 200), (100, 100)), (100, 50));
+        assert_eq!(fit_dimensions((200, 400), (100, 100)), (50, 100));
+        assert_eq!(fit_dimensions((50, 20), (100, 100)), (50, 20));
+        assert_eq!(fit_dimensions((1000, 1), (10, 10)), (10, 1));
+        assert_eq!(fit_dimensions((10, 10), (0, 0)), (1, 1));
+        assert_eq!(fit_dimensions((0, 5), (2, 2)), (0, 2));
+    }
+
+    #[test]
+    fn box_filter_averages() {
+        #[rustfmt::skip]
+        let data = vec![
+            0, 2, 10, 10,
+            4, 6, 20, 21,
+            1, 1, 1, 1,
+            1, 1, 1, 1,
+        ];
+        let decoder = MemoryDecoder::new(4, 4, ColorType::L8, data);
+        let thumbnail = ThumbnailDecoder::new(decoder, 2, 2);
+        assert_eq!(thumbnail.dimensions(), (2, 2));
+        assert_eq!(decode(thumbnail), [3, 15, 1, 1]);
+    }
+
+    #[test]
+    fn passes_empty_images() {
+        let decoder = MemoryDecoder::new(0, 5, ColorType::Rgb8, Vec::new());
+        let thumbnail = ThumbnailDecoder::new(decoder, 2, 2);
+        assert_eq!(thumbnail.dimensions(), (0, 2));
+        assert_eq!(decode(thumbnail), []);
+
+        let decoder = MemoryDecoder::new(5, 0, ColorType::L8, Vec::new());
+        let thumbnail = ThumbnailDecoder::new(decoder, 2, 2).with_filter(DownscaleFilter::Nearest);
+        assert_eq!(thumbnail.dimensions(), (2, 0));
+        assert_eq!(decode(thumbnail), []);
+    }
+
+    /// Decodes at half the size when hinted to, picking the top left pixel of each block.
+    struct HalvingDecoder {
+        inner: MemoryDecoder,
+        halved: bool,
+    }
+
+    impl<'a> ImageDecoder<'a> for HalvingDecoder {
+        type Reader = io::Cursor<Vec<u8>>;
+
+        fn dimensions(&self) -> (u32, u32) {
+            let (width, height) = self.inner.dimensions();
+            if self.halved {
+                (width.div_ceil(2), height.div_ceil(2))
+            } else {
+                (width, height)
+            }
+        }
+
+        fn color_type(&self) -> ColorType {
+            self.inner.color_type()
+        }
+
+        fn set_scale_hint(&mut self, numerator: u32, denominator: u32) -> (u32, u32) {
+            self.halved = numerator != 0 && u64::from(numerator) * 2 <= u64::from(denominator);
+            if self.halved {
+                (1, 2)
+            } else {
+                (1, 1)
+            }
+        }
+
+        fn into_reader(self) -> ImageResult<Self::Reader> {
+            let (width, height) = self.dimensions();
+            let src_width = self.inner.dimensions().0 as usize;
+            let step = if self.halved { 2 } else { 1 };
+            let data = self.inner.buffer.into_vec();
+            let pixels = (0..height as usize)
+                .flat_map(|y| (0..width as usize).map(move |x| (y * step, x * step)))
+                .map(|(y, x)| data[y * src_width + x])
+                .collect();
+            Ok(io::Cursor::new(pixels))
+        }
+    }
+
+    #[test]
+    fn hints_native_scale() {
+        let data: Vec<u8> = (0..64).collect();
+        let decoder = HalvingDecoder {
+            inner: MemoryDecoder::new(8, 8, ColorType::L8, data),
+            halved: false,
+        };
+        let thumbnail = ThumbnailDecoder::new(decoder, 2, 2);
+        assert_eq!(thumbnail.dimensions(), (2, 2));
+        assert_eq!(decode(thumbnail), [9, 13, 41, 45]);
+
+        let decoder = HalvingDecoder {
+            inner: MemoryDecoder::new(8, 8, ColorType::L8, (0..64).collect()),
+            halved: false,
+        };
+        let thumbnail = ThumbnailDecoder::new(decoder, 6, 6);
+        assert_eq!(thumbnail.into_inner().dimensions(), (8, 8));
+    }
+
+    #[test]
+    fn box_filter_16bit() {
+        let data: Vec<u8> = [1000u16, 3000, 60000, 65535]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        let decoder = MemoryDecoder::new(1, 2, ColorType::La16, data);
+        let thumbnail = ThumbnailDecoder::new(decoder, 1, 1);
+        let expected: Vec<u8> = [30500u16, 34268]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        assert_eq!(decode(thumbnail), expected);
+    }
+
+    #[test]
+    fn box_filter_float() {
+        let data: Vec<u8> = [0.25f32, 0.5, 1.0, 2.0, 0.0, 0.0]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        let decoder = MemoryDecoder::new(2, 1, ColorType::Rgb32F, data);
+        let thumbnail = ThumbnailDecoder::new(decoder, 1, 1);
+        let expected: Vec<u8> = [1.125f32, 0.25, 0.5]
+            .iter()
+            .flat_map(|s| s.to_ne_bytes())
+            .collect();
+        assert_eq!(decode(thumbnail), expected);
+    }
+
+    #[test]
+    fn nearest_picks_centers() {
+        let data: Vec<u8> = (0..9).collect();
+        let decoder = MemoryDecoder::new(3, 3, ColorType::L8, data);
+        let thumbnail = ThumbnailDecoder::new(decoder, 1, 1).with_filter(DownscaleFilter::Nearest);
+        assert_eq!(decode(thumbnail), [4]);
+    }
+}
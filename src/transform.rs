@@ -0,0 +1,168 @@
+//! Flips and rotations of raw pixel buffers.
+//!
+//! All functions operate on the byte layout produced by `ImageDecoder::read_image`: rows from top
+//! to bottom without padding, each pixel taking `color.bytes_per_pixel()` bytes. Rotations are
+//! clockwise. The dimensions passed are those of the input, a rotation by 90 or 270 degrees
+//! produces an image of `height` by `width` pixels.
+//!
+//! # Panics
+//!
+//! Every function panics if the length of the buffer does not match the dimensions and color type.
+
+use crate::ColorType;
+
+/// Mirror the image along its vertical axis, swapping left and right.
+pub fn flip_horizontal_in_place(buf: &mut [u8], width: u32, height: u32, color: ColorType) {
+    let bpp = check_len(buf, width, height, color);
+    if bpp == 0 || width == 0 {
+        return;
+    }
+
+    for row in buf.chunks_exact_mut(width as usize * bpp) {
+        let (mut left, mut right) = (0, width as usize - 1);
+        while left < right {
+            swap_pixels(row, left, right, bpp);
+            left += 1;
+            right -= 1;
+        }
+    }
+}
+
+/// Mirror the image along its horizontal axis, swapping top and bottom.
+///
+/// This converts between top-down and bottom-up row order.
+pub fn flip_vertical_in_place(buf: &mut [u8], width: u32, height: u32, color: ColorType) {
+    let row_len = check_len(buf, width, height, color) * width as usize;
+    let height = height as usize;
+
+    for top in 0..height / 2 {
+        let bottom = height - 1 - top;
+        let (upper, lower) = buf.split_at_mut(bottom * row_len);
+        upper[top * row_len..][..row_len].swap_with_slice(&mut lower[..row_len]);
+    }
+}
+
+/// Rotate the image by 180 degrees.
+pub fn rotate180_in_place(buf: &mut [u8], width: u32, height: u32, color: ColorType) {
+    let bpp = check_len(buf, width, height, color);
+    let pixels = width as usize * height as usize;
+    if bpp == 0 || pixels == 0 {
+        return;
+    }
+
+    let (mut first, mut last) = (0, pixels - 1);
+    while first < last {
+        swap_pixels(buf, first, last, bpp);
+        first += 1;
+        last -= 1;
+    }
+}
+
+/// Rotate the image by 90 degrees.
+pub fn rotate90(buf: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+    rotate_quarter(buf, width, height, color, |x, y| (height - 1 - y, x))
+}
+
+/// Rotate the image by 180 degrees.
+pub fn rotate180(buf: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+    let mut rotated = buf.to_vec();
+    rotate180_in_place(&mut rotated, width, height, color);
+    rotated
+}
+
+/// Rotate the image by 270 degrees.
+pub fn rotate270(buf: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+    rotate_quarter(buf, width, height, color, |x, y| (y, width - 1 - x))
+}
+
+/// Rotate by a quarter turn where `target` maps input to output coordinates.
+fn rotate_quarter(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: ColorType,
+    target: impl Fn(u32, u32) -> (u32, u32),
+) -> Vec<u8> {
+    let bpp = check_len(buf, width, height, color);
+    let mut rotated = vec![0; buf.len()];
+
+    // The output is `height` pixels wide.
+    for y in 0..height {
+        for x in 0..width {
+            let (target_x, target_y) = target(x, y);
+            let source = (y as usize * width as usize + x as usize) * bpp;
+            let dest = (target_y as usize * height as usize + target_x as usize) * bpp;
+            rotated[dest..][..bpp].copy_from_slice(&buf[source..][..bpp]);
+        }
+    }
+
+    rotated
+}
+
+fn swap_pixels(buf: &mut [u8], a: usize, b: usize, bpp: usize) {
+    debug_assert!(a < b);
+    let (head, tail) = buf.split_at_mut(b * bpp);
+    head[a * bpp..][..bpp].swap_with_slice(&mut tail[..bpp]);
+}
+
+fn check_len(buf: &[u8], width: u32, height: u32, color: ColorType) -> usize {
+    let bpp = usize::from(color.bytes_per_pixel());
+    let expected = u64::from(width) * u64::from(height) * bpp as u64;
+    assert_eq!(
+        buf.len() as u64,
+        expected,
+        "Buffer length does not match the dimensions"
+    );
+    bpp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3x2 image with 2 bytes per pixel:
+    // 1 2 3
+    // 4 5 6
+    fn image() -> Vec<u8> {
+        (1..=6).flat_map(|p| vec![p, p * 10]).collect()
+    }
+
+    fn pixels(buf: &[u8]) -> Vec<u8> {
+        buf.chunks(2).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn flips() {
+        let mut buf = image();
+        flip_horizontal_in_place(&mut buf, 3, 2, ColorType::La8);
+        assert_eq!(pixels(&buf), [3, 2, 1, 6, 5, 4]);
+        assert_eq!(buf[1], 30);
+
+        let mut buf = image();
+        flip_vertical_in_place(&mut buf, 3, 2, ColorType::La8);
+        assert_eq!(pixels(&buf), [4, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn rotations() {
+        let buf = image();
+        assert_eq!(
+            pixels(&rotate90(&buf, 3, 2, ColorType::La8)),
+            [4, 1, 5, 2, 6, 3]
+        );
+        assert_eq!(
+            pixels(&rotate180(&buf, 3, 2, ColorType::La8)),
+            [6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            pixels(&rotate270(&buf, 3, 2, ColorType::La8)),
+            [3, 6, 2, 5, 1, 4]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_wrong_length() {
+        flip_vertical_in_place(&mut [0; 5], 3, 2, ColorType::L8);
+    }
+}
@@ -0,0 +1,109 @@
+use std::convert::TryFrom;
+
+use crate::{ColorType, Dimensions, PixelBuffer};
+
+/// A read-only source of pixel data with known dimensions and color type.
+///
+/// This is the raw byte equivalent of `image::GenericImageView`. Implementations need not keep
+/// any pixel data in memory, they may as well produce rows on request, e.g. for tiles, sub-views
+/// into a larger image, or procedurally generated content. Consumers such as streaming encoders
+/// should therefore request data row by row.
+///
+/// All byte data uses the same layout as `ImageDecoder::read_image`, i.e. samples wider than a
+/// byte are in native endian.
+pub trait ImageView {
+    /// Returns a tuple containing the width and height of the image.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Returns the color type of the pixel data.
+    fn color_type(&self) -> ColorType;
+
+    /// Returns the width and height of the image, see `dimensions`.
+    fn size(&self) -> Dimensions {
+        self.dimensions().into()
+    }
+
+    /// Copy the row at `y` into the buffer.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `y` is not smaller than the height or if `buf.len()` is not
+    /// `self.row_bytes()`.
+    fn read_row(&self, y: u32, buf: &mut [u8]);
+
+    /// Returns the number of bytes in a single row.
+    fn row_bytes(&self) -> u64 {
+        u64::from(self.dimensions().0) * u64::from(self.color_type().bytes_per_pixel())
+    }
+
+    /// Copy the pixel at `(x, y)` into the buffer.
+    ///
+    /// The provided implementation reads the whole row, sources with random access should
+    /// override it.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the coordinates are out of bounds or if `buf.len()` is not the
+    /// size of a pixel of the color type.
+    fn read_pixel(&self, x: u32, y: u32, buf: &mut [u8]) {
+        let bpp = usize::from(self.color_type().bytes_per_pixel());
+        assert_eq!(buf.len(), bpp);
+        assert!(x < self.dimensions().0);
+
+        let row_len = usize::try_from(self.row_bytes()).expect("Row exceeds the address space");
+        let mut row = vec![0; row_len];
+        self.read_row(y, &mut row);
+
+        let start = x as usize * bpp;
+        buf.copy_from_slice(&row[start..][..bpp]);
+    }
+}
+
+impl<T: ImageView + ?Sized> ImageView for &'_ T {
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        (**self).color_type()
+    }
+
+    fn read_row(&self, y: u32, buf: &mut [u8]) {
+        (**self).read_row(y, buf)
+    }
+
+    fn row_bytes(&self) -> u64 {
+        (**self).row_bytes()
+    }
+
+    fn read_pixel(&self, x: u32, y: u32, buf: &mut [u8]) {
+        (**self).read_pixel(x, y, buf)
+    }
+}
+
+impl ImageView for PixelBuffer {
+    fn dimensions(&self) -> (u32, u32) {
+        PixelBuffer::dimensions(self)
+    }
+
+    fn color_type(&self) -> ColorType {
+        PixelBuffer::color_type(self)
+    }
+
+    fn read_row(&self, y: u32, buf: &mut [u8]) {
+        assert!(y < self.height());
+        let row_len = buf.len();
+        assert_eq!(row_len as u64, self.row_bytes());
+
+        buf.copy_from_slice(&self.as_bytes()[y as usize * row_len..][..row_len]);
+    }
+
+    fn read_pixel(&self, x: u32, y: u32, buf: &mut [u8]) {
+        let bpp = usize::from(self.color_type().bytes_per_pixel());
+        assert_eq!(buf.len(), bpp);
+        assert!(x < self.width() && y < self.height());
+
+        let index = (y as usize * self.width() as usize + x as usize) * bpp;
+        buf.copy_from_slice(&self.as_bytes()[index..][..bpp]);
+    }
+}